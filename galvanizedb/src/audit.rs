@@ -0,0 +1,74 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde_json::json;
+use crate::error::{GalvanizeError, Result};
+use crate::output::{Value, format_unix_time};
+use crate::session::QueryOutcome;
+
+/// A file that every statement run in the shell is appended to, one JSON
+/// object per line, with when it ran, how long it took and what it did.
+/// Clones write to the same file, so background jobs can log too.
+#[derive(Clone)]
+pub struct AuditLog {
+    file: Arc<Mutex<File>>,
+}
+
+// The current UTC time, e.g. `2024-05-01T12:30:00.250Z`.
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let datetime = format_unix_time(now.as_secs() as i64, "DATETIME").replace(' ', "T");
+    format!("{}.{:03}Z", datetime, now.subsec_millis())
+}
+
+fn param_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(v) => json!(v),
+        Value::Real(v) => json!(v),
+        Value::Boolean(v) => json!(v),
+        Value::Decimal(v) | Value::Text(v) => json!(v),
+        Value::Blob(v) => json!(format!("x'{}'", v.iter().map(|b| format!("{:02X}", b)).collect::<String>())),
+    }
+}
+
+impl AuditLog {
+    /// Opens the log at `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> Result<AuditLog> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| GalvanizeError::Io(format!("Error opening audit log '{}': {}", path.display(), e)))?;
+        Ok(AuditLog { file: Arc::new(Mutex::new(file)) })
+    }
+
+    /// Appends a statement run on `database` and its outcome. A log that
+    /// cannot be written is reported, but does not fail the statement.
+    pub fn record(&self, database: &str, sql: &str, params: &[Value], elapsed: Duration, result: std::result::Result<QueryOutcome, &GalvanizeError>) {
+        let mut entry = json!({
+            "time": timestamp(),
+            "database": database,
+            "statement": sql.trim(),
+            "duration_ms": (elapsed.as_secs_f64() * 1000.0 * 1000.0).round() / 1000.0,
+        });
+        if !params.is_empty() {
+            entry["params"] = params.iter().map(param_json).collect();
+        }
+        match result {
+            Ok(QueryOutcome::Rows(count)) => entry["rows"] = json!(count),
+            Ok(QueryOutcome::Changes { rows_affected, .. }) => entry["rows_affected"] = json!(rows_affected),
+            Err(e) => entry["error"] = json!(e.to_string()),
+        }
+
+        // Each entry is written whole, in one call, so that entries from
+        // background jobs do not interleave.
+        let line = format!("{}\n", entry);
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            errln!("Error writing to the audit log: {}", e);
+        }
+    }
+}
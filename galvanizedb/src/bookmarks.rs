@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use crate::config;
+use crate::error::{GalvanizeError, Result};
+
+// Bookmarks live next to the configuration file, as name = "path" pairs.
+fn path() -> Result<PathBuf> {
+    config::config_dir()
+        .map(|dir| dir.join("bookmarks.toml"))
+        .ok_or_else(|| GalvanizeError::Io("No configuration directory for bookmarks: HOME is not set.".to_string()))
+}
+
+/// The database files bookmarked, by name. No file just means none was
+/// added yet.
+pub fn load() -> Result<BTreeMap<String, String>> {
+    let path = path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| GalvanizeError::Io(format!("Error reading '{}': {}", path.display(), e)))?;
+    toml::from_str(&text).map_err(|e| GalvanizeError::Parse(format!("Error in '{}': {}", path.display(), e)))
+}
+
+fn write(bookmarks: &BTreeMap<String, String>) -> Result<()> {
+    let path = path()?;
+    let text = toml::to_string(bookmarks).map_err(|e| GalvanizeError::Execution(e.to_string()))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| GalvanizeError::Io(format!("Error creating '{}': {}", dir.display(), e)))?;
+    }
+    std::fs::write(&path, text).map_err(|e| GalvanizeError::Io(format!("Error writing '{}': {}", path.display(), e)))
+}
+
+/// Bookmarks `database` as `name`, replacing any bookmark of that name.
+/// Names that are numbers would be taken for those of RECENT.
+pub fn add(name: &str, database: &str) -> Result<()> {
+    let valid = name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') && !name.chars().all(|c| c.is_ascii_digit());
+    if name.is_empty() || !valid {
+        return Err(GalvanizeError::Parse(format!(
+            "Invalid bookmark name '{}': use letters, digits, _ and -, and not only digits.",
+            name
+        )));
+    }
+    let mut bookmarks = load()?;
+    bookmarks.insert(name.to_string(), database.to_string());
+    write(&bookmarks)
+}
+
+/// Removes the bookmark `name`, and returns whether there was one.
+pub fn remove(name: &str) -> Result<bool> {
+    let mut bookmarks = load()?;
+    if bookmarks.remove(name).is_none() {
+        return Ok(false);
+    }
+    write(&bookmarks)?;
+    Ok(true)
+}
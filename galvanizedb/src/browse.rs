@@ -0,0 +1,458 @@
+use std::cmp::Ordering;
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, Cell, Clear, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::Frame;
+use crate::error::Result;
+use crate::output::{Value, text_width, value_text};
+use crate::session::{QueryResult, Session};
+use crate::settings::Settings;
+use crate::quote_identifier;
+use crate::shell::parameter_value;
+
+// Widths columns start out with at most, and can be narrowed to.
+const MAX_INITIAL_WIDTH: usize = 40;
+const MIN_WIDTH: u16 = 3;
+// Rows looked at to size the columns.
+const WIDTH_SAMPLE_ROWS: usize = 200;
+/// Rows loaded to browse; the rest of a result is left out.
+pub const MAX_BROWSE_ROWS: usize = 10_000;
+
+const HELP: &str = " q quit  arrows move  s sort  +/- width  Enter inspect  / search  n next ";
+const EDIT_HELP: &str = " q quit  arrows move  s sort  +/- width  Enter inspect  / search  n next  e edit ";
+
+enum Mode {
+    Grid,
+    Inspect,
+    Search(String),
+    // The new text of the selected cell.
+    Edit(String),
+    // An UPDATE waiting to be confirmed, with the new value of the cell.
+    Confirm(String, Value),
+}
+
+// The table that the rows come from, for editing them.
+struct Editing {
+    table: String,
+    // The columns that identify a row: its primary key, or the rowid.
+    keys: Vec<usize>,
+}
+
+/// A full-screen, scrollable grid over the rows of a result, for BROWSE.
+pub struct Browser<'a> {
+    settings: &'a Settings,
+    editing: Option<Editing>,
+    title: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+    // Indexes into `rows`, in the order they are shown.
+    order: Vec<usize>,
+    // The column sorted by, and whether in descending order.
+    sort: Option<(usize, bool)>,
+    widths: Vec<u16>,
+    // The selected cell, by position in `order` and column.
+    row: usize,
+    column: usize,
+    // The first row and column on screen.
+    offset: usize,
+    first_column: usize,
+    // Rows on screen, as of the last draw.
+    page_height: usize,
+    mode: Mode,
+    last_search: Option<String>,
+    message: Option<String>,
+}
+
+// A number to sort a value by, if it is one.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(v) => Some(*v as f64),
+        Value::Real(v) => Some(*v),
+        Value::Boolean(v) => Some(f64::from(u8::from(*v))),
+        Value::Decimal(v) => v.parse().ok(),
+        _ => None,
+    }
+}
+
+// Orders NULLs first, then numbers, text and blobs, as SQLite does.
+fn compare(a: &Value, b: &Value) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Text(_) => 2,
+        Value::Blob(_) => 3,
+        _ => 1,
+    };
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+        (Value::Text(a), Value::Text(b)) => a.cmp(b),
+        (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+        _ => match (number(a), number(b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            _ => rank(a).cmp(&rank(b)),
+        },
+    }
+}
+
+// The text shown for a value in the grid, on a single line.
+fn cell_text(value: &Value, settings: &Settings) -> String {
+    value_text(value, settings).replace(['\n', '\r', '\t'], " ")
+}
+
+/// A value written as an SQL literal.
+pub(crate) fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(v) => v.to_string(),
+        Value::Real(v) => format!("{:?}", v),
+        Value::Decimal(v) => v.clone(),
+        Value::Boolean(v) => if *v { "TRUE" } else { "FALSE" }.to_string(),
+        Value::Text(v) => crate::quote_literal(v),
+        Value::Blob(v) => format!("X'{}'", v.iter().map(|b| format!("{:02X}", b)).collect::<String>()),
+    }
+}
+
+impl<'a> Browser<'a> {
+    pub fn new(title: &str, result: QueryResult, settings: &'a Settings) -> Browser<'a> {
+        let widths = result
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let widest = result.rows.iter().take(WIDTH_SAMPLE_ROWS).map(|row| text_width(&cell_text(&row[i], settings))).max().unwrap_or(0);
+                widest.max(text_width(name)).clamp(MIN_WIDTH as usize, MAX_INITIAL_WIDTH) as u16
+            })
+            .collect();
+        Browser {
+            settings,
+            editing: None,
+            title: title.to_string(),
+            order: (0..result.rows.len()).collect(),
+            columns: result.columns,
+            rows: result.rows,
+            sort: None,
+            widths,
+            row: 0,
+            column: 0,
+            offset: 0,
+            first_column: 0,
+            page_height: 1,
+            mode: Mode::Grid,
+            last_search: None,
+            message: None,
+        }
+    }
+
+    /// Lets the cells be edited, with UPDATEs of `table` that find the row
+    /// by the values of the `keys` columns.
+    pub fn editable(mut self, table: &str, keys: Vec<usize>) -> Self {
+        self.editing = Some(Editing { table: table.to_string(), keys });
+        self
+    }
+
+    /// Takes over the terminal until the user quits. Edits are run on `db`.
+    pub async fn run(&mut self, db: &mut Session) -> Result<()> {
+        let mut terminal = ratatui::try_init()?;
+        let result = self.event_loop(&mut terminal, db).await;
+        ratatui::try_restore()?;
+        result
+    }
+
+    async fn event_loop(&mut self, terminal: &mut DefaultTerminal, db: &mut Session) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            self.message = None;
+            match &mut self.mode {
+                Mode::Inspect => self.mode = Mode::Grid,
+                Mode::Search(text) => match key.code {
+                    KeyCode::Esc => self.mode = Mode::Grid,
+                    KeyCode::Enter => {
+                        let text = std::mem::take(text);
+                        self.mode = Mode::Grid;
+                        if !text.is_empty() {
+                            self.search(&text);
+                            self.last_search = Some(text);
+                        }
+                    },
+                    KeyCode::Backspace => {
+                        text.pop();
+                    },
+                    KeyCode::Char(c) => text.push(c),
+                    _ => {},
+                },
+                Mode::Edit(text) => match key.code {
+                    KeyCode::Esc => self.mode = Mode::Grid,
+                    KeyCode::Enter => {
+                        let text = std::mem::take(text);
+                        self.mode = self.update_statement(&text);
+                    },
+                    KeyCode::Backspace => {
+                        text.pop();
+                    },
+                    KeyCode::Char(c) => text.push(c),
+                    _ => {},
+                },
+                Mode::Confirm(sql, value) => {
+                    if key.code == KeyCode::Char('y') {
+                        let (sql, value) = (std::mem::take(sql), std::mem::replace(value, Value::Null));
+                        self.message = Some(match db.execute_sql(&sql).await {
+                            Ok(result) if result.rows_affected == 0 => "No row was updated; it may have been changed or deleted.".to_string(),
+                            Ok(_) => {
+                                let row = self.order[self.row];
+                                self.rows[row][self.column] = value;
+                                "Row updated.".to_string()
+                            },
+                            Err(e) => e.to_string(),
+                        });
+                    }
+                    self.mode = Mode::Grid;
+                },
+                Mode::Grid => {
+                    let last_row = self.order.len().saturating_sub(1);
+                    let last_column = self.columns.len().saturating_sub(1);
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Up | KeyCode::Char('k') => self.row = self.row.saturating_sub(1),
+                        KeyCode::Down | KeyCode::Char('j') => self.row = (self.row + 1).min(last_row),
+                        KeyCode::Left | KeyCode::Char('h') => self.column = self.column.saturating_sub(1),
+                        KeyCode::Right | KeyCode::Char('l') => self.column = (self.column + 1).min(last_column),
+                        KeyCode::PageUp => self.row = self.row.saturating_sub(self.page_height),
+                        KeyCode::PageDown => self.row = (self.row + self.page_height).min(last_row),
+                        KeyCode::Home | KeyCode::Char('g') => self.row = 0,
+                        KeyCode::End | KeyCode::Char('G') => self.row = last_row,
+                        KeyCode::Char('0') => self.column = 0,
+                        KeyCode::Char('$') => self.column = last_column,
+                        KeyCode::Char('s') => self.sort_by_column(),
+                        KeyCode::Char('+') | KeyCode::Char('>') => self.resize_column(2),
+                        KeyCode::Char('-') | KeyCode::Char('<') => self.resize_column(-2),
+                        KeyCode::Enter if !self.order.is_empty() => self.mode = Mode::Inspect,
+                        KeyCode::Char('/') => self.mode = Mode::Search(String::new()),
+                        KeyCode::Char('e') => self.start_edit(),
+                        KeyCode::Char('n') => match self.last_search.clone() {
+                            Some(text) => self.search(&text),
+                            None => self.message = Some("Nothing to search for yet; press / first.".to_string()),
+                        },
+                        _ => {},
+                    }
+                },
+            }
+        }
+    }
+
+    // Starts editing the selected cell, from its current value.
+    fn start_edit(&mut self) {
+        let Some(&row) = self.order.get(self.row) else { return };
+        self.mode = match (&self.editing, &self.rows[row][self.column]) {
+            (None, _) => {
+                self.message = Some("Only BROWSE table_name; can be edited, of a table with a primary key or rowid.".to_string());
+                return;
+            },
+            (_, Value::Blob(_)) => {
+                self.message = Some("Blobs cannot be edited here.".to_string());
+                return;
+            },
+            (_, Value::Null) => Mode::Edit("NULL".to_string()),
+            // Text that would read as another type is quoted.
+            (_, Value::Text(text)) if !matches!(parameter_value(text), Value::Text(_)) => {
+                Mode::Edit(format!("'{}'", text.replace('\'', "''")))
+            },
+            (_, value) => Mode::Edit(value_text(value, self.settings)),
+        };
+    }
+
+    // The UPDATE that sets the selected cell to the value typed, waiting to
+    // be confirmed.
+    fn update_statement(&self, text: &str) -> Mode {
+        let (Some(editing), Some(&row)) = (&self.editing, self.order.get(self.row)) else { return Mode::Grid };
+        let value = parameter_value(text);
+        let conditions: Vec<String> = editing
+            .keys
+            .iter()
+            .map(|&key| match &self.rows[row][key] {
+                Value::Null => format!("{} IS NULL", quote_identifier(&self.columns[key])),
+                value => format!("{} = {}", quote_identifier(&self.columns[key]), sql_literal(value)),
+            })
+            .collect();
+        let sql = format!(
+            "UPDATE {} SET {} = {} WHERE {};",
+            quote_identifier(&editing.table),
+            quote_identifier(&self.columns[self.column]),
+            sql_literal(&value),
+            conditions.join(" AND ")
+        );
+        Mode::Confirm(sql, value)
+    }
+
+    // Sorts by the selected column: ascending, then descending, then back
+    // to the order of the result. The selected row stays selected.
+    fn sort_by_column(&mut self) {
+        let selected = self.order.get(self.row).copied();
+        self.sort = match self.sort {
+            Some((column, false)) if column == self.column => Some((column, true)),
+            Some((column, true)) if column == self.column => None,
+            _ => Some((self.column, false)),
+        };
+        match self.sort {
+            Some((column, descending)) => {
+                let rows = &self.rows;
+                self.order.sort_by(|&a, &b| {
+                    let ordering = compare(&rows[a][column], &rows[b][column]);
+                    if descending { ordering.reverse() } else { ordering }
+                });
+            },
+            None => self.order.sort_unstable(),
+        }
+        if let Some(selected) = selected {
+            self.row = self.order.iter().position(|&i| i == selected).unwrap_or(0);
+        }
+    }
+
+    fn resize_column(&mut self, change: i32) {
+        if let Some(width) = self.widths.get_mut(self.column) {
+            *width = (i32::from(*width) + change).clamp(i32::from(MIN_WIDTH), i32::from(u16::MAX)) as u16;
+        }
+    }
+
+    // Selects the next cell after the selected one containing `text`,
+    // ignoring case, going on from the top at the end.
+    fn search(&mut self, text: &str) {
+        let text = text.to_lowercase();
+        let width = self.columns.len();
+        let cells = self.order.len() * width;
+        let start = self.row * width + self.column;
+        for step in 1..=cells {
+            let cell = (start + step) % cells;
+            let (row, column) = (cell / width, cell % width);
+            if cell_text(&self.rows[self.order[row]][column], self.settings).to_lowercase().contains(&text) {
+                self.row = row;
+                self.column = column;
+                return;
+            }
+        }
+        self.message = Some(format!("'{}' was not found.", text));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [title_area, grid_area, status_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+        let sort = match self.sort {
+            Some((column, descending)) => format!(", sorted by {} {}", self.columns[column], if descending { "DESC" } else { "ASC" }),
+            None => String::new(),
+        };
+        let position = format!(
+            " {}  row {} of {}, column {} of {}{} ",
+            self.title,
+            if self.order.is_empty() { 0 } else { self.row + 1 },
+            self.order.len(),
+            self.column + 1,
+            self.columns.len(),
+            sort
+        );
+        frame.render_widget(Paragraph::new(position).reversed(), title_area);
+
+        self.draw_grid(frame, grid_area);
+
+        let status = match (&self.mode, &self.message) {
+            (Mode::Search(text), _) => Line::from(format!("/{}", text)),
+            (Mode::Edit(text), _) => Line::from(format!("{} = {}", self.columns[self.column], text)),
+            (Mode::Confirm(..), _) => Line::from(" y runs this statement, any other key cancels it ").bold(),
+            (_, Some(message)) => Line::from(message.as_str()).bold(),
+            _ => Line::from(if self.editing.is_some() { EDIT_HELP } else { HELP }).dim(),
+        };
+        if let Mode::Edit(text) = &self.mode {
+            let x = status_area.x + text_width(&format!("{} = {}", self.columns[self.column], text)) as u16;
+            frame.set_cursor_position((x.min(status_area.right().saturating_sub(1)), status_area.y));
+        }
+        frame.render_widget(Paragraph::new(status), status_area);
+
+        match &self.mode {
+            Mode::Inspect => self.draw_cell(frame, grid_area),
+            Mode::Confirm(sql, _) => self.draw_statement(frame, grid_area, sql),
+            _ => {},
+        }
+    }
+
+    // Shows the UPDATE an edit runs, before it is confirmed.
+    fn draw_statement(&self, frame: &mut Frame, area: Rect, sql: &str) {
+        let height = area.height.min(8);
+        let popup = Rect { x: area.x + area.width / 8, y: area.y + (area.height - height) / 2, width: area.width - area.width / 4, height };
+        let block = Block::bordered().title(" Run this UPDATE? ").title_bottom(" y runs it ");
+        frame.render_widget(Clear, popup);
+        frame.render_widget(Paragraph::new(sql).wrap(Wrap { trim: false }).block(block), popup);
+    }
+
+    fn draw_grid(&mut self, frame: &mut Frame, area: Rect) {
+        // Rows below the header.
+        self.page_height = (area.height as usize).saturating_sub(1).max(1);
+        if self.row < self.offset {
+            self.offset = self.row;
+        } else if self.row >= self.offset + self.page_height {
+            self.offset = self.row + 1 - self.page_height;
+        }
+
+        // The columns that fit, starting from one that keeps the selected
+        // column on screen.
+        self.first_column = self.first_column.min(self.column);
+        let visible = |first: usize| {
+            let mut used = 0;
+            let mut end = first;
+            while end < self.widths.len() && (end == first || used + self.widths[end] as usize <= area.width as usize) {
+                used += self.widths[end] as usize + 1;
+                end += 1;
+            }
+            end
+        };
+        while self.column >= visible(self.first_column) {
+            self.first_column += 1;
+        }
+        let columns = self.first_column..visible(self.first_column);
+
+        let header = Row::new(columns.clone().map(|i| Cell::from(self.columns[i].as_str()))).bold().underlined();
+        let rows = self.order.iter().skip(self.offset).take(self.page_height).map(|&row| {
+            Row::new(columns.clone().map(|i| {
+                let text = cell_text(&self.rows[row][i], self.settings);
+                match &self.rows[row][i] {
+                    Value::Null => Cell::from(text).dim().italic(),
+                    value if number(value).is_some() && !matches!(value, Value::Boolean(_)) => {
+                        Cell::from(Text::from(text).alignment(Alignment::Right))
+                    },
+                    _ => Cell::from(text),
+                }
+            }))
+        });
+        let widths = columns.clone().map(|i| Constraint::Length(self.widths[i]));
+        let table = Table::new(rows, widths)
+            .header(header)
+            .row_highlight_style(Style::new().add_modifier(Modifier::BOLD))
+            .cell_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = TableState::default()
+            .with_selected((!self.order.is_empty()).then_some(self.row - self.offset))
+            .with_selected_column(Some(self.column - self.first_column));
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+
+    // Shows the whole of the selected value in a box over the grid.
+    fn draw_cell(&self, frame: &mut Frame, area: Rect) {
+        let Some(&row) = self.order.get(self.row) else { return };
+        let popup = Rect {
+            x: area.x + area.width / 8,
+            y: area.y + area.height / 8,
+            width: area.width - area.width / 4,
+            height: area.height - area.height / 4,
+        };
+        let block = Block::bordered().title(format!(" {} ", self.columns[self.column])).title_bottom(" any key closes ");
+        let value = match &self.rows[row][self.column] {
+            Value::Text(text) => text.clone(),
+            value => cell_text(value, self.settings),
+        };
+        frame.render_widget(Clear, popup);
+        frame.render_widget(Paragraph::new(value).wrap(Wrap { trim: false }).block(block), popup);
+    }
+}
@@ -0,0 +1,372 @@
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hinter, HistoryHinter};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::history::FileHistory;
+use rustyline::{Cmd, ConditionalEventHandler, Context, Editor, Event, EventContext, EventHandler, Helper, KeyEvent, Movement, RepeatCount};
+use crate::error::Result;
+use crate::script::{dollar_quote, format_sql, is_incomplete};
+use crate::theme::{Theme, paint};
+
+const SQL_KEYWORDS: &[&str] = &[
+    "ABORT", "ADD", "ALL", "ALTER", "ANALYZE", "AND", "AS", "ASC", "ATTACH", "AUTOINCREMENT",
+    "BEGIN", "BETWEEN", "BY", "CASCADE", "CASE", "CAST", "CHECK", "COLLATE", "COLUMN", "COLUMNS",
+    "COMMIT", "CONFLICT", "CONSTRAINT", "CREATE", "CROSS", "DATABASE", "DEFAULT", "DELETE", "DESC",
+    "DETACH", "DISTINCT", "DROP", "ELSE", "END", "ESCAPE", "EXCEPT", "EXISTS", "EXIT", "EXPLAIN",
+    "FOREIGN", "FROM", "FULL", "GLOB", "GROUP", "HAVING", "HELP", "IF", "IGNORE", "IN", "INDEX",
+    "INNER", "INSERT", "INTEGER", "INTERSECT", "INTO", "IS", "JOIN", "KEY", "LEFT", "LIKE", "LIMIT",
+    "NOT", "NULL", "OFFSET", "ON", "OR", "ORDER", "OUTER", "PRAGMA", "PRIMARY", "REAL", "REFERENCES",
+    "REINDEX", "RELEASE", "RENAME", "REPLACE", "RETURNING", "RIGHT", "ROLLBACK", "SAVEPOINT",
+    "SCHEMA", "SELECT", "SET", "SHOW", "TABLE", "TABLES", "TEMP", "TEXT", "THEN", "TO",
+    "TRANSACTION", "TRIGGER", "UNION", "UNIQUE", "UPDATE", "USE", "USING", "VACUUM", "VALUES",
+    "VIEW", "WHEN", "WHERE", "WITH", "WITHOUT", "BLOB",
+];
+
+// Shell commands whose operand is a name, path or label rather than SQL, so
+// that `USE index;` is not taken for a keyword.
+const NAME_COMMANDS: &[&str] = &[
+    "BOOKMARK", "CREATE DATABASE", "DROP DATABASE", "EXPORT", "IMPORT", "RUN", "SAVE QUERY", "USE",
+];
+
+/// Table and column names of the connected database, shared between the REPL
+/// (which refreshes it) and the line editor (which completes from it).
+pub type SchemaCache = Arc<Mutex<Vec<String>>>;
+
+pub struct SqlHelper {
+    schema: SchemaCache,
+    // Colors for the statement being typed; None leaves it uncolored.
+    theme: Option<Theme>,
+    // Show keywords upper-cased as they are typed, as they will be entered.
+    uppercase: bool,
+    history_hinter: HistoryHinter,
+}
+
+impl SqlHelper {
+    pub fn new(schema: SchemaCache) -> Self {
+        SqlHelper { schema, theme: None, uppercase: false, history_hinter: HistoryHinter::new() }
+    }
+
+    pub fn set_theme(&mut self, theme: Option<Theme>) {
+        self.theme = theme;
+    }
+
+    pub fn set_uppercase(&mut self, uppercase: bool) {
+        self.uppercase = uppercase;
+    }
+}
+
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(pos)
+}
+
+impl Completer for SqlHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = word_start(line, pos);
+        let prefix = &line[start..pos];
+
+        if prefix.is_empty() {
+            return Ok((pos, Vec::new()));
+        }
+
+        let lowercase = prefix.chars().all(|c| !c.is_uppercase());
+        let mut candidates: Vec<String> = SQL_KEYWORDS
+            .iter()
+            .filter(|kw| kw.len() >= prefix.len() && kw[..prefix.len()].eq_ignore_ascii_case(prefix))
+            .map(|kw| if lowercase { kw.to_lowercase() } else { kw.to_string() })
+            .collect();
+
+        if let Ok(names) = self.schema.lock() {
+            candidates.extend(
+                names
+                    .iter()
+                    .filter(|name| name.to_lowercase().starts_with(&prefix.to_lowercase()))
+                    .cloned(),
+            );
+        }
+
+        candidates.sort();
+        candidates.dedup();
+
+        let pairs = candidates
+            .into_iter()
+            .map(|c| Pair { display: c.clone(), replacement: c })
+            .collect();
+
+        Ok((start, pairs))
+    }
+}
+
+// Suggests the rest of the newest history entry that starts with the line
+// typed so far, accepted with the right arrow key. Without colors the
+// suggestion could not be told apart from the line, so there is none.
+impl Hinter for SqlHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        self.theme.as_ref()?;
+        self.history_hinter.hint(line, pos, ctx)
+    }
+}
+
+// The byte ranges of `line` to color, with the style of each: keywords,
+// strings, numbers and comments, and in the error style quotes that are never
+// closed and parentheses without a partner.
+fn highlight_spans<'t>(line: &str, theme: &'t Theme) -> Vec<(usize, usize, &'t str)> {
+    let mut spans = Vec::new();
+    let mut open_parens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '-' if line[start..].starts_with("--") => {
+                spans.push((start, line.len(), theme.comment.as_str()));
+                break;
+            },
+            '/' if line[start..].starts_with("/*") => {
+                let end = line[start + 2..].find("*/").map_or(line.len(), |end| start + 2 + end + 2);
+                spans.push((start, end, theme.comment.as_str()));
+                while chars.peek().is_some_and(|&(i, _)| i < end) {
+                    chars.next();
+                }
+            },
+            '\'' | '"' | '`' => {
+                // A doubled quote stands for the quote itself.
+                let mut end = None;
+                while let Some((i, next)) = chars.next() {
+                    if next == c {
+                        if chars.peek().is_some_and(|&(_, after)| after == c) {
+                            chars.next();
+                        } else {
+                            end = Some(i + 1);
+                            break;
+                        }
+                    }
+                }
+                match end {
+                    Some(end) if c == '\'' => spans.push((start, end, theme.string.as_str())),
+                    Some(_) => {},
+                    None => spans.push((start, line.len(), theme.error.as_str())),
+                }
+            },
+            '(' => open_parens.push(start),
+            ')' if open_parens.pop().is_none() => spans.push((start, start + 1, theme.error.as_str())),
+            _ if c.is_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, next)) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_' || (c.is_ascii_digit() && next == '.')) {
+                        break;
+                    }
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+                let word = &line[start..end];
+                if c.is_ascii_digit() {
+                    spans.push((start, end, theme.number.as_str()));
+                } else if SQL_KEYWORDS.iter().any(|keyword| keyword.eq_ignore_ascii_case(word)) {
+                    spans.push((start, end, theme.keyword.as_str()));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    spans.extend(open_parens.into_iter().map(|start| (start, start + 1, theme.error.as_str())));
+    spans.sort_by_key(|&(start, _, _)| start);
+    spans
+}
+
+impl Highlighter for SqlHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let line = if self.uppercase {
+            // Keywords are ASCII, so upper-casing keeps every word in place.
+            // The word at the cursor is left until it is finished.
+            let mut upper = uppercase_keywords(line);
+            let start = word_start(line, pos);
+            let end = line[pos..].find(|c: char| !c.is_alphanumeric() && c != '_').map_or(line.len(), |end| pos + end);
+            upper.replace_range(start..end, &line[start..end]);
+            Cow::Owned(upper)
+        } else {
+            Cow::Borrowed(line)
+        };
+        let Some(theme) = &self.theme else {
+            return line;
+        };
+        let spans = highlight_spans(&line, theme);
+        if spans.is_empty() {
+            return line;
+        }
+
+        let mut highlighted = String::with_capacity(line.len() * 2);
+        let mut done = 0;
+        for (start, end, style) in spans {
+            highlighted.push_str(&line[done..start]);
+            highlighted.push_str(&paint(&line[start..end], style));
+            done = end;
+        }
+        highlighted.push_str(&line[done..]);
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        match &self.theme {
+            Some(theme) => Cow::Owned(paint(hint, &theme.hint)),
+            None => Cow::Borrowed(hint),
+        }
+    }
+
+    // Every change can alter the colors, e.g. by closing a quote, and can
+    // finish a keyword.
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        self.theme.is_some() || self.uppercase
+    }
+}
+
+// Enter starts a new line, instead of running the input, inside a quoted
+// string, a block comment or the body of a trigger.
+impl Validator for SqlHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(if is_incomplete(ctx.input()) { ValidationResult::Incomplete } else { ValidationResult::Valid(None) })
+    }
+}
+
+impl Helper for SqlHelper {}
+
+/// Lays out the statements being edited with `format_sql`, bound to Alt+Q so
+/// that a long one recalled from the history can be read before running it.
+pub struct FormatStatement;
+
+impl ConditionalEventHandler for FormatStatement {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        if ctx.line().trim().is_empty() {
+            return Some(Cmd::Noop);
+        }
+        Some(Cmd::Replace(Movement::WholeBuffer, Some(format_sql(ctx.line()))))
+    }
+}
+
+// The length of the command from NAME_COMMANDS that `input` starts with, if
+// it does.
+fn name_command(input: &str) -> Option<usize> {
+    NAME_COMMANDS.iter().find_map(|command| {
+        let mut length = 0;
+        for word in command.split(' ') {
+            let rest = &input[length..];
+            let start = length + rest.len() - rest.trim_start().len();
+            if (length > 0 && start == length) || !input.get(start..start + word.len()).is_some_and(|found| found.eq_ignore_ascii_case(word)) {
+                return None;
+            }
+            length = start + word.len();
+        }
+        let next = input[length..].chars().next();
+        (!next.is_some_and(|c| c.is_alphanumeric() || c == '_')).then_some(length)
+    })
+}
+
+/// Upper-cases the SQL keywords in `input`. Quoted strings and names,
+/// comments, `:variables`, the parts of paths and qualified names, the
+/// operands of commands such as `USE` and backslash and dot commands are
+/// left as they are.
+pub fn uppercase_keywords(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    let mut statement_start = true;
+    let mut operand = false;
+    let mut previous = None;
+    while let Some(c) = rest.chars().next() {
+        if statement_start {
+            if let Some(length) = name_command(rest) {
+                output.push_str(&rest[..length].to_ascii_uppercase());
+                statement_start = false;
+                operand = true;
+                previous = rest[..length].chars().last();
+                rest = &rest[length..];
+                continue;
+            }
+        }
+        // Each token is copied as it is, unless it is a keyword.
+        let end = match c {
+            '\'' | '"' | '`' => rest[1..].find(c).map_or(rest.len(), |end| end + 2),
+            '[' => rest.find(']').map_or(rest.len(), |end| end + 1),
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest[2..].find("*/").map_or(rest.len(), |end| end + 4),
+            '$' if dollar_quote(rest).is_some() => {
+                let tag = dollar_quote(rest).unwrap_or_default();
+                rest[tag.len()..].find(tag).map_or(rest.len(), |end| end + 2 * tag.len())
+            },
+            '\\' | '.' if statement_start => rest.find([';', '\n']).unwrap_or(rest.len()),
+            c if c.is_alphanumeric() || c == '_' => {
+                let end = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+                let word = &rest[..end];
+                let next = rest[end..].chars().next();
+                let attached = matches!(previous, Some(':' | '@' | '$' | '.' | '/' | '\\')) || matches!(next, Some('.' | '/' | '\\'));
+                if !attached && !operand && SQL_KEYWORDS.contains(&word.to_uppercase().as_str()) {
+                    output.push_str(&word.to_uppercase());
+                } else {
+                    output.push_str(word);
+                }
+                statement_start = false;
+                previous = word.chars().last();
+                rest = &rest[end..];
+                continue;
+            },
+            c => c.len_utf8(),
+        };
+        output.push_str(&rest[..end]);
+        if c == ';' {
+            statement_start = true;
+            operand = false;
+        } else if !c.is_whitespace() {
+            statement_start = false;
+        }
+        previous = rest[..end].chars().last();
+        rest = &rest[end..];
+    }
+    output
+}
+
+/// Binds the shell's own keys, such as Alt+Q, in the line editor `rl`.
+pub fn bind_keys(rl: &mut Editor<SqlHelper, FileHistory>) {
+    rl.bind_sequence(KeyEvent::alt('q'), EventHandler::Conditional(Box::new(FormatStatement)));
+}
+
+/// Collects all table, view and column names of the connected database.
+pub async fn load_schema_names(conn: &mut SqliteConnection) -> Result<Vec<String>> {
+    let tables: Vec<String> = sqlx::query("SELECT name FROM sqlite_master WHERE type IN ('table', 'view');")
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| row.get::<String, _>("name"))
+        .collect();
+
+    let mut names = tables.clone();
+
+    for table in &tables {
+        let columns = sqlx::query("SELECT name FROM pragma_table_info(?);")
+            .bind(table)
+            .fetch_all(&mut *conn)
+            .await?;
+        names.extend(columns.iter().map(|row| row.get::<String, _>("name")));
+    }
+
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+pub fn set_schema_names(schema: &SchemaCache, names: Vec<String>) {
+    if let Ok(mut cache) = schema.lock() {
+        *cache = names;
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use flate2::write::GzEncoder;
+use crate::error::{GalvanizeError, Result};
+
+/// A file that exports and dumps are written to. Files ending in `.gz` or
+/// `.zst` are compressed as they are written.
+pub enum OutputFile {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl OutputFile {
+    /// Creates the file at `path`, compressed according to its extension.
+    pub fn create(path: &Path) -> Result<OutputFile> {
+        let file = File::create(path).map_err(|e| GalvanizeError::Io(format!("Error creating '{}': {}", path.display(), e)))?;
+        let file = BufWriter::new(file);
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+        Ok(match extension.as_deref() {
+            Some("gz") => OutputFile::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Some("zst") => OutputFile::Zstd(zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+            _ => OutputFile::Plain(file),
+        })
+    }
+
+    /// Writes out whatever is still buffered, and the end of the compressed
+    /// stream. A file that is dropped without this is left incomplete.
+    pub fn finish(self) -> Result<()> {
+        let mut file = match self {
+            OutputFile::Plain(file) => file,
+            OutputFile::Gzip(encoder) => encoder.finish()?,
+            OutputFile::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Plain(file) => file.write(buf),
+            OutputFile::Gzip(encoder) => encoder.write(buf),
+            OutputFile::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Gzip(encoder) => encoder.flush(),
+            OutputFile::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::error::{GalvanizeError, Result};
+use crate::theme::{Theme, ThemeStyles};
+
+/// Settings read from the configuration file. Every key is optional, and
+/// command line flags take precedence over the file.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub data_dir: Option<PathBuf>,
+    pub format: Option<String>,
+    pub null: Option<String>,
+    pub blob: Option<String>,
+    pub timing: Option<bool>,
+    pub pager: Option<bool>,
+    pub prompt: Option<String>,
+    /// Key bindings of the line editor, `"emacs"` or `"vi"`.
+    pub keymap: Option<String>,
+    pub overflow: Option<String>,
+    /// How many rows of a result the interactive shell shows at most.
+    pub max_rows: Option<u64>,
+    /// Whether results have a header row, whatever the format's default.
+    pub headers: Option<bool>,
+    pub separator: Option<String>,
+    /// Statement timeout, e.g. `"30s"`.
+    pub timeout: Option<String>,
+    /// How long to wait for a locked SQLite database, e.g. `"10s"`.
+    pub busy_timeout: Option<String>,
+    pub busy_retries: Option<u32>,
+    pub foreign_keys: Option<bool>,
+    pub optimize_on_exit: Option<bool>,
+    /// Whether to register the shell's SQL functions, such as regexp().
+    pub functions: Option<bool>,
+    /// Maximum widths of table columns, by column name.
+    pub column_widths: BTreeMap<String, usize>,
+    pub history_file: Option<PathBuf>,
+    pub history_size: Option<usize>,
+    pub color: Option<bool>,
+    /// Whether to leave out messages about what commands did.
+    pub quiet: Option<bool>,
+    /// Whether each database has a history file of its own.
+    pub history_per_database: Option<bool>,
+    /// Whether keywords typed at the prompt are upper-cased.
+    pub uppercase_keywords: Option<bool>,
+    /// Whether the statements of one input line run in one transaction.
+    pub single_transaction: Option<bool>,
+    /// Name of a built-in theme, or of one in `themes`.
+    pub theme: Option<String>,
+    /// Color themes, by name, e.g. `[themes.mine]` with `header = "bold yellow"`.
+    pub themes: BTreeMap<String, ThemeStyles>,
+    /// File that every statement is recorded in, as JSON lines.
+    pub audit_log: Option<PathBuf>,
+    /// Pragmas applied to every new connection, e.g. `journal_mode = "WAL"`.
+    pub pragmas: BTreeMap<String, toml::Value>,
+}
+
+impl ConfigFile {
+    /// The settings that can also be changed with `SET`, as name/value pairs.
+    pub fn settings(&self) -> Vec<(String, String)> {
+        let mut settings = Vec::new();
+        if let Some(data_dir) = &self.data_dir {
+            settings.push(("data_dir".to_string(), data_dir.display().to_string()));
+        }
+        if let Some(format) = &self.format {
+            settings.push(("format".to_string(), format.clone()));
+        }
+        if let Some(null) = &self.null {
+            settings.push(("null".to_string(), null.clone()));
+        }
+        if let Some(blob) = &self.blob {
+            settings.push(("blob".to_string(), blob.clone()));
+        }
+        if let Some(timing) = self.timing {
+            settings.push(("timing".to_string(), timing.to_string()));
+        }
+        if let Some(pager) = self.pager {
+            settings.push(("pager".to_string(), pager.to_string()));
+        }
+        if let Some(prompt) = &self.prompt {
+            settings.push(("prompt".to_string(), prompt.clone()));
+        }
+        if let Some(keymap) = &self.keymap {
+            settings.push(("keymap".to_string(), keymap.clone()));
+        }
+        if let Some(overflow) = &self.overflow {
+            settings.push(("overflow".to_string(), overflow.clone()));
+        }
+        if let Some(max_rows) = self.max_rows {
+            settings.push(("max_rows".to_string(), max_rows.to_string()));
+        }
+        if let Some(headers) = self.headers {
+            settings.push(("headers".to_string(), headers.to_string()));
+        }
+        if let Some(separator) = &self.separator {
+            settings.push(("separator".to_string(), separator.clone()));
+        }
+        if let Some(timeout) = &self.timeout {
+            settings.push(("timeout".to_string(), timeout.clone()));
+        }
+        if let Some(busy_timeout) = &self.busy_timeout {
+            settings.push(("busy_timeout".to_string(), busy_timeout.clone()));
+        }
+        if let Some(busy_retries) = self.busy_retries {
+            settings.push(("busy_retries".to_string(), busy_retries.to_string()));
+        }
+        if let Some(foreign_keys) = self.foreign_keys {
+            settings.push(("foreign_keys".to_string(), foreign_keys.to_string()));
+        }
+        if let Some(optimize_on_exit) = self.optimize_on_exit {
+            settings.push(("optimize_on_exit".to_string(), optimize_on_exit.to_string()));
+        }
+        if let Some(functions) = self.functions {
+            settings.push(("functions".to_string(), functions.to_string()));
+        }
+        if let Some(color) = self.color {
+            settings.push(("color".to_string(), color.to_string()));
+        }
+        if let Some(quiet) = self.quiet {
+            settings.push(("quiet".to_string(), quiet.to_string()));
+        }
+        if let Some(history_per_database) = self.history_per_database {
+            settings.push(("history_per_database".to_string(), history_per_database.to_string()));
+        }
+        if let Some(uppercase_keywords) = self.uppercase_keywords {
+            settings.push(("uppercase_keywords".to_string(), uppercase_keywords.to_string()));
+        }
+        if let Some(single_transaction) = self.single_transaction {
+            settings.push(("single_transaction".to_string(), single_transaction.to_string()));
+        }
+        if let Some(theme) = &self.theme {
+            settings.push(("theme".to_string(), theme.clone()));
+        }
+        for (column, width) in &self.column_widths {
+            settings.push((format!("width.{}", column), width.to_string()));
+        }
+        settings
+    }
+
+    /// The themes defined in the file, by lowercase name.
+    pub fn themes(&self) -> Result<BTreeMap<String, Theme>> {
+        self.themes.iter().map(|(name, styles)| Ok((name.to_lowercase(), Theme::from_styles(&name.to_lowercase(), styles)?))).collect()
+    }
+
+    /// Pragma names and values in the form they are written in a `PRAGMA` statement.
+    pub fn pragmas(&self) -> Vec<(String, String)> {
+        self.pragmas
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    toml::Value::String(s) => s.clone(),
+                    toml::Value::Boolean(b) => if *b { "ON".to_string() } else { "OFF".to_string() },
+                    other => other.to_string(),
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+}
+
+// $XDG_CONFIG_HOME/galvanizedb, falling back to ~/.config.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => Path::new(&std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("galvanizedb"))
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+fn read(path: &Path) -> Result<ConfigFile> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| GalvanizeError::Io(format!("Error reading '{}': {}", path.display(), e)))?;
+    toml::from_str(&text).map_err(|e| GalvanizeError::Parse(format!("Error in '{}': {}", path.display(), e)))
+}
+
+/// Loads the configuration from `path`, or from the default location when no
+/// path is given. A missing default file is not an error.
+pub fn load(path: Option<&Path>) -> Result<ConfigFile> {
+    match path {
+        Some(path) => read(path),
+        None => match default_path() {
+            Some(path) if path.exists() => read(&path),
+            _ => Ok(ConfigFile::default()),
+        },
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+use sqlx::{ConnectOptions, Connection, Row};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use crate::error::{GalvanizeError, Result};
+use crate::quote_identifier;
+
+struct Column {
+    name: String,
+    kind: String,
+    not_null: bool,
+    default: Option<String>,
+    primary_key: bool,
+}
+
+impl Column {
+    // The column as it is written in CREATE TABLE or ADD COLUMN.
+    fn definition(&self) -> String {
+        let mut definition = quote_identifier(&self.name);
+        if !self.kind.is_empty() {
+            definition.push(' ');
+            definition.push_str(&self.kind);
+        }
+        if self.primary_key {
+            definition.push_str(" PRIMARY KEY");
+        }
+        if self.not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if let Some(default) = &self.default {
+            definition.push_str(" DEFAULT ");
+            definition.push_str(default);
+        }
+        definition
+    }
+
+    fn same_as(&self, other: &Column) -> bool {
+        self.kind.eq_ignore_ascii_case(&other.kind)
+            && self.not_null == other.not_null
+            && self.default == other.default
+            && self.primary_key == other.primary_key
+    }
+}
+
+struct SchemaObject {
+    kind: String,
+    name: String,
+    table: String,
+    sql: String,
+    // The columns of an ordinary table; virtual tables and other objects are
+    // compared by their statement alone.
+    columns: Vec<Column>,
+}
+
+impl SchemaObject {
+    fn is_table(&self) -> bool {
+        self.kind == "table"
+    }
+
+    fn is_virtual(&self) -> bool {
+        self.is_table() && self.sql.to_uppercase().starts_with("CREATE VIRTUAL TABLE")
+    }
+
+    fn key(&self) -> (String, String) {
+        (self.kind.clone(), self.name.to_lowercase())
+    }
+
+    fn label(&self) -> String {
+        if self.kind == "index" || self.kind == "trigger" {
+            format!("{} {} on {}", self.kind, self.name, self.table)
+        } else {
+            format!("{} {}", self.kind, self.name)
+        }
+    }
+
+    fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.name.eq_ignore_ascii_case(name))
+    }
+}
+
+enum Difference<'a> {
+    // Only in the other database.
+    Added(&'a SchemaObject),
+    // Only in the connected database.
+    Removed(&'a SchemaObject),
+    // In both, with a different statement.
+    Changed(&'a SchemaObject, &'a SchemaObject),
+    // A table in both, with different columns or constraints.
+    TableChanged {
+        current: &'a SchemaObject,
+        other: &'a SchemaObject,
+        added: Vec<&'a Column>,
+        removed: Vec<&'a Column>,
+        changed: Vec<(&'a Column, &'a Column)>,
+    },
+}
+
+impl Difference<'_> {
+    // Whether reconciling the table takes creating it anew and copying the
+    // rows over, because ALTER TABLE can only add and drop columns.
+    fn needs_rebuild(&self) -> bool {
+        let Difference::TableChanged { added, removed, changed, .. } = self else {
+            return false;
+        };
+        !changed.is_empty()
+            || (added.is_empty() && removed.is_empty())
+            || added.iter().any(|column| column.primary_key || (column.not_null && column.default.is_none()))
+            || removed.iter().any(|column| column.primary_key)
+    }
+
+    fn write(&self, prefix: &str, out: &mut dyn Write) -> Result<()> {
+        match self {
+            Difference::Added(object) => writeln!(out, "{}+ {}", prefix, object.label())?,
+            Difference::Removed(object) => writeln!(out, "{}- {}", prefix, object.label())?,
+            Difference::Changed(current, other) => {
+                writeln!(out, "{}~ {}", prefix, current.label())?;
+                writeln!(out, "{}    - {}", prefix, normalize_sql(&current.sql))?;
+                writeln!(out, "{}    + {}", prefix, normalize_sql(&other.sql))?;
+            },
+            Difference::TableChanged { current, other, added, removed, changed } => {
+                writeln!(out, "{}~ {}", prefix, current.label())?;
+                for column in added {
+                    writeln!(out, "{}    + column {}", prefix, column.definition())?;
+                }
+                for column in removed {
+                    writeln!(out, "{}    - column {}", prefix, column.definition())?;
+                }
+                for (before, after) in changed {
+                    writeln!(out, "{}    ~ column {} -> {}", prefix, before.definition(), after.definition())?;
+                }
+                if added.is_empty() && removed.is_empty() && changed.is_empty() {
+                    writeln!(out, "{}    - {}", prefix, normalize_sql(&current.sql))?;
+                    writeln!(out, "{}    + {}", prefix, normalize_sql(&other.sql))?;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+// The statement on one line, with runs of whitespace collapsed and none
+// around punctuation, so that layout alone is not a difference.
+fn normalize_sql(sql: &str) -> String {
+    let mut normalized = String::with_capacity(sql.len());
+    let mut space = false;
+    for c in sql.trim().chars() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        let word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '"' | '\'' | '`' | ']');
+        if space && normalized.ends_with(word) && word(c) {
+            normalized.push(' ');
+        }
+        space = false;
+        normalized.push(c);
+    }
+    normalized
+}
+
+// Whether two statements define the same thing, regardless of layout, the
+// case of keywords and names, and whether names are quoted.
+fn same_sql(a: &str, b: &str) -> bool {
+    let comparable = |sql: &str| {
+        let mut comparable = String::with_capacity(sql.len());
+        let mut in_literal = false;
+        for c in sql.chars() {
+            match c {
+                '\'' => {
+                    in_literal = !in_literal;
+                    comparable.push(c);
+                },
+                _ if in_literal => comparable.push(c),
+                '"' | '`' | '[' | ']' => {},
+                _ => comparable.push(c.to_ascii_lowercase()),
+            }
+        }
+        normalize_sql(&comparable)
+    };
+    comparable(a) == comparable(b)
+}
+
+async fn read_columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<Column>> {
+    Ok(sqlx::query("SELECT name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?) ORDER BY cid;")
+        .bind(table)
+        .fetch_all(conn)
+        .await?
+        .iter()
+        .map(|row| Column {
+            name: row.get("name"),
+            kind: row.get("type"),
+            not_null: row.get::<i64, _>("notnull") != 0,
+            default: row.get("dflt_value"),
+            primary_key: row.get::<i64, _>("pk") != 0,
+        })
+        .collect())
+}
+
+// The tables, indexes, views and triggers of a database in creation order,
+// leaving out SQLite's own objects and the shadow tables of virtual tables.
+async fn read_schema(conn: &mut SqliteConnection) -> Result<Vec<SchemaObject>> {
+    let mut objects: Vec<SchemaObject> = sqlx::query(
+        "SELECT type, name, tbl_name, sql FROM sqlite_master \
+            WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY rowid;",
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .iter()
+    .map(|row| SchemaObject {
+        kind: row.get("type"),
+        name: row.get("name"),
+        table: row.get("tbl_name"),
+        sql: row.get("sql"),
+        columns: Vec::new(),
+    })
+    .collect();
+
+    let virtual_tables: Vec<String> = objects.iter().filter(|object| object.is_virtual()).map(|object| object.name.clone()).collect();
+    let is_shadow = |name: &str| {
+        virtual_tables.iter().any(|vt| name.len() > vt.len() + 1 && name.starts_with(vt.as_str()) && name[vt.len()..].starts_with('_'))
+    };
+    objects.retain(|object| !((object.is_table() || object.kind == "index") && is_shadow(&object.name)));
+
+    for object in objects.iter_mut().filter(|object| object.is_table() && !object.is_virtual()) {
+        object.columns = read_columns(conn, &object.name).await?;
+    }
+    Ok(objects)
+}
+
+fn compare<'a>(current: &'a [SchemaObject], other: &'a [SchemaObject]) -> Vec<Difference<'a>> {
+    let mut differences = Vec::new();
+    for object in current {
+        match other.iter().find(|candidate| candidate.key() == object.key()) {
+            None => differences.push(Difference::Removed(object)),
+            Some(counterpart) if object.is_table() && !object.is_virtual() && !counterpart.is_virtual() => {
+                let added: Vec<&Column> = counterpart.columns.iter().filter(|column| object.column(&column.name).is_none()).collect();
+                let removed: Vec<&Column> = object.columns.iter().filter(|column| counterpart.column(&column.name).is_none()).collect();
+                let changed: Vec<(&Column, &Column)> = object
+                    .columns
+                    .iter()
+                    .filter_map(|column| counterpart.column(&column.name).filter(|after| !column.same_as(after)).map(|after| (column, after)))
+                    .collect();
+                if !added.is_empty() || !removed.is_empty() || !changed.is_empty() || !same_sql(&object.sql, &counterpart.sql) {
+                    differences.push(Difference::TableChanged { current: object, other: counterpart, added, removed, changed });
+                }
+            },
+            Some(counterpart) => {
+                if !same_sql(&object.sql, &counterpart.sql) {
+                    differences.push(Difference::Changed(object, counterpart));
+                }
+            },
+        }
+    }
+    for object in other {
+        if !current.iter().any(|candidate| candidate.key() == object.key()) {
+            differences.push(Difference::Added(object));
+        }
+    }
+    differences
+}
+
+// Writes the statements that turn the `current` schema into the `other` one,
+// each group headed by the difference it reconciles. Tables whose columns
+// change beyond what ALTER TABLE supports are created anew under their name
+// and get the rows of the columns they keep.
+fn write_statements(other: &[SchemaObject], differences: &[Difference], out: &mut dyn Write) -> Result<()> {
+    let rebuilt: HashSet<String> = differences
+        .iter()
+        .filter(|difference| difference.needs_rebuild())
+        .filter_map(|difference| match difference {
+            Difference::TableChanged { current, .. } => Some(current.name.to_lowercase()),
+            _ => None,
+        })
+        .collect();
+
+    // Dependent objects are dropped first and created last, so they never
+    // refer to a table in the middle of being changed.
+    if !rebuilt.is_empty() {
+        writeln!(out, "PRAGMA foreign_keys=OFF;")?;
+        writeln!(out, "PRAGMA legacy_alter_table=ON;")?;
+    }
+    writeln!(out, "BEGIN TRANSACTION;")?;
+    for kind in ["trigger", "view", "index"] {
+        for difference in differences {
+            let object = match difference {
+                Difference::Removed(object) | Difference::Changed(object, _) if object.kind == kind => object,
+                _ => continue,
+            };
+            difference.write("-- ", out)?;
+            writeln!(out, "DROP {} {};", kind.to_uppercase(), quote_identifier(&object.name))?;
+        }
+    }
+
+    for difference in differences {
+        match difference {
+            Difference::Removed(object) if object.is_table() => {
+                difference.write("-- ", out)?;
+                writeln!(out, "DROP TABLE {};", quote_identifier(&object.name))?;
+            },
+            Difference::Changed(current, other) if current.is_table() => {
+                difference.write("-- ", out)?;
+                writeln!(out, "DROP TABLE {};", quote_identifier(&current.name))?;
+                writeln!(out, "{};", other.sql)?;
+            },
+            Difference::Added(object) if object.is_table() => {
+                difference.write("-- ", out)?;
+                writeln!(out, "{};", object.sql)?;
+            },
+            Difference::TableChanged { current, other, added, removed, .. } => {
+                difference.write("-- ", out)?;
+                let table = quote_identifier(&current.name);
+                if difference.needs_rebuild() {
+                    let old = quote_identifier(&format!("_{}_old", current.name));
+                    let kept = other
+                        .columns
+                        .iter()
+                        .filter(|column| current.column(&column.name).is_some())
+                        .map(|column| quote_identifier(&column.name))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    writeln!(out, "ALTER TABLE {} RENAME TO {};", table, old)?;
+                    writeln!(out, "{};", other.sql)?;
+                    if !kept.is_empty() {
+                        writeln!(out, "INSERT INTO {} ({}) SELECT {} FROM {};", table, kept, kept, old)?;
+                    }
+                    writeln!(out, "DROP TABLE {};", old)?;
+                } else {
+                    for column in added {
+                        writeln!(out, "ALTER TABLE {} ADD COLUMN {};", table, column.definition())?;
+                    }
+                    for column in removed {
+                        writeln!(out, "ALTER TABLE {} DROP COLUMN {};", table, quote_identifier(&column.name))?;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // A rebuilt table lost its indexes and triggers with the old copy, so it
+    // gets all of them again, changed or not.
+    for kind in ["index", "view", "trigger"] {
+        for object in other.iter().filter(|object| object.kind == kind) {
+            let difference = differences.iter().find(|difference| match difference {
+                Difference::Added(added) | Difference::Changed(_, added) => added.key() == object.key(),
+                _ => false,
+            });
+            match difference {
+                Some(difference) => difference.write("-- ", out)?,
+                None if rebuilt.contains(&object.table.to_lowercase()) => {},
+                None => continue,
+            }
+            writeln!(out, "{};", object.sql)?;
+        }
+    }
+    writeln!(out, "COMMIT;")?;
+    if !rebuilt.is_empty() {
+        writeln!(out, "PRAGMA legacy_alter_table=OFF;")?;
+    }
+    Ok(())
+}
+
+/// Compares the tables, columns, indexes, views and triggers of the connected
+/// database with those of the SQLite database at `path`, and writes the
+/// differences, `+` for what only the other database has and `-` for what
+/// only the connected one has. With `statements`, it writes instead an SQL
+/// script that makes the connected database match the other one. Returns the
+/// number of differences.
+pub async fn diff_schema(conn: &mut SqliteConnection, path: &Path, statements: bool, out: &mut dyn Write) -> Result<usize> {
+    let mut other_conn = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await
+        .map_err(|e| GalvanizeError::from(e).context(format!("Error opening '{}'", path.display())))?;
+    let other = read_schema(&mut other_conn).await;
+    let _ = other_conn.close().await;
+    let other = other?;
+    let current = read_schema(conn).await?;
+
+    let differences = compare(&current, &other);
+    if differences.is_empty() {
+        return Ok(0);
+    }
+    if statements {
+        write_statements(&other, &differences, out)?;
+    } else {
+        for difference in &differences {
+            difference.write("", out)?;
+        }
+    }
+    out.flush()?;
+    Ok(differences.len())
+}
@@ -0,0 +1,220 @@
+use std::io::Write;
+use futures_util::TryStreamExt;
+use sqlx::{Row, TypeInfo, ValueRef};
+use sqlx::sqlite::{SqliteConnection, SqliteRow};
+use crate::error::{GalvanizeError, Result};
+use crate::{quote_identifier, quote_literal};
+
+/// What a dump includes: the schema, the rows or both, of the tables whose
+/// names match one of `tables` (all tables if empty) and none of `exclude`.
+/// Both are LIKE patterns, matched without regard to case.
+pub struct DumpOptions {
+    pub schema: bool,
+    pub data: bool,
+    pub tables: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        DumpOptions {
+            schema: true,
+            data: true,
+            tables: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl DumpOptions {
+    fn includes(&self, table: &str) -> bool {
+        (self.tables.is_empty() || self.tables.iter().any(|pattern| like_matches(pattern, table)))
+            && !self.exclude.iter().any(|pattern| like_matches(pattern, table))
+    }
+
+    fn is_filtered(&self) -> bool {
+        !self.tables.is_empty() || !self.exclude.is_empty()
+    }
+}
+
+struct SchemaObject {
+    kind: String,
+    name: String,
+    table: String,
+    sql: String,
+}
+
+// Whether `text` matches an SQL LIKE `pattern`, where % matches any run of
+// characters and _ any one character. ASCII letters match either case.
+fn like_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let text: Vec<char> = text.chars().map(|c| c.to_ascii_lowercase()).collect();
+    // matches[j]: whether the pattern so far matches the first j characters.
+    let mut matches = vec![false; text.len() + 1];
+    matches[0] = true;
+    for p in pattern {
+        let mut next = vec![false; text.len() + 1];
+        for j in 0..=text.len() {
+            next[j] = match p {
+                '%' => matches[j] || (j > 0 && next[j - 1]),
+                '_' => j > 0 && matches[j - 1],
+                c => j > 0 && matches[j - 1] && text[j - 1] == c,
+            };
+        }
+        matches = next;
+    }
+    matches[text.len()]
+}
+
+// Renders a column of a row as an SQL literal, based on the value's storage
+// class so that it round-trips exactly.
+fn sql_literal(row: &SqliteRow, index: usize) -> String {
+    let raw = match row.try_get_raw(index) {
+        Ok(raw) => raw,
+        Err(_) => return "NULL".to_string(),
+    };
+
+    if raw.is_null() {
+        return "NULL".to_string();
+    }
+
+    let storage_class = raw.type_info().name().to_string();
+    match storage_class.as_str() {
+        "INTEGER" => row.try_get_unchecked::<i64, _>(index).unwrap_or_default().to_string(),
+        "REAL" => {
+            let value: f64 = row.try_get_unchecked(index).unwrap_or_default();
+            if value.is_nan() {
+                "NULL".to_string()
+            } else if value.is_infinite() {
+                if value > 0.0 { "9e999".to_string() } else { "-9e999".to_string() }
+            } else {
+                format!("{:?}", value)
+            }
+        },
+        "BLOB" => {
+            let bytes: Vec<u8> = row.try_get_unchecked(index).unwrap_or_default();
+            format!("X'{}'", bytes.iter().map(|b| format!("{:02X}", b)).collect::<String>())
+        },
+        _ => quote_literal(&row.try_get_unchecked::<String, _>(index).unwrap_or_default()),
+    }
+}
+
+async fn dump_table_data(conn: &mut SqliteConnection, table: &str, filter: &str, out: &mut dyn Write) -> Result<()> {
+    let query = format!("SELECT * FROM {}{};", quote_identifier(table), filter);
+    let mut rows = sqlx::query(&query).fetch(conn);
+
+    while let Some(row) = rows.try_next().await? {
+        let values: Vec<String> = (0..row.len()).map(|i| sql_literal(&row, i)).collect();
+        writeln!(out, "INSERT INTO {} VALUES({});", quote_identifier(table), values.join(","))?;
+    }
+    Ok(())
+}
+
+/// Writes the statements that create the tables, views, indexes and triggers
+/// of the database, in creation order, and returns how many there were. With
+/// a LIKE `pattern`, only objects whose name or table matches it are written.
+pub async fn write_schema(conn: &mut SqliteConnection, pattern: Option<&str>, out: &mut dyn Write) -> Result<usize> {
+    let statements: Vec<String> = sqlx::query_scalar(
+        "SELECT sql FROM sqlite_master WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+            AND (?1 IS NULL OR name LIKE ?1 OR tbl_name LIKE ?1) ORDER BY rowid;",
+    )
+    .bind(pattern)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for sql in &statements {
+        writeln!(out, "{};", sql)?;
+    }
+    out.flush()?;
+    Ok(statements.len())
+}
+
+/// Writes the schema and contents of the database as an SQL script that
+/// recreates it. Tables and their rows come first, followed by indexes, views
+/// and triggers, each in creation order, so every object exists before
+/// anything that depends on it. `options` limits the dump to the schema or
+/// the rows, and to some of the tables; indexes and triggers go with their
+/// table.
+pub async fn dump_database(conn: &mut SqliteConnection, options: &DumpOptions, out: &mut dyn Write) -> Result<()> {
+    let objects: Vec<SchemaObject> = sqlx::query(
+        "SELECT type, name, tbl_name, sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY rowid;",
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .iter()
+    .map(|row| SchemaObject {
+        kind: row.get("type"),
+        name: row.get("name"),
+        table: row.get("tbl_name"),
+        sql: row.get("sql"),
+    })
+    .collect();
+
+    let virtual_tables: Vec<String> = objects
+        .iter()
+        .filter(|object| object.kind == "table" && object.sql.to_uppercase().starts_with("CREATE VIRTUAL TABLE"))
+        .map(|object| object.name.clone())
+        .collect();
+
+    // Shadow tables are maintained by their virtual table and recreated with it.
+    let is_shadow_table = |name: &str| {
+        virtual_tables.iter().any(|vt| name.len() > vt.len() + 1 && name.starts_with(vt.as_str()) && name[vt.len()..].starts_with('_'))
+    };
+
+    let objects: Vec<SchemaObject> = objects
+        .into_iter()
+        .filter(|object| object.name.starts_with("sqlite_") || options.includes(&object.table))
+        .collect();
+
+    if options.is_filtered() && !objects.iter().any(|object| !object.name.starts_with("sqlite_")) {
+        return Err(GalvanizeError::Execution(if options.tables.is_empty() {
+            "Every table is excluded, so there is nothing to dump.".to_string()
+        } else {
+            format!("No tables to dump match {}.", options.tables.join(", "))
+        }));
+    }
+
+    writeln!(out, "PRAGMA foreign_keys=OFF;")?;
+    writeln!(out, "BEGIN TRANSACTION;")?;
+
+    let mut dumped_tables = Vec::new();
+    for object in objects.iter().filter(|object| object.kind == "table") {
+        if object.name.starts_with("sqlite_") || is_shadow_table(&object.name) {
+            continue;
+        }
+        if options.schema {
+            writeln!(out, "{};", object.sql)?;
+        }
+        if options.data {
+            dump_table_data(conn, &object.name, "", out).await?;
+        }
+        dumped_tables.push(quote_literal(&object.name));
+    }
+
+    if options.data && objects.iter().any(|object| object.name == "sqlite_sequence") {
+        if options.is_filtered() {
+            // Only the counters of the dumped tables are reset.
+            let filter = format!(" WHERE name IN ({})", dumped_tables.join(", "));
+            writeln!(out, "DELETE FROM sqlite_sequence{};", filter)?;
+            dump_table_data(conn, "sqlite_sequence", &filter, out).await?;
+        } else {
+            writeln!(out, "DELETE FROM sqlite_sequence;")?;
+            dump_table_data(conn, "sqlite_sequence", "", out).await?;
+        }
+    }
+
+    if options.schema {
+        for kind in ["index", "view", "trigger"] {
+            // Only a virtual table's own indexes are shadows; triggers and
+            // views are never created by it, even when named like one.
+            let is_shadow = |object: &SchemaObject| kind == "index" && is_shadow_table(&object.name);
+            for object in objects.iter().filter(|object| object.kind == kind && !is_shadow(object)) {
+                writeln!(out, "{};", object.sql)?;
+            }
+        }
+    }
+
+    writeln!(out, "COMMIT;")?;
+    out.flush()?;
+    Ok(())
+}
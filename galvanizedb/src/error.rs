@@ -0,0 +1,161 @@
+use std::fmt::Display;
+use thiserror::Error;
+
+pub type Result<T, E = GalvanizeError> = std::result::Result<T, E>;
+
+/// An error from any GalvanizeDB operation. The message is written for the
+/// user; the variant says what kind of failure it was, which decides the exit
+/// status of a batch run.
+#[derive(Debug, Error)]
+pub enum GalvanizeError {
+    /// A database could not be opened, or none is connected.
+    #[error("{0}")]
+    Connection(String),
+    /// A command, setting or input file could not be understood.
+    #[error("{0}")]
+    Parse(String),
+    /// The database rejected a statement, or a command could not be carried out.
+    #[error("{0}")]
+    Execution(String),
+    /// A file could not be read or written.
+    #[error("{0}")]
+    Io(String),
+    /// The command is not available for the type of the connected database.
+    #[error("{0}")]
+    UnsupportedType(String),
+    /// The database is locked by another connection or process.
+    #[error("{0}")]
+    Busy(String),
+}
+
+/// Where the statement that an error came from is, in a script.
+pub struct ErrorLocation {
+    /// The script: a file name, `<command line>` or `<stdin>`.
+    pub source: String,
+    pub statement: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl GalvanizeError {
+    /// A short name for the kind of error, for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GalvanizeError::Connection(_) => "connection",
+            GalvanizeError::Parse(_) => "parse",
+            GalvanizeError::Execution(_) => "execution",
+            GalvanizeError::Io(_) => "io",
+            GalvanizeError::UnsupportedType(_) => "unsupported-type",
+            GalvanizeError::Busy(_) => "busy",
+        }
+    }
+
+    /// The process exit status for a batch run that failed with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GalvanizeError::Execution(_) | GalvanizeError::Busy(_) => 1,
+            GalvanizeError::Parse(_) => 2,
+            GalvanizeError::Connection(_) => 3,
+            GalvanizeError::Io(_) => 4,
+            GalvanizeError::UnsupportedType(_) => 5,
+        }
+    }
+
+    /// The error as one line of JSON, for `--json-errors`: its kind, exit
+    /// status and message, and the statement it came from, if known.
+    pub fn to_json(&self, location: Option<&ErrorLocation>) -> String {
+        let mut json = serde_json::json!({
+            "code": self.kind(),
+            "exit_code": self.exit_code(),
+            "message": self.to_string(),
+        });
+        if let Some(location) = location {
+            json["source"] = location.source.clone().into();
+            json["statement"] = location.statement.clone().into();
+            json["line"] = location.line.into();
+            json["column"] = location.column.into();
+        }
+        json.to_string()
+    }
+
+    /// The same error, with `context` put in front of its message.
+    pub fn context(self, context: impl Display) -> Self {
+        let wrap = |message: String| format!("{}: {}", context, message);
+        match self {
+            GalvanizeError::Connection(message) => GalvanizeError::Connection(wrap(message)),
+            GalvanizeError::Parse(message) => GalvanizeError::Parse(wrap(message)),
+            GalvanizeError::Execution(message) => GalvanizeError::Execution(wrap(message)),
+            GalvanizeError::Io(message) => GalvanizeError::Io(wrap(message)),
+            GalvanizeError::UnsupportedType(message) => GalvanizeError::UnsupportedType(wrap(message)),
+            GalvanizeError::Busy(message) => GalvanizeError::Busy(wrap(message)),
+        }
+    }
+}
+
+impl From<std::io::Error> for GalvanizeError {
+    fn from(e: std::io::Error) -> Self {
+        GalvanizeError::Io(e.to_string())
+    }
+}
+
+// Whether SQLite failed with SQLITE_BUSY or SQLITE_LOCKED, or one of their
+// extended codes.
+fn is_sqlite_busy(e: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(db) = e else {
+        return false;
+    };
+    db.try_downcast_ref::<sqlx::sqlite::SqliteError>().is_some()
+        && db.code().and_then(|code| code.parse::<i32>().ok()).is_some_and(|code| matches!(code & 0xff, 5 | 6))
+}
+
+impl From<sqlx::Error> for GalvanizeError {
+    fn from(e: sqlx::Error) -> Self {
+        if is_sqlite_busy(&e) {
+            return GalvanizeError::Busy(format!(
+                "{}. Another connection or process is using the database; try again once it has finished, \
+                 or wait longer with SET busy_timeout and retry automatically with SET busy_retries.",
+                e
+            ));
+        }
+        match e {
+            sqlx::Error::Configuration(_)
+            | sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed => GalvanizeError::Connection(e.to_string()),
+            _ => GalvanizeError::Execution(e.to_string()),
+        }
+    }
+}
+
+impl From<csv::Error> for GalvanizeError {
+    fn from(e: csv::Error) -> Self {
+        if e.is_io_error() {
+            GalvanizeError::Io(e.to_string())
+        } else {
+            GalvanizeError::Parse(e.to_string())
+        }
+    }
+}
+
+impl From<parquet::errors::ParquetError> for GalvanizeError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        GalvanizeError::Io(format!("Error writing Parquet: {}", e))
+    }
+}
+
+impl From<arrow_schema::ArrowError> for GalvanizeError {
+    fn from(e: arrow_schema::ArrowError) -> Self {
+        GalvanizeError::Execution(format!("Error converting rows for Parquet: {}", e))
+    }
+}
+
+impl From<serde_json::Error> for GalvanizeError {
+    fn from(e: serde_json::Error) -> Self {
+        if e.is_io() {
+            GalvanizeError::Io(e.to_string())
+        } else {
+            GalvanizeError::Execution(e.to_string())
+        }
+    }
+}
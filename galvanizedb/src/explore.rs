@@ -0,0 +1,356 @@
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+use crate::error::Result;
+use crate::output::Value;
+use crate::session::Session;
+use crate::{postgres, quote_identifier, quote_literal};
+
+const HELP: &str = " q quit  arrows move  Enter/Right open  Left close  PgUp/PgDn scroll the definition ";
+
+/// An entry of the schema tree: a database, a table or view, the group of
+/// its columns, indexes or triggers, or one of those.
+pub struct Node {
+    label: String,
+    // Shown beside the tree while the node is selected: its CREATE
+    // statement, or a description.
+    detail: String,
+    children: Vec<Node>,
+    expanded: bool,
+}
+
+impl Node {
+    fn new(label: String, detail: String) -> Node {
+        Node { label, detail, children: Vec::new(), expanded: false }
+    }
+
+    // A node listing `children` under `name`, left out when there are none.
+    fn group(name: &str, children: Vec<Node>) -> Option<Node> {
+        if children.is_empty() {
+            return None;
+        }
+        let detail = children.iter().map(|child| child.detail.as_str()).collect::<Vec<_>>().join("\n\n");
+        Some(Node { label: format!("{} ({})", name, children.len()), detail, children, expanded: false })
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(v) => v.to_string(),
+        Value::Real(v) => v.to_string(),
+        Value::Boolean(v) => v.to_string(),
+        Value::Decimal(v) | Value::Text(v) => v.clone(),
+        Value::Blob(v) => String::from_utf8_lossy(v).into_owned(),
+    }
+}
+
+fn is_true(value: &Value) -> bool {
+    match value {
+        Value::Integer(v) => *v != 0,
+        Value::Boolean(v) => *v,
+        Value::Text(v) => v.eq_ignore_ascii_case("yes") || v.eq_ignore_ascii_case("true"),
+        _ => false,
+    }
+}
+
+// A column, from its name, type, whether it may be NULL, its default and
+// whether it is part of the primary key.
+fn column_node(table: &str, row: &[Value]) -> Node {
+    let (name, kind, default) = (text(&row[0]), text(&row[1]), text(&row[3]));
+    let (not_null, primary_key) = (is_true(&row[2]), is_true(&row[4]));
+    let mut label = format!("{} {}", name, kind).trim_end().to_string();
+    if primary_key {
+        label.push_str(" PK");
+    }
+    let mut detail = format!(
+        "Column {} of {}\n\nType:        {}\nNull:        {}",
+        name,
+        table,
+        if kind.is_empty() { "(none)" } else { &kind },
+        if not_null { "NO" } else { "YES" }
+    );
+    if !default.is_empty() {
+        detail.push_str(&format!("\nDefault:     {}", default));
+    }
+    if primary_key {
+        detail.push_str("\nPrimary key: YES");
+    }
+    Node::new(label, detail)
+}
+
+/// Reads the schema of every database of an SQLite session (main, temp and
+/// any attached ones), or of the current schema of a PostgreSQL session.
+pub async fn load(db: &mut Session) -> Result<Vec<Node>> {
+    let mut databases = if db.is_postgres() { vec![load_postgres(db).await?] } else { load_sqlite(db).await? };
+    if let Some(first) = databases.first_mut() {
+        first.expanded = true;
+    }
+    Ok(databases)
+}
+
+async fn load_sqlite(db: &mut Session) -> Result<Vec<Node>> {
+    let mut databases = Vec::new();
+    for database in db.execute_sql("SELECT name, file FROM pragma_database_list ORDER BY seq;").await?.rows {
+        let (name, file) = (text(&database[0]), text(&database[1]));
+        let schema = quote_identifier(&name);
+        let objects = db
+            .execute_sql(&format!(
+                "SELECT type, name, tbl_name, sql FROM {}.sqlite_master \
+                 WHERE name NOT LIKE 'sqlite\\_%' ESCAPE '\\' AND sql IS NOT NULL ORDER BY name;",
+                schema
+            ))
+            .await?
+            .rows;
+        let columns = db
+            .execute_sql(&format!(
+                "SELECT m.name, p.name, p.type, p.\"notnull\", p.dflt_value, p.pk \
+                 FROM {}.sqlite_master m, pragma_table_info(m.name, {}) p \
+                 WHERE m.type IN ('table', 'view') ORDER BY m.name, p.cid;",
+                schema,
+                quote_literal(&name)
+            ))
+            .await?
+            .rows;
+
+        let mut tables = Vec::new();
+        for object in objects.iter().filter(|object| matches!(text(&object[0]).as_str(), "table" | "view")) {
+            let table = text(&object[1]);
+            // Indexes and triggers belong to the table named in tbl_name.
+            let belonging = |kind: &str| -> Vec<Node> {
+                objects
+                    .iter()
+                    .filter(|other| text(&other[0]) == kind && text(&other[2]) == table)
+                    .map(|other| Node::new(text(&other[1]), format!("{};", text(&other[3]))))
+                    .collect()
+            };
+            let table_columns = columns
+                .iter()
+                .filter(|column| text(&column[0]) == table)
+                .map(|column| column_node(&table, &column[1..]))
+                .collect();
+            let mut node = Node::new(format!("{} ({})", table, text(&object[0])), format!("{};", text(&object[3])));
+            node.children = [Node::group("Columns", table_columns), Node::group("Indexes", belonging("index")), Node::group("Triggers", belonging("trigger"))]
+                .into_iter()
+                .flatten()
+                .collect();
+            tables.push(node);
+        }
+
+        let label = if file.is_empty() { name.clone() } else { format!("{} ({})", name, file) };
+        let detail = format!(
+            "Database {}\n\nFile:   {}\nTables: {}\nViews:  {}",
+            name,
+            if file.is_empty() { "(in memory)" } else { &file },
+            objects.iter().filter(|object| text(&object[0]) == "table").count(),
+            objects.iter().filter(|object| text(&object[0]) == "view").count()
+        );
+        let mut node = Node::new(label, detail);
+        node.children = tables;
+        databases.push(node);
+    }
+    Ok(databases)
+}
+
+async fn load_postgres(db: &mut Session) -> Result<Node> {
+    let database = db.execute_sql("SELECT current_database()::text, current_schema()::text;").await?.rows;
+    let (name, schema) = database.first().map(|row| (text(&row[0]), text(&row[1]))).unwrap_or_default();
+    let tables = db.execute_sql(postgres::EXPLORE_TABLES).await?.rows;
+    let columns = db.execute_sql(postgres::EXPLORE_COLUMNS).await?.rows;
+    let indexes = db.execute_sql(postgres::EXPLORE_INDEXES).await?.rows;
+    let triggers = db.execute_sql(postgres::EXPLORE_TRIGGERS).await?.rows;
+
+    let belonging = |objects: &[Vec<Value>], table: &str| -> Vec<Node> {
+        objects
+            .iter()
+            .filter(|object| text(&object[0]) == table)
+            .map(|object| Node::new(text(&object[1]), format!("{};", text(&object[2]))))
+            .collect()
+    };
+    let mut nodes = Vec::new();
+    for table in &tables {
+        let (table, kind, definition) = (text(&table[0]), text(&table[1]), text(&table[2]));
+        let table_columns: Vec<&[Value]> = columns.iter().filter(|column| text(&column[0]) == table).map(|column| &column[1..]).collect();
+        // PostgreSQL keeps no CREATE TABLE statement, so one is put
+        // together from the columns.
+        let detail = if kind == "view" {
+            format!("CREATE VIEW {} AS\n{}", quote_identifier(&table), definition.trim_end())
+        } else {
+            let lines: Vec<String> = table_columns
+                .iter()
+                .map(|column| {
+                    let mut line = format!("    {} {}", quote_identifier(&text(&column[0])), text(&column[1]));
+                    if is_true(&column[2]) {
+                        line.push_str(" NOT NULL");
+                    }
+                    if !matches!(column[3], Value::Null) {
+                        line.push_str(&format!(" DEFAULT {}", text(&column[3])));
+                    }
+                    line
+                })
+                .collect();
+            format!("CREATE TABLE {} (\n{}\n);", quote_identifier(&table), lines.join(",\n"))
+        };
+        let mut node = Node::new(format!("{} ({})", table, kind), detail);
+        node.children = [
+            Node::group("Columns", table_columns.iter().map(|column| column_node(&table, column)).collect()),
+            Node::group("Indexes", belonging(&indexes, &table)),
+            Node::group("Triggers", belonging(&triggers, &table)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        nodes.push(node);
+    }
+
+    let detail = format!("Database {}\n\nSchema: {}\nTables: {}", name, schema, nodes.len());
+    let mut node = Node::new(format!("{} ({})", name, schema), detail);
+    node.children = nodes;
+    Ok(node)
+}
+
+/// A full-screen tree of the schema, with the definition of the selected
+/// entry beside it, for `\explore`.
+pub struct Explorer {
+    nodes: Vec<Node>,
+    // The selected line of the tree.
+    selected: usize,
+    // How far the definition is scrolled down.
+    scroll: u16,
+    list: ListState,
+}
+
+impl Explorer {
+    pub fn new(nodes: Vec<Node>) -> Explorer {
+        Explorer { nodes, selected: 0, scroll: 0, list: ListState::default() }
+    }
+
+    /// Takes over the terminal until the user quits.
+    pub fn run(&mut self) -> Result<()> {
+        let mut terminal = ratatui::try_init()?;
+        let result = self.event_loop(&mut terminal);
+        ratatui::try_restore()?;
+        result
+    }
+
+    // The lines of the tree, as the depth and path of each node shown.
+    fn lines(&self) -> Vec<(usize, Vec<usize>)> {
+        fn walk(nodes: &[Node], path: &mut Vec<usize>, lines: &mut Vec<(usize, Vec<usize>)>) {
+            for (i, node) in nodes.iter().enumerate() {
+                path.push(i);
+                lines.push((path.len() - 1, path.clone()));
+                if node.expanded {
+                    walk(&node.children, path, lines);
+                }
+                path.pop();
+            }
+        }
+        let mut lines = Vec::new();
+        walk(&self.nodes, &mut Vec::new(), &mut lines);
+        lines
+    }
+
+    fn node(&self, path: &[usize]) -> &Node {
+        let mut node = &self.nodes[path[0]];
+        for &i in &path[1..] {
+            node = &node.children[i];
+        }
+        node
+    }
+
+    fn node_mut(&mut self, path: &[usize]) -> &mut Node {
+        let mut node = &mut self.nodes[path[0]];
+        for &i in &path[1..] {
+            node = &mut node.children[i];
+        }
+        node
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let lines = self.lines();
+            let Some((_, path)) = lines.get(self.selected).cloned() else { return Ok(()) };
+            let before = self.selected;
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(lines.len() - 1),
+                KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+                KeyCode::End | KeyCode::Char('G') => self.selected = lines.len() - 1,
+                KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+                KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+                // Opens a closed entry, or steps into an open one.
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                    let node = self.node_mut(&path);
+                    if node.expanded {
+                        self.selected += 1;
+                    } else {
+                        node.expanded = !node.children.is_empty();
+                    }
+                },
+                KeyCode::Char(' ') => {
+                    let node = self.node_mut(&path);
+                    node.expanded = !node.expanded && !node.children.is_empty();
+                },
+                // Closes an open entry, or steps out to the one it is in.
+                KeyCode::Left | KeyCode::Char('h') => {
+                    let node = self.node_mut(&path);
+                    if node.expanded {
+                        node.expanded = false;
+                    } else if path.len() > 1 {
+                        let parent = &path[..path.len() - 1];
+                        self.selected = lines.iter().position(|(_, line)| line == parent).unwrap_or(0);
+                    }
+                },
+                _ => {},
+            }
+            if self.selected != before {
+                self.scroll = 0;
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main_area, status_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [tree_area, detail_area] = Layout::horizontal([Constraint::Percentage(40), Constraint::Min(0)]).areas(main_area);
+
+        let lines = self.lines();
+        let items: Vec<ListItem> = lines
+            .iter()
+            .map(|(depth, path)| {
+                let node = self.node(path);
+                let marker = match (node.children.is_empty(), node.expanded) {
+                    (true, _) => "  ",
+                    (false, true) => "▾ ",
+                    (false, false) => "▸ ",
+                };
+                ListItem::new(format!("{}{}{}", "  ".repeat(*depth), marker, node.label))
+            })
+            .collect();
+        let tree = List::new(items)
+            .block(Block::bordered().title(" Schema "))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        self.list.select(Some(self.selected));
+        frame.render_stateful_widget(tree, tree_area, &mut self.list);
+
+        if let Some((_, path)) = lines.get(self.selected) {
+            let node = self.node(path);
+            let detail = Paragraph::new(node.detail.as_str())
+                .wrap(Wrap { trim: false })
+                .scroll((self.scroll, 0))
+                .block(Block::bordered().title(format!(" {} ", node.label)));
+            frame.render_widget(detail, detail_area);
+        }
+
+        frame.render_widget(Paragraph::new(Line::from(HELP).dim()), status_area);
+    }
+}
@@ -0,0 +1,117 @@
+use std::ffi::{CString, c_int, c_void};
+use std::fmt::Write;
+use libsqlite3_sys::*;
+use md5::Md5;
+use regex::bytes::Regex;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqliteConnection;
+
+type Function = unsafe extern "C" fn(*mut sqlite3_context, c_int, *mut *mut sqlite3_value);
+
+const FUNCTIONS: [(&str, c_int, bool, Function); 4] = [
+    ("regexp", 2, true, regexp),
+    ("uuid4", 0, false, uuid4),
+    ("md5", 1, true, md5),
+    ("sha256", 1, true, sha256),
+];
+
+/// Registers `regexp(pattern, text)`, which also makes `text REGEXP pattern`
+/// work, `uuid4()`, `md5(value)` and `sha256(value)` on a connection.
+pub(crate) async fn register(conn: &mut SqliteConnection) -> sqlx::Result<()> {
+    let mut handle = conn.lock_handle().await?;
+    let db = handle.as_raw_handle().as_ptr();
+    for (name, arguments, deterministic, function) in FUNCTIONS {
+        let name = CString::new(name).expect("function names have no NUL characters");
+        let flags = SQLITE_UTF8 | if deterministic { SQLITE_DETERMINISTIC | SQLITE_INNOCUOUS } else { 0 };
+        // SAFETY: the handle is valid and locked for the duration of the call,
+        // and SQLite copies the name.
+        let code = unsafe {
+            sqlite3_create_function_v2(db, name.as_ptr(), arguments, flags, std::ptr::null_mut(), Some(function), None, None, None)
+        };
+        if code != SQLITE_OK {
+            return Err(sqlx::Error::Configuration(format!("Error registering the SQL function {}()", name.to_string_lossy()).into()));
+        }
+    }
+    Ok(())
+}
+
+// The bytes of a text or blob argument, or None if it is NULL.
+unsafe fn argument<'a>(argv: *mut *mut sqlite3_value, index: usize) -> Option<&'a [u8]> {
+    let value = *argv.add(index);
+    if sqlite3_value_type(value) == SQLITE_NULL {
+        return None;
+    }
+    // sqlite3_value_bytes must be called after sqlite3_value_blob, which may
+    // convert the value.
+    let data = sqlite3_value_blob(value);
+    let len = sqlite3_value_bytes(value);
+    if data.is_null() || len <= 0 {
+        Some(&[])
+    } else {
+        Some(std::slice::from_raw_parts(data.cast(), len as usize))
+    }
+}
+
+unsafe fn result_text(ctx: *mut sqlite3_context, text: &str) {
+    sqlite3_result_text(ctx, text.as_ptr().cast(), text.len() as c_int, SQLITE_TRANSIENT());
+}
+
+unsafe fn result_error(ctx: *mut sqlite3_context, message: &str) {
+    sqlite3_result_error(ctx, message.as_ptr().cast(), message.len() as c_int);
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+unsafe extern "C" fn drop_regex(regex: *mut c_void) {
+    drop(Box::from_raw(regex.cast::<Regex>()));
+}
+
+// regexp(pattern, text): 1 if the text matches the pattern anywhere, 0 if not.
+// The compiled pattern is kept as auxiliary data, so it is only compiled once
+// per statement.
+unsafe extern "C" fn regexp(ctx: *mut sqlite3_context, _argc: c_int, argv: *mut *mut sqlite3_value) {
+    let (Some(pattern), Some(text)) = (argument(argv, 0), argument(argv, 1)) else {
+        sqlite3_result_null(ctx);
+        return;
+    };
+
+    let cached = sqlite3_get_auxdata(ctx, 0).cast::<Regex>();
+    if !cached.is_null() {
+        sqlite3_result_int(ctx, (*cached).is_match(text) as c_int);
+        return;
+    }
+    let compiled = match std::str::from_utf8(pattern).map_err(|e| e.to_string()).and_then(|p| Regex::new(p).map_err(|e| e.to_string())) {
+        Ok(regex) => regex,
+        Err(e) => return result_error(ctx, &format!("Invalid regular expression: {}", e)),
+    };
+    sqlite3_result_int(ctx, compiled.is_match(text) as c_int);
+    // SQLite may drop the pattern straight away, so it is only handed over
+    // once it has been used.
+    sqlite3_set_auxdata(ctx, 0, Box::into_raw(Box::new(compiled)).cast(), Some(drop_regex));
+}
+
+// uuid4(): a random UUID as text.
+unsafe extern "C" fn uuid4(ctx: *mut sqlite3_context, _argc: c_int, _argv: *mut *mut sqlite3_value) {
+    result_text(ctx, &uuid::Uuid::new_v4().to_string());
+}
+
+// md5(value): the MD5 digest of a text or blob, in hex.
+unsafe extern "C" fn md5(ctx: *mut sqlite3_context, _argc: c_int, argv: *mut *mut sqlite3_value) {
+    match argument(argv, 0) {
+        Some(value) => result_text(ctx, &to_hex(&Md5::digest(value))),
+        None => sqlite3_result_null(ctx),
+    }
+}
+
+// sha256(value): the SHA-256 digest of a text or blob, in hex.
+unsafe extern "C" fn sha256(ctx: *mut sqlite3_context, _argc: c_int, argv: *mut *mut sqlite3_value) {
+    match argument(argv, 0) {
+        Some(value) => result_text(ctx, &to_hex(&Sha256::digest(value))),
+        None => sqlite3_result_null(ctx),
+    }
+}
@@ -0,0 +1,526 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+use crate::error::{GalvanizeError, Result};
+use crate::import::{MAX_BATCH_ROWS, MAX_BOUND_PARAMETERS};
+use crate::output::{Value, format_unix_time, row_values};
+use crate::quote_identifier;
+use crate::session::bind_values;
+
+const FIRST_NAMES: [&str; 30] = [
+    "Anna", "Ben", "Carla", "David", "Elena", "Felix", "Grace", "Hugo", "Ines", "Jonas", "Katja", "Liam", "Maria", "Noah", "Olga",
+    "Pavel", "Quinn", "Rosa", "Sven", "Tara", "Umar", "Vera", "Wim", "Xenia", "Yusuf", "Zoe", "Marko", "Lena", "Ivan", "Sara",
+];
+const LAST_NAMES: [&str; 30] = [
+    "Smith", "Novak", "Garcia", "Müller", "Rossi", "Kowalski", "Jansen", "Horvat", "Dubois", "Silva", "Petrov", "Nagy", "Berg",
+    "Walsh", "Kim", "Chen", "Okafor", "Larsen", "Costa", "Fischer", "Moreau", "Ivanova", "Brown", "Weber", "Santos", "Haddad",
+    "Lindqvist", "Tanaka", "Kovač", "Murphy",
+];
+const CITIES: [&str; 20] = [
+    "Amsterdam", "Berlin", "Lisbon", "Zagreb", "Vienna", "Prague", "Oslo", "Dublin", "Madrid", "Warsaw", "Toronto", "Chicago",
+    "Austin", "Melbourne", "Osaka", "Seoul", "Nairobi", "Lima", "Lyon", "Milan",
+];
+const COUNTRIES: [&str; 16] = [
+    "Netherlands", "Germany", "Portugal", "Croatia", "Austria", "Czechia", "Norway", "Ireland", "Spain", "Poland", "Canada",
+    "United States", "Australia", "Japan", "Kenya", "Peru",
+];
+const STREETS: [&str; 12] = [
+    "Main Street", "Oak Avenue", "Station Road", "Park Lane", "Church Street", "Mill Road", "High Street", "Elm Street",
+    "River Road", "Market Square", "Hill Street", "Lake Drive",
+];
+const COMPANIES: [&str; 12] = [
+    "Acme", "Globex", "Initech", "Umbrella", "Hooli", "Vandelay", "Stark", "Wayne", "Tyrell", "Cyberdyne", "Soylent", "Wonka",
+];
+const DOMAINS: [&str; 5] = ["example.com", "example.org", "example.net", "mail.test", "inbox.test"];
+const WORDS: [&str; 40] = [
+    "lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit", "sed", "do", "eiusmod", "tempor",
+    "incididunt", "ut", "labore", "et", "dolore", "magna", "aliqua", "enim", "ad", "minim", "veniam", "quis", "nostrud",
+    "exercitation", "ullamco", "laboris", "nisi", "aliquip", "ex", "ea", "commodo", "consequat", "duis", "aute", "irure",
+    "in", "reprehenderit", "voluptate",
+];
+const COLORS: [&str; 8] = ["red", "green", "blue", "yellow", "purple", "orange", "black", "white"];
+const STATUSES: [&str; 4] = ["active", "inactive", "pending", "archived"];
+
+// Dates are generated within the last five years.
+const DATE_RANGE_SECS: i64 = 5 * 365 * 86_400;
+
+/// How the values of a column are made up.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Generator {
+    Name,
+    FirstName,
+    LastName,
+    Username,
+    Email,
+    Phone,
+    Street,
+    City,
+    Country,
+    Zip,
+    Company,
+    Url,
+    Word,
+    Sentence,
+    Paragraph,
+    Int(i64, i64),
+    Real(f64, f64),
+    Bool,
+    Date,
+    DateTime,
+    Uuid,
+    Blob,
+    OneOf(Vec<String>),
+    Null,
+    // A value of the referenced column of another table.
+    Reference(Vec<Value>),
+}
+
+const GENERATOR_NAMES: &str = "name, first_name, last_name, username, email, phone, street, city, country, zip, company, url, \
+    word, sentence, paragraph, int(min, max), real(min, max), bool, date, datetime, uuid, blob, one_of('a', 'b', ...) or null";
+
+// Splits `text` at commas that are outside quotes and parentheses.
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in text.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('(', None) => depth += 1,
+            (')', None) => depth -= 1,
+            (',', None) if depth == 0 => {
+                parts.push(text[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+
+fn unquote_argument(argument: &str) -> String {
+    let argument = argument.trim();
+    for quote in ['\'', '"'] {
+        if argument.len() >= 2 && argument.starts_with(quote) && argument.ends_with(quote) {
+            let inner = &argument[1..argument.len() - 1];
+            return inner.replace(&format!("{}{}", quote, quote), &quote.to_string());
+        }
+    }
+    argument.to_string()
+}
+
+/// Parses per-column generators like `age = int(18, 90), status = one_of('new', 'paid')`.
+pub fn parse_overrides(text: &str) -> Result<Vec<(String, Generator)>> {
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    split_top_level(text)
+        .into_iter()
+        .map(|assignment| {
+            let (column, spec) = assignment
+                .split_once('=')
+                .ok_or_else(|| GalvanizeError::Parse(format!("Expected column = generator, got '{}'.", assignment)))?;
+            let column = unquote_argument(column);
+            if column.is_empty() {
+                return Err(GalvanizeError::Parse(format!("Expected column = generator, got '{}'.", assignment)));
+            }
+            Ok((column, Generator::parse(spec)?))
+        })
+        .collect()
+}
+
+impl Generator {
+    /// Parses a generator such as `email`, `int(18, 90)` or
+    /// `one_of('new', 'paid')`.
+    fn parse(spec: &str) -> Result<Generator> {
+        let spec = spec.trim();
+        let (name, arguments) = match spec.split_once('(') {
+            Some((name, rest)) => {
+                let arguments = rest.trim_end().strip_suffix(')').ok_or_else(|| Self::unknown(spec))?;
+                (name.trim().to_lowercase(), split_top_level(arguments).into_iter().filter(|a| !a.is_empty()).collect())
+            },
+            None => (spec.to_lowercase(), Vec::new()),
+        };
+        let numbers = |arguments: &[&str]| -> Result<(f64, f64)> {
+            match arguments {
+                [min, max] => match (min.parse::<f64>(), max.parse::<f64>()) {
+                    (Ok(min), Ok(max)) if min <= max && (max - min).is_finite() => Ok((min, max)),
+                    (Ok(min), Ok(max)) if min <= max => {
+                        Err(GalvanizeError::Parse(format!("{}() needs finite bounds, no further apart than {:e}.", name, f64::MAX)))
+                    },
+                    _ => Err(GalvanizeError::Parse(format!("{}() needs a minimum and a maximum, smallest first.", name))),
+                },
+                _ => Err(GalvanizeError::Parse(format!("{}() needs a minimum and a maximum, like {}(1, 100).", name, name))),
+            }
+        };
+
+        let generator = match (name.as_str(), arguments.as_slice()) {
+            ("int", arguments) => {
+                let (min, max) = numbers(arguments)?;
+                Generator::Int(min as i64, max as i64)
+            },
+            ("real", arguments) => {
+                let (min, max) = numbers(arguments)?;
+                Generator::Real(min, max)
+            },
+            ("one_of", []) => return Err(GalvanizeError::Parse("one_of() needs at least one value.".to_string())),
+            ("one_of", arguments) => Generator::OneOf(arguments.iter().map(|argument| unquote_argument(argument)).collect()),
+            (_, [_, ..]) => return Err(Self::unknown(spec)),
+            ("name", []) => Generator::Name,
+            ("first_name", []) => Generator::FirstName,
+            ("last_name", []) => Generator::LastName,
+            ("username", []) => Generator::Username,
+            ("email", []) => Generator::Email,
+            ("phone", []) => Generator::Phone,
+            ("street", []) => Generator::Street,
+            ("city", []) => Generator::City,
+            ("country", []) => Generator::Country,
+            ("zip", []) => Generator::Zip,
+            ("company", []) => Generator::Company,
+            ("url", []) => Generator::Url,
+            ("word", []) => Generator::Word,
+            ("sentence", []) => Generator::Sentence,
+            ("paragraph", []) => Generator::Paragraph,
+            ("bool", []) => Generator::Bool,
+            ("date", []) => Generator::Date,
+            ("datetime", []) => Generator::DateTime,
+            ("uuid", []) => Generator::Uuid,
+            ("blob", []) => Generator::Blob,
+            ("null", []) => Generator::Null,
+            _ => return Err(Self::unknown(spec)),
+        };
+        Ok(generator)
+    }
+
+    fn unknown(spec: &str) -> GalvanizeError {
+        GalvanizeError::Parse(format!("Unknown generator '{}'. Generators are {}.", spec, GENERATOR_NAMES))
+    }
+
+    // Picks a generator that suits a column, by its name first and its
+    // declared type after that.
+    fn infer(name: &str, declared_type: &str) -> Generator {
+        let name = name.to_lowercase();
+        let kind = declared_type.to_uppercase();
+        let has = |words: &[&str]| words.iter().any(|word| name.contains(word));
+
+        if has(&["email", "e_mail"]) {
+            Generator::Email
+        } else if has(&["first_name", "firstname", "given_name", "forename"]) {
+            Generator::FirstName
+        } else if has(&["last_name", "lastname", "surname", "family_name"]) {
+            Generator::LastName
+        } else if has(&["username", "user_name", "login", "handle", "nick"]) {
+            Generator::Username
+        } else if has(&["company", "organization", "organisation", "employer"]) {
+            Generator::Company
+        } else if name == "name" || name.ends_with("_name") || name == "author" || name == "customer" {
+            Generator::Name
+        } else if has(&["phone", "mobile", "fax"]) {
+            Generator::Phone
+        } else if has(&["street", "address"]) {
+            Generator::Street
+        } else if has(&["city", "town"]) {
+            Generator::City
+        } else if has(&["country"]) {
+            Generator::Country
+        } else if has(&["zip", "postal", "postcode"]) {
+            Generator::Zip
+        } else if has(&["url", "website", "homepage", "link"]) {
+            Generator::Url
+        } else if has(&["uuid", "guid"]) {
+            Generator::Uuid
+        } else if has(&["color", "colour"]) {
+            Generator::OneOf(COLORS.map(String::from).to_vec())
+        } else if has(&["status", "state"]) {
+            Generator::OneOf(STATUSES.map(String::from).to_vec())
+        } else if has(&["description", "body", "bio", "content", "comment", "notes", "summary", "text"]) {
+            Generator::Paragraph
+        } else if has(&["title", "subject", "headline", "label"]) {
+            Generator::Sentence
+        } else if name.starts_with("is_") || name.starts_with("has_") || has(&["active", "enabled", "verified", "deleted"]) || kind.contains("BOOL") {
+            Generator::Bool
+        } else if name == "age" || name.ends_with("_age") {
+            Generator::Int(18, 90)
+        } else if has(&["price", "amount", "total", "cost", "salary", "balance", "fee"]) {
+            Generator::Real(1.0, 1000.0)
+        } else if has(&["quantity", "qty", "count", "stock"]) {
+            Generator::Int(1, 100)
+        } else if has(&["rating", "score"]) {
+            Generator::Int(1, 5)
+        } else if name == "lat" || has(&["latitude"]) {
+            Generator::Real(-90.0, 90.0)
+        } else if name == "lon" || name == "lng" || has(&["longitude"]) {
+            Generator::Real(-180.0, 180.0)
+        } else if kind == "DATE" || name.ends_with("_on") || name.ends_with("date") || name == "birthday" {
+            Generator::Date
+        } else if kind.contains("TIME") || name.ends_with("_at") || has(&["created", "updated", "timestamp"]) {
+            Generator::DateTime
+        } else if kind.contains("INT") {
+            Generator::Int(0, 1000)
+        } else if kind.contains("REAL") || kind.contains("FLOA") || kind.contains("DOUB") || kind.contains("NUM") || kind.contains("DEC") {
+            Generator::Real(0.0, 1000.0)
+        } else if kind.contains("BLOB") {
+            Generator::Blob
+        } else {
+            Generator::Word
+        }
+    }
+
+    fn generate(&self, rng: &mut impl Rng, now: i64) -> Value {
+        let pick = |rng: &mut dyn rand::RngCore, list: &[&str]| list.choose(rng).copied().unwrap_or_default().to_string();
+        let words = |rng: &mut dyn rand::RngCore, count: usize| (0..count).map(|_| pick(rng, &WORDS)).collect::<Vec<_>>().join(" ");
+        let sentence = |rng: &mut dyn rand::RngCore| {
+            let count = rng.gen_range(4..10);
+            let text = words(rng, count);
+            let mut chars = text.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        };
+        let ascii = |name: String| name.to_lowercase().replace('ü', "u").replace('č', "c");
+
+        match self {
+            Generator::Name => Value::Text(format!("{} {}", pick(rng, &FIRST_NAMES), pick(rng, &LAST_NAMES))),
+            Generator::FirstName => Value::Text(pick(rng, &FIRST_NAMES)),
+            Generator::LastName => Value::Text(pick(rng, &LAST_NAMES)),
+            Generator::Username => Value::Text(format!("{}{}", ascii(pick(rng, &FIRST_NAMES)), rng.gen_range(1..10_000))),
+            Generator::Email => Value::Text(format!(
+                "{}.{}{}@{}",
+                ascii(pick(rng, &FIRST_NAMES)),
+                ascii(pick(rng, &LAST_NAMES)),
+                rng.gen_range(1..100_000),
+                pick(rng, &DOMAINS)
+            )),
+            Generator::Phone => Value::Text(format!("+1-{:03}-{:03}-{:04}", rng.gen_range(200..1000), rng.gen_range(200..1000), rng.gen_range(0..10_000))),
+            Generator::Street => Value::Text(format!("{} {}", rng.gen_range(1..300), pick(rng, &STREETS))),
+            Generator::City => Value::Text(pick(rng, &CITIES)),
+            Generator::Country => Value::Text(pick(rng, &COUNTRIES)),
+            Generator::Zip => Value::Text(format!("{:05}", rng.gen_range(1000..100_000))),
+            Generator::Company => Value::Text(format!("{} {}", pick(rng, &COMPANIES), pick(rng, &["Inc.", "Ltd.", "GmbH", "Group", "Labs"]))),
+            Generator::Url => Value::Text(format!("https://{}.{}/{}", pick(rng, &WORDS), pick(rng, &DOMAINS), pick(rng, &WORDS))),
+            Generator::Word => Value::Text(pick(rng, &WORDS)),
+            Generator::Sentence => Value::Text(sentence(rng)),
+            Generator::Paragraph => {
+                let count = rng.gen_range(2..5);
+                Value::Text((0..count).map(|_| format!("{}.", sentence(rng))).collect::<Vec<_>>().join(" "))
+            },
+            Generator::Int(min, max) => Value::Integer(rng.gen_range(*min..=*max)),
+            Generator::Real(min, max) => Value::Real((rng.gen_range(*min..=*max) * 100.0).round() / 100.0),
+            Generator::Bool => Value::Integer(rng.gen_range(0..=1)),
+            Generator::Date => Value::Text(format_unix_time(now - rng.gen_range(0..DATE_RANGE_SECS), "DATE")),
+            Generator::DateTime => Value::Text(format_unix_time(now - rng.gen_range(0..DATE_RANGE_SECS), "DATETIME")),
+            Generator::Uuid => Value::Text(uuid::Uuid::new_v4().to_string()),
+            Generator::Blob => Value::Blob((0..16).map(|_| rng.gen()).collect()),
+            Generator::OneOf(values) => Value::Text(values.choose(rng).cloned().unwrap_or_default()),
+            Generator::Null => Value::Null,
+            Generator::Reference(values) => values.choose(rng).cloned().unwrap_or(Value::Null),
+        }
+    }
+}
+
+struct TableColumn {
+    name: String,
+    declared_type: String,
+    // Whether SQLite fills the column in itself, as with INTEGER PRIMARY KEY.
+    automatic: bool,
+}
+
+async fn table_columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<TableColumn>> {
+    let rows = sqlx::query("SELECT name, type, pk, (SELECT count(*) FROM pragma_table_info(?1) WHERE pk > 0) AS pk_columns FROM pragma_table_xinfo(?1) WHERE hidden = 0 ORDER BY cid;")
+        .bind(table)
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let declared_type: String = row.get("type");
+            let primary_key = row.get::<i64, _>("pk") > 0 && row.get::<i64, _>("pk_columns") == 1;
+            TableColumn {
+                name: row.get("name"),
+                automatic: primary_key && declared_type.eq_ignore_ascii_case("INTEGER"),
+                declared_type,
+            }
+        })
+        .collect())
+}
+
+// Generators that take values from the tables that foreign keys point to, so
+// the generated rows refer to rows that exist.
+async fn reference_generators(conn: &mut SqliteConnection, table: &str) -> Result<Vec<(String, Generator)>> {
+    let keys = sqlx::query("SELECT \"from\", \"table\", \"to\" FROM pragma_foreign_key_list(?) WHERE seq = 0;")
+        .bind(table)
+        .fetch_all(&mut *conn)
+        .await?;
+    let mut generators = Vec::new();
+    for key in keys {
+        let column: String = key.get("from");
+        let parent: String = key.get("table");
+        let mut parent_column: Option<String> = key.get("to");
+        // Without a column, the key refers to the primary key of the parent,
+        // whose first column goes with the key's first. Only a parent with no
+        // declared primary key is keyed by its rowid.
+        if parent_column.is_none() {
+            parent_column = sqlx::query_scalar("SELECT name FROM pragma_table_info(?) WHERE pk > 0 ORDER BY pk LIMIT 1;")
+                .bind(&parent)
+                .fetch_optional(&mut *conn)
+                .await?;
+        }
+        let parent_column = parent_column.map_or_else(|| "rowid".to_string(), |column| quote_identifier(&column));
+        let query = format!("SELECT {} FROM {} ORDER BY random() LIMIT 10000;", parent_column, quote_identifier(&parent));
+        let values = sqlx::query(&query)
+            .fetch_all(&mut *conn)
+            .await?
+            .iter()
+            .filter_map(|row| row_values(row).into_iter().next())
+            .collect();
+        generators.push((column, Generator::Reference(values)));
+    }
+    Ok(generators)
+}
+
+async fn insert_rows(conn: &mut SqliteConnection, table: &str, columns: &[(String, Generator)], count: u64) -> Result<()> {
+    let mut rng = rand::thread_rng();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64);
+    let batch_rows = (MAX_BOUND_PARAMETERS / columns.len().max(1)).clamp(1, MAX_BATCH_ROWS) as u64;
+    let column_list = columns.iter().map(|(name, _)| quote_identifier(name)).collect::<Vec<_>>().join(", ");
+    let placeholders = format!("({})", vec!["?"; columns.len()].join(", "));
+
+    let mut remaining = count;
+    while remaining > 0 {
+        let rows = remaining.min(batch_rows);
+        let sql = if columns.is_empty() {
+            format!("INSERT INTO {} DEFAULT VALUES;", quote_identifier(table))
+        } else {
+            format!("INSERT INTO {} ({}) VALUES {};", quote_identifier(table), column_list, vec![placeholders.as_str(); rows as usize].join(", "))
+        };
+        let rows = if columns.is_empty() { 1 } else { rows };
+        let values: Vec<Value> = (0..rows).flat_map(|_| columns.iter().map(|(_, generator)| generator.generate(&mut rng, now)).collect::<Vec<_>>()).collect();
+        bind_values(sqlx::query(&sql), &values).execute(&mut *conn).await?;
+        remaining -= rows;
+    }
+    Ok(())
+}
+
+/// Inserts `count` rows of made-up data into `table`. Each column gets values
+/// from its generator in `overrides`, or else from one that suits its name and
+/// type; foreign keys take values from the rows they can refer to, and
+/// INTEGER PRIMARY KEY columns are left to SQLite. The rows are inserted in
+/// batches under a savepoint, so either all of them are or none.
+pub async fn generate_rows(conn: &mut SqliteConnection, table: &str, count: u64, overrides: &[(String, Generator)]) -> Result<()> {
+    let table_columns = table_columns(conn, table).await?;
+    if table_columns.is_empty() {
+        return Err(GalvanizeError::Execution(format!("Table '{}' does not exist.", table)));
+    }
+    if let Some((name, _)) = overrides.iter().find(|(name, _)| !table_columns.iter().any(|column| column.name.eq_ignore_ascii_case(name))) {
+        return Err(GalvanizeError::Execution(format!("Table '{}' has no column '{}'.", table, name)));
+    }
+
+    let references = reference_generators(conn, table).await?;
+    let mut columns = Vec::new();
+    for column in &table_columns {
+        let chosen = overrides.iter().chain(&references).find(|(name, _)| name.eq_ignore_ascii_case(&column.name));
+        match chosen {
+            Some((_, generator)) => columns.push((column.name.clone(), generator.clone())),
+            None if column.automatic => {},
+            None => columns.push((column.name.clone(), Generator::infer(&column.name, &column.declared_type))),
+        }
+    }
+
+    sqlx::query("SAVEPOINT generate_rows;").execute(&mut *conn).await?;
+    match insert_rows(conn, table, &columns, count).await {
+        Ok(()) => {
+            sqlx::query("RELEASE generate_rows;").execute(&mut *conn).await?;
+            Ok(())
+        },
+        Err(e) => {
+            sqlx::query("ROLLBACK TO generate_rows;").execute(&mut *conn).await?;
+            sqlx::query("RELEASE generate_rows;").execute(&mut *conn).await?;
+            Err(e)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(spec: &str) -> String {
+        Generator::parse(spec).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_bounds() {
+        assert_eq!(Generator::parse("int(18, 90)").unwrap(), Generator::Int(18, 90));
+        assert_eq!(Generator::parse(" INT ( -5 ,5 ) ").unwrap(), Generator::Int(-5, 5));
+        assert_eq!(Generator::parse("int(7, 7)").unwrap(), Generator::Int(7, 7));
+        assert_eq!(Generator::parse("real(0.5, 2)").unwrap(), Generator::Real(0.5, 2.0));
+        assert!(error("int(90, 18)").contains("smallest first"));
+        assert!(error("int(a, b)").contains("smallest first"));
+        assert!(error("int(1)").contains("like int(1, 100)"));
+        assert!(error("int()").contains("like int(1, 100)"));
+        assert!(error("real(-1e308, 1e308)").contains("finite bounds"));
+        assert!(error("int(1, 2").contains("Unknown generator"));
+    }
+
+    #[test]
+    fn unquotes_one_of_values() {
+        assert_eq!(
+            Generator::parse("one_of('new', \"paid\", plain)").unwrap(),
+            Generator::OneOf(vec!["new".to_string(), "paid".to_string(), "plain".to_string()])
+        );
+        assert_eq!(
+            Generator::parse("one_of('a, b', 'it''s', '(x)')").unwrap(),
+            Generator::OneOf(vec!["a, b".to_string(), "it's".to_string(), "(x)".to_string()])
+        );
+        assert!(error("one_of()").contains("at least one value"));
+    }
+
+    #[test]
+    fn rejects_unknown_generators() {
+        assert!(error("colour").starts_with("Unknown generator 'colour'."));
+        assert!(error("email(5)").starts_with("Unknown generator 'email(5)'."));
+        assert!(error("").starts_with("Unknown generator ''."));
+        assert_eq!(Generator::parse("Email").unwrap(), Generator::Email);
+    }
+
+    #[test]
+    fn parses_overrides() {
+        assert_eq!(parse_overrides("  ").unwrap(), []);
+        assert_eq!(
+            parse_overrides("age = int(18, 90), \"Status\" = one_of('new', 'paid'), email = null").unwrap(),
+            [
+                ("age".to_string(), Generator::Int(18, 90)),
+                ("Status".to_string(), Generator::OneOf(vec!["new".to_string(), "paid".to_string()])),
+                ("email".to_string(), Generator::Null),
+            ]
+        );
+        assert!(parse_overrides("age int(1, 2)").unwrap_err().to_string().contains("Expected column = generator"));
+        assert!(parse_overrides(" = email").unwrap_err().to_string().contains("Expected column = generator"));
+        assert!(parse_overrides("age = int(1, 2), name = nope").is_err());
+    }
+
+    #[test]
+    fn infers_from_names_before_types() {
+        assert_eq!(Generator::infer("Email_Address", "TEXT"), Generator::Email);
+        assert_eq!(Generator::infer("first_name", "TEXT"), Generator::FirstName);
+        assert_eq!(Generator::infer("customer_name", "TEXT"), Generator::Name);
+        assert_eq!(Generator::infer("age", "TEXT"), Generator::Int(18, 90));
+        assert_eq!(Generator::infer("is_admin", "TEXT"), Generator::Bool);
+        assert_eq!(Generator::infer("price", "INTEGER"), Generator::Real(1.0, 1000.0));
+        assert_eq!(Generator::infer("created_at", "TEXT"), Generator::DateTime);
+        assert_eq!(Generator::infer("shipped_on", "TEXT"), Generator::Date);
+        assert_eq!(Generator::infer("status", "TEXT"), Generator::OneOf(STATUSES.map(String::from).to_vec()));
+    }
+
+    #[test]
+    fn infers_from_types_otherwise() {
+        assert_eq!(Generator::infer("x", "INTEGER"), Generator::Int(0, 1000));
+        assert_eq!(Generator::infer("x", "DECIMAL(10, 2)"), Generator::Real(0.0, 1000.0));
+        assert_eq!(Generator::infer("x", "boolean"), Generator::Bool);
+        assert_eq!(Generator::infer("x", "DATE"), Generator::Date);
+        assert_eq!(Generator::infer("x", "TIMESTAMP"), Generator::DateTime);
+        assert_eq!(Generator::infer("x", "BLOB"), Generator::Blob);
+        assert_eq!(Generator::infer("x", ""), Generator::Word);
+    }
+}
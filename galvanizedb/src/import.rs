@@ -0,0 +1,390 @@
+use std::path::Path;
+use clap::ValueEnum;
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+use crate::error::{GalvanizeError, Result};
+use crate::quote_identifier;
+
+// SQLite allows at most 32766 bound parameters per statement.
+pub(crate) const MAX_BOUND_PARAMETERS: usize = 32_766;
+pub(crate) const MAX_BATCH_ROWS: usize = 500;
+
+/// What an import does with a row that violates a uniqueness or other
+/// constraint of the table.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    /// Fail the import, which then changes nothing.
+    #[default]
+    Abort,
+    /// Skip the row.
+    Ignore,
+    /// Replace the rows it conflicts with.
+    Replace,
+}
+
+impl OnConflict {
+    pub fn from_name(name: &str) -> Option<OnConflict> {
+        OnConflict::from_str(name, true).ok()
+    }
+
+    fn insert(&self) -> &'static str {
+        match self {
+            OnConflict::Abort => "INSERT",
+            OnConflict::Ignore => "INSERT OR IGNORE",
+            OnConflict::Replace => "INSERT OR REPLACE",
+        }
+    }
+}
+
+/// How many rows an import read from its file, and how many of them it
+/// inserted; the rest were skipped with [`OnConflict::Ignore`].
+#[derive(Clone, Copy, Default)]
+pub struct ImportCount {
+    pub read: u64,
+    pub inserted: u64,
+}
+
+impl ImportCount {
+    fn add(&mut self, read: usize, inserted: u64) {
+        self.read += read as u64;
+        self.inserted += inserted;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColumnType {
+    Integer,
+    Real,
+    Text,
+}
+
+impl ColumnType {
+    fn name(&self) -> &'static str {
+        match self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Text => "TEXT",
+        }
+    }
+
+    // Widens the type so that it can also hold `value`.
+    fn widen(self, value: &str) -> ColumnType {
+        if value.is_empty() {
+            return self;
+        }
+        match self {
+            ColumnType::Integer if value.parse::<i64>().is_ok() => ColumnType::Integer,
+            ColumnType::Integer | ColumnType::Real if value.parse::<f64>().is_ok() => ColumnType::Real,
+            _ => ColumnType::Text,
+        }
+    }
+}
+
+fn csv_reader(path: &Path) -> Result<csv::Reader<std::fs::File>> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| GalvanizeError::from(e).context(format!("Error opening '{}'", path.display())))
+}
+
+// Returns the name and declared type of every column of the table.
+async fn table_columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<(String, String)>> {
+    Ok(sqlx::query("SELECT name, type FROM pragma_table_info(?);")
+        .bind(table)
+        .fetch_all(conn)
+        .await?
+        .iter()
+        .map(|row| (row.get::<String, _>("name"), row.get::<String, _>("type")))
+        .collect())
+}
+
+// Whether a declared type has numeric affinity, in which case empty CSV fields
+// are stored as NULL rather than as empty strings.
+fn is_numeric_type(declared_type: &str) -> bool {
+    let declared_type = declared_type.to_uppercase();
+    ["INT", "REAL", "FLOA", "DOUB", "NUM", "DEC", "BOOL"].iter().any(|t| declared_type.contains(t))
+}
+
+// The first record is taken to be a header when it matches the columns of the
+// target table or, for a new table, when it consists of distinct, non-empty,
+// non-numeric names.
+fn looks_like_header(record: &csv::StringRecord, existing_columns: &[(String, String)]) -> bool {
+    if !existing_columns.is_empty() {
+        return record.len() == existing_columns.len()
+            && record.iter().zip(existing_columns).all(|(field, (column, _))| field.trim().eq_ignore_ascii_case(column));
+    }
+
+    let mut names: Vec<String> = record.iter().map(|field| field.trim().to_lowercase()).collect();
+    let all_names = names.iter().all(|name| !name.is_empty() && name.parse::<f64>().is_err());
+    names.sort();
+    names.dedup();
+    all_names && names.len() == record.len()
+}
+
+// Reads the whole file once to infer the narrowest type of every column.
+fn infer_column_types(path: &Path, column_count: usize, skip_header: bool) -> Result<Vec<ColumnType>> {
+    let mut types = vec![ColumnType::Integer; column_count];
+
+    for record in csv_reader(path)?.records().skip(usize::from(skip_header)) {
+        let record = record?;
+        for (column_type, field) in types.iter_mut().zip(record.iter()) {
+            *column_type = column_type.widen(field);
+        }
+    }
+    Ok(types)
+}
+
+async fn insert_batch(
+    conn: &mut sqlx::SqliteConnection,
+    table: &str,
+    on_conflict: OnConflict,
+    empty_as_null: &[bool],
+    batch: &[csv::StringRecord],
+) -> Result<u64> {
+    let placeholders = format!("({})", vec!["?"; empty_as_null.len()].join(", "));
+    let sql = format!(
+        "{} INTO {} VALUES {};",
+        on_conflict.insert(),
+        quote_identifier(table),
+        vec![placeholders; batch.len()].join(", ")
+    );
+
+    let mut query = sqlx::query(&sql);
+    for record in batch {
+        for (field, empty_as_null) in record.iter().zip(empty_as_null) {
+            if field.is_empty() && *empty_as_null {
+                query = query.bind(None::<String>);
+            } else {
+                query = query.bind(field.to_string());
+            }
+        }
+    }
+    Ok(query.execute(conn).await?.rows_affected())
+}
+
+/// Imports a CSV file into `table`, creating the table from the inferred
+/// column types if it does not exist yet. `header` forces whether the first
+/// line is a header; by default it is detected. The table is created and all
+/// rows are inserted under a savepoint, so a failed import changes nothing,
+/// also when a transaction is already open. `on_conflict` decides what happens
+/// to rows that violate a constraint.
+pub async fn import_csv(conn: &mut SqliteConnection, path: &Path, table: &str, header: Option<bool>, on_conflict: OnConflict) -> Result<ImportCount> {
+    sqlx::query("SAVEPOINT import_csv;").execute(&mut *conn).await?;
+
+    match import_records(conn, path, table, header, on_conflict).await {
+        Ok(imported) => {
+            sqlx::query("RELEASE import_csv;").execute(&mut *conn).await?;
+            Ok(imported)
+        },
+        Err(e) => {
+            sqlx::query("ROLLBACK TO import_csv;").execute(&mut *conn).await?;
+            sqlx::query("RELEASE import_csv;").execute(&mut *conn).await?;
+            Err(e)
+        },
+    }
+}
+
+async fn import_records(conn: &mut SqliteConnection, path: &Path, table: &str, header: Option<bool>, on_conflict: OnConflict) -> Result<ImportCount> {
+    let existing_columns = table_columns(conn, table).await?;
+    let mut records = csv_reader(path)?.into_records();
+
+    let first = match records.next() {
+        Some(record) => record?,
+        None => return Err(GalvanizeError::Parse(format!("'{}' is empty.", path.display()))),
+    };
+    let has_header = header.unwrap_or_else(|| looks_like_header(&first, &existing_columns));
+    let column_count = first.len();
+    let empty_as_null: Vec<bool>;
+
+    if existing_columns.is_empty() {
+        let names: Vec<String> = if has_header {
+            first.iter().map(|name| name.trim().to_string()).collect()
+        } else {
+            (1..=column_count).map(|i| format!("column{}", i)).collect()
+        };
+        let types = infer_column_types(path, column_count, has_header)?;
+        let definitions: Vec<String> = names
+            .iter()
+            .zip(&types)
+            .map(|(name, column_type)| format!("{} {}", quote_identifier(name), column_type.name()))
+            .collect();
+        let create = format!("CREATE TABLE {} ({});", quote_identifier(table), definitions.join(", "));
+        sqlx::query(&create).execute(&mut *conn).await?;
+        empty_as_null = types.iter().map(|column_type| *column_type != ColumnType::Text).collect();
+    } else if column_count != existing_columns.len() {
+        return Err(GalvanizeError::Parse(format!(
+            "'{}' has {} columns but table '{}' has {}.",
+            path.display(), column_count, table, existing_columns.len()
+        )));
+    } else {
+        empty_as_null = existing_columns.iter().map(|(_, declared_type)| is_numeric_type(declared_type)).collect();
+    }
+
+    let batch_rows = (MAX_BOUND_PARAMETERS / column_count.max(1)).clamp(1, MAX_BATCH_ROWS);
+    let mut batch = Vec::with_capacity(batch_rows);
+    let mut imported = ImportCount::default();
+
+    if !has_header {
+        batch.push(first);
+    }
+
+    for record in records {
+        let record = record?;
+        if record.len() != column_count {
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            return Err(GalvanizeError::Parse(format!("line {}: expected {} fields but found {}.", line, column_count, record.len())));
+        }
+
+        batch.push(record);
+        if batch.len() == batch_rows {
+            let inserted = insert_batch(conn, table, on_conflict, &empty_as_null, &batch).await?;
+            imported.add(batch.len(), inserted);
+            batch.clear();
+        }
+    }
+
+    if !batch.is_empty() {
+        let inserted = insert_batch(conn, table, on_conflict, &empty_as_null, &batch).await?;
+        imported.add(batch.len(), inserted);
+    }
+
+    Ok(imported)
+}
+
+// Reads a JSON file holding either an array of objects or one object per line
+// (NDJSON).
+fn read_json_records(path: &Path) -> Result<Vec<serde_json::Map<String, serde_json::Value>>> {
+    let text = std::fs::read_to_string(path).map_err(|e| GalvanizeError::Io(format!("Error opening '{}': {}", path.display(), e)))?;
+    let values: Vec<serde_json::Value> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(&text).map_err(|e| GalvanizeError::Parse(e.to_string()))?
+    } else {
+        serde_json::Deserializer::from_str(&text)
+            .into_iter()
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|e| GalvanizeError::Parse(e.to_string()))?
+    };
+
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| match value {
+            serde_json::Value::Object(record) => Ok(record),
+            _ => Err(GalvanizeError::Parse(format!("record {} is not a JSON object.", i + 1))),
+        })
+        .collect()
+}
+
+// The SQL column type for a JSON value, or None for null, which fits any type.
+fn json_column_type(value: &serde_json::Value) -> Option<ColumnType> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(_) => Some(ColumnType::Integer),
+        serde_json::Value::Number(number) if number.is_i64() => Some(ColumnType::Integer),
+        serde_json::Value::Number(_) => Some(ColumnType::Real),
+        _ => Some(ColumnType::Text),
+    }
+}
+
+async fn insert_json_batch(
+    conn: &mut SqliteConnection,
+    table: &str,
+    on_conflict: OnConflict,
+    columns: &[String],
+    batch: &[serde_json::Map<String, serde_json::Value>],
+) -> Result<u64> {
+    let placeholders = format!("({})", vec!["?"; columns.len()].join(", "));
+    let sql = format!(
+        "{} INTO {} ({}) VALUES {};",
+        on_conflict.insert(),
+        quote_identifier(table),
+        columns.iter().map(|column| quote_identifier(column)).collect::<Vec<_>>().join(", "),
+        vec![placeholders; batch.len()].join(", ")
+    );
+
+    let mut query = sqlx::query(&sql);
+    for record in batch {
+        for column in columns {
+            query = match record.get(column) {
+                None | Some(serde_json::Value::Null) => query.bind(None::<String>),
+                Some(serde_json::Value::Bool(value)) => query.bind(*value),
+                Some(serde_json::Value::Number(number)) => match number.as_i64() {
+                    Some(value) => query.bind(value),
+                    None => query.bind(number.as_f64()),
+                },
+                Some(serde_json::Value::String(value)) => query.bind(value.clone()),
+                Some(value) => query.bind(value.to_string()),
+            };
+        }
+    }
+    Ok(query.execute(conn).await?.rows_affected())
+}
+
+/// Imports a JSON file, either an array of objects or newline-delimited
+/// objects, into `table`. Each key is a column; if the table does not exist
+/// it is created with every key that appears in the file, typed from the
+/// values. Like [`import_csv`], the import happens under a savepoint and
+/// `on_conflict` decides what happens to rows that violate a constraint.
+pub async fn import_json(conn: &mut SqliteConnection, path: &Path, table: &str, on_conflict: OnConflict) -> Result<ImportCount> {
+    sqlx::query("SAVEPOINT import_json;").execute(&mut *conn).await?;
+
+    match import_json_records(conn, path, table, on_conflict).await {
+        Ok(imported) => {
+            sqlx::query("RELEASE import_json;").execute(&mut *conn).await?;
+            Ok(imported)
+        },
+        Err(e) => {
+            sqlx::query("ROLLBACK TO import_json;").execute(&mut *conn).await?;
+            sqlx::query("RELEASE import_json;").execute(&mut *conn).await?;
+            Err(e)
+        },
+    }
+}
+
+async fn import_json_records(conn: &mut SqliteConnection, path: &Path, table: &str, on_conflict: OnConflict) -> Result<ImportCount> {
+    let records = read_json_records(path)?;
+    if records.is_empty() {
+        return Err(GalvanizeError::Parse(format!("'{}' has no records.", path.display())));
+    }
+
+    // Columns in the order their keys first appear, with the type that fits
+    // all of their values.
+    let mut columns: Vec<(String, Option<ColumnType>)> = Vec::new();
+    for record in &records {
+        for (key, value) in record {
+            let value_type = json_column_type(value);
+            match columns.iter_mut().find(|(column, _)| column == key) {
+                Some((_, column_type)) => {
+                    *column_type = match (*column_type, value_type) {
+                        (Some(a), Some(b)) if a == b => Some(a),
+                        (Some(ColumnType::Text), _) | (_, Some(ColumnType::Text)) => Some(ColumnType::Text),
+                        (Some(_), Some(_)) => Some(ColumnType::Real),
+                        (column_type, None) | (None, column_type) => column_type,
+                    };
+                },
+                None => columns.push((key.clone(), value_type)),
+            }
+        }
+    }
+
+    let existing_columns = table_columns(conn, table).await?;
+    if existing_columns.is_empty() {
+        let definitions: Vec<String> = columns
+            .iter()
+            .map(|(name, column_type)| format!("{} {}", quote_identifier(name), column_type.unwrap_or(ColumnType::Text).name()))
+            .collect();
+        let create = format!("CREATE TABLE {} ({});", quote_identifier(table), definitions.join(", "));
+        sqlx::query(&create).execute(&mut *conn).await?;
+    } else if let Some((key, _)) = columns.iter().find(|(key, _)| !existing_columns.iter().any(|(column, _)| column.eq_ignore_ascii_case(key))) {
+        return Err(GalvanizeError::Parse(format!("'{}' has the key '{}', but table '{}' has no such column.", path.display(), key, table)));
+    }
+
+    let columns: Vec<String> = columns.into_iter().map(|(name, _)| name).collect();
+    let batch_rows = (MAX_BOUND_PARAMETERS / columns.len().max(1)).clamp(1, MAX_BATCH_ROWS);
+    let mut imported = ImportCount::default();
+    for batch in records.chunks(batch_rows) {
+        let inserted = insert_json_batch(conn, table, on_conflict, &columns, batch).await?;
+        imported.add(batch.len(), inserted);
+    }
+    Ok(imported)
+}
@@ -0,0 +1,221 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use futures_util::TryStreamExt;
+use sqlx::{Column, Row};
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::{Sqlite, SqliteConnection};
+use tokio::task::JoinHandle;
+use tracing::info;
+use crate::audit::AuditLog;
+use crate::error::{GalvanizeError, Result};
+use crate::output::{Value, row_values};
+use crate::returns_rows;
+use crate::session::{PROGRESS_HANDLER_OPS, QueryOutcome, QueryResult, bind_values, in_transaction};
+
+/// Where a background job stands.
+#[derive(Clone)]
+pub enum JobStatus {
+    Running,
+    Done(QueryResult),
+    Failed(String),
+    Killed,
+}
+
+impl JobStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobStatus::Running => "running",
+            JobStatus::Done(_) => "done",
+            JobStatus::Failed(_) => "failed",
+            JobStatus::Killed => "killed",
+        }
+    }
+}
+
+struct JobState {
+    status: JobStatus,
+    // How long the statement took, once it has finished.
+    elapsed: Option<Duration>,
+}
+
+/// A statement running, or run, in the background on a connection of its own.
+pub struct Job {
+    pub id: usize,
+    pub sql: String,
+    started: Instant,
+    state: Arc<Mutex<JobState>>,
+    killed: Arc<AtomicBool>,
+    // None once the task has been awaited.
+    handle: Option<JoinHandle<()>>,
+    // Whether the user has been told the job finished.
+    reported: bool,
+}
+
+impl Job {
+    pub fn status(&self) -> JobStatus {
+        self.state.lock().unwrap().status.clone()
+    }
+
+    /// How long the job ran, or has been running.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed.unwrap_or_else(|| self.started.elapsed())
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(self.state.lock().unwrap().status, JobStatus::Running)
+    }
+}
+
+/// The background jobs of a session, numbered from 1.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+}
+
+// Runs the statement and keeps its whole result.
+async fn run(conn: &mut SqliteConnection, sql: &str, params: &[Value]) -> Result<QueryResult> {
+    if returns_rows(sql) {
+        let mut rows = bind_values(sqlx::query(sql), params).fetch(&mut *conn);
+        let mut result = QueryResult::default();
+        while let Some(row) = rows.try_next().await? {
+            // Column names are taken from the first row; an empty result has none.
+            if result.columns.is_empty() {
+                result.columns = row.columns().iter().map(|col| col.name().to_string()).collect();
+            }
+            result.rows.push(row_values(&row));
+        }
+        Ok(result)
+    } else {
+        let done = bind_values(sqlx::query(sql), params).execute(&mut *conn).await?;
+        Ok(QueryResult {
+            rows_affected: done.rows_affected(),
+            last_insert_rowid: Some(done.last_insert_rowid()),
+            ..QueryResult::default()
+        })
+    }
+}
+
+impl Jobs {
+    /// Starts running `sql` on `conn` in the background, and returns the
+    /// number of the new job. The statement is recorded in `audit_log`, if
+    /// given, once it finishes.
+    pub fn spawn(&mut self, mut conn: PoolConnection<Sqlite>, database: &str, sql: &str, params: Vec<Value>, audit_log: Option<AuditLog>) -> usize {
+        let id = self.jobs.last().map_or(1, |job| job.id + 1);
+        let state = Arc::new(Mutex::new(JobState { status: JobStatus::Running, elapsed: None }));
+        let killed = Arc::new(AtomicBool::new(false));
+        let started = Instant::now();
+        info!(job = id, sql, "Starting background job.");
+
+        let task = {
+            let (state, killed, sql, database) = (Arc::clone(&state), Arc::clone(&killed), sql.to_string(), database.to_string());
+            async move {
+                // KILL makes the progress handler fail the statement.
+                if let Ok(mut handle) = conn.lock_handle().await {
+                    let killed = Arc::clone(&killed);
+                    handle.set_progress_handler(PROGRESS_HANDLER_OPS, move || !killed.load(Ordering::Relaxed));
+                }
+                let mut result = run(&mut conn, &sql, &params).await;
+                if let Ok(mut handle) = conn.lock_handle().await {
+                    handle.remove_progress_handler();
+                }
+                // The connection goes back to the pool, where an open
+                // transaction would catch the statements of others.
+                if in_transaction(&mut conn).await {
+                    let _ = sqlx::query("ROLLBACK;").execute(&mut *conn).await;
+                    result = Err(GalvanizeError::Execution(
+                        "Background statements cannot leave a transaction open; it was rolled back.".to_string(),
+                    ));
+                }
+                if let Some(audit_log) = audit_log {
+                    let outcome = result.as_ref().map(|result| {
+                        if returns_rows(&sql) {
+                            QueryOutcome::Rows(result.rows.len() as u64)
+                        } else {
+                            QueryOutcome::Changes { rows_affected: result.rows_affected, last_insert_rowid: result.last_insert_rowid }
+                        }
+                    });
+                    audit_log.record(&database, &sql, &params, started.elapsed(), outcome);
+                }
+
+                let status = match result {
+                    Ok(result) => JobStatus::Done(result),
+                    Err(_) if killed.load(Ordering::Relaxed) => JobStatus::Killed,
+                    Err(e) => JobStatus::Failed(e.to_string()),
+                };
+                info!(job = id, status = status.name(), elapsed_ms = started.elapsed().as_secs_f64() * 1000.0, "Background job finished.");
+                *state.lock().unwrap() = JobState { status, elapsed: Some(started.elapsed()) };
+            }
+        };
+
+        self.jobs.push(Job {
+            id,
+            sql: sql.to_string(),
+            started,
+            state,
+            killed,
+            handle: Some(tokio::spawn(task)),
+            reported: false,
+        });
+        id
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    fn get_mut(&mut self, id: usize) -> Result<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id).ok_or_else(|| GalvanizeError::Execution(format!("There is no job {}.", id)))
+    }
+
+    /// Stops the statement of a running job.
+    pub fn kill(&mut self, id: usize) -> Result<()> {
+        let job = self.get_mut(id)?;
+        if !job.is_running() {
+            return Err(GalvanizeError::Execution(format!("Job {} has already finished.", id)));
+        }
+        job.killed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Waits for a job to finish, or until Ctrl+C is pressed, and returns
+    /// its status then.
+    pub async fn wait(&mut self, id: usize) -> Result<JobStatus> {
+        let job = self.get_mut(id)?;
+        if let Some(handle) = job.handle.as_mut() {
+            tokio::select! {
+                _ = handle => job.handle = None,
+                Ok(()) = tokio::signal::ctrl_c() => {},
+            }
+        }
+        job.reported = true;
+        Ok(job.status())
+    }
+
+    /// The numbers of the jobs that are still running.
+    pub fn running(&self) -> Vec<usize> {
+        self.jobs.iter().filter(|job| job.is_running()).map(|job| job.id).collect()
+    }
+
+    /// The jobs that have finished since this was last asked, for telling the
+    /// user about them.
+    pub fn newly_finished(&mut self) -> Vec<&Job> {
+        let mut finished = Vec::new();
+        for job in self.jobs.iter_mut().filter(|job| !job.reported && !job.is_running()) {
+            job.reported = true;
+            finished.push(&*job);
+        }
+        finished
+    }
+
+    /// Kills every running job and waits for them to stop, so their
+    /// connections are back in the pool.
+    pub async fn kill_all(&mut self) {
+        for job in &mut self.jobs {
+            job.killed.store(true, Ordering::Relaxed);
+            if let Some(handle) = job.handle.take() {
+                let _ = handle.await;
+            }
+        }
+    }
+}
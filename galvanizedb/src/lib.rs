@@ -0,0 +1,102 @@
+//! The engine behind the GalvanizeDB shell. A [`Session`] connects to an
+//! SQLite database or a PostgreSQL server and runs statements, either
+//! returning the whole [`QueryResult`] or streaming rows to a
+//! [`ResultRenderer`]. The interactive shell itself lives in [`shell`].
+
+// The output macros are used throughout, so they come first.
+#[macro_use]
+mod tee;
+pub mod audit;
+mod bookmarks;
+mod browse;
+mod completion;
+mod compression;
+mod diff;
+pub mod config;
+pub mod dump;
+pub mod error;
+mod explore;
+mod functions;
+mod generate;
+pub mod import;
+mod jobs;
+mod meta;
+mod migrate;
+pub mod output;
+mod pager;
+pub mod postgres;
+mod queries;
+mod recent;
+mod resume;
+pub mod remote;
+mod script;
+mod search;
+pub mod server;
+pub mod session;
+pub mod settings;
+pub mod shell;
+pub mod theme;
+
+use std::path::PathBuf;
+
+pub use error::GalvanizeError;
+pub use output::Value;
+pub use session::{FormatRenderer, QueryOutcome, QueryResult, ResultRenderer, Session};
+pub use settings::Settings;
+use script::returns_rows;
+
+/// The database name that opens an empty in-memory database.
+pub const MEMORY_DATABASE: &str = ":memory:";
+
+// Expands a leading `~` in a path to the home directory, and `$NAME` or
+// `${NAME}` to the value of the environment variable. Unset variables are
+// left as they are. On Windows, `/` separators become `\`.
+fn expand_path(path: &str) -> PathBuf {
+    let mut expanded = String::new();
+    let mut rest = path;
+
+    if let Some(after) = path.strip_prefix('~').filter(|after| after.is_empty() || after.starts_with(['/', '\\'])) {
+        if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+            expanded.push_str(&home.to_string_lossy());
+            rest = after;
+        }
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => braced.find('}').map_or(("", 0), |end| (&braced[..end], end + 2)),
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], end)
+            },
+        };
+        match std::env::var(name).ok().filter(|_| !name.is_empty()) {
+            Some(value) => {
+                expanded.push_str(&value);
+                rest = &after[len..];
+            },
+            None => {
+                expanded.push('$');
+                rest = after;
+            },
+        }
+    }
+    expanded.push_str(rest);
+
+    if cfg!(windows) {
+        expanded = expanded.replace('/', "\\");
+    }
+    PathBuf::from(expanded)
+}
+
+// Quotes a value as an SQL string literal.
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+// Quotes a name as an SQL identifier.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
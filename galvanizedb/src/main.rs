@@ -1,256 +1,628 @@
-use std::path::Path;
-use sqlx::{Column, Result, Row, TypeInfo};
-use sqlx::sqlite::SqlitePool;
-use rustyline::Editor;
-use rustyline::config::Config;
-use rustyline::error::ReadlineError;
-use rustyline::history::MemHistory;
-
-fn extract_db_name(input: &str) -> Option<String> {
-    let parts: Vec<&str> = input.split_whitespace().collect();
-
-    if parts.len() >= 2 {
-        let command = parts[0].to_uppercase();
-
-        if command == "USE" && parts.len() == 2 {
-            let database_name = parts[1].strip_suffix(';').unwrap_or(parts[1]);
-            Some(format_db_name(database_name))
-        } else if (command == "CREATE" || command == "DROP") && parts.len() >= 3 && parts[1].eq_ignore_ascii_case("database") {
-            let database_name = parts[2].strip_suffix(';').unwrap_or(parts[2]);
-            Some(format_db_name(database_name))
-        } else {
-            None
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use clap::{Args, Parser, Subcommand};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+use galvanizedb::audit::AuditLog;
+use galvanizedb::config::{self, ConfigFile};
+use galvanizedb::error::ErrorLocation;
+use galvanizedb::output::OutputFormat;
+use galvanizedb::remote;
+use galvanizedb::server::{self, ServeOptions};
+use galvanizedb::settings::apply_setting;
+use galvanizedb::dump::DumpOptions;
+use galvanizedb::import::OnConflict;
+use galvanizedb::shell::{OnError, Shell, Task, format_db_name, run_batch, run_repl};
+use galvanizedb::theme::color_by_default;
+use galvanizedb::{GalvanizeError, MEMORY_DATABASE, Settings, postgres};
+
+const DEFAULT_HISTORY_FILE: &str = ".galvanizedb_history";
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+// Whether errors that end the program are reported as JSON.
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Command line database interface",
+    after_help = "Exit status: 0 on success, 1 if a statement failed, 2 for an invalid command or setting, \
+        3 if a database could not be opened, 4 for a file error and 5 for a command the database does not support."
+)]
+struct Cli {
+    // Without a subcommand, the arguments of `shell`.
+    #[command(flatten)]
+    shell: ShellArgs,
+
+    #[command(flatten)]
+    options: GlobalOptions,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+// Options every subcommand takes.
+#[derive(Args)]
+struct GlobalOptions {
+    /// Output format for query results [default: table]
+    #[arg(long, value_enum, global = true)]
+    format: Option<OutputFormat>,
+
+    /// Text shown in place of NULL values [default: NULL]
+    #[arg(long = "null", global = true)]
+    null_value: Option<String>,
+
+    /// Directory where database names are looked up and created [default: current directory]
+    #[arg(long, value_name = "DIR", global = true)]
+    data_dir: Option<String>,
+
+    /// Do not color output (also: the NO_COLOR environment variable)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Leave out the banner and messages about what commands did, so that only results are printed
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Report errors on stderr as JSON lines with their kind, exit status, message, and the
+    /// statement, line and column they came from
+    #[arg(long, global = true)]
+    json_errors: bool,
+
+    /// Configuration file to read instead of ~/.config/galvanizedb/config.toml
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// Append every statement, with its time, duration and outcome, to FILE as JSON lines
+    #[arg(long, value_name = "FILE", global = true)]
+    audit_log: Option<PathBuf>,
+
+    /// Log connections, statements and errors to stderr; -vv adds connection pool and driver
+    /// details, -vvv everything
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Write the log to FILE instead of stderr, at the -v level unless more is asked for
+    #[arg(long, value_name = "FILE", global = true)]
+    log_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ShellArgs {
+    /// Database file, or postgres:// URL, to connect to on startup
+    database: Option<String>,
+
+    /// Start with an empty in-memory database
+    #[arg(long, conflicts_with = "database")]
+    memory: bool,
+
+    /// Create the database file if it does not exist
+    #[arg(long)]
+    create: bool,
+
+    /// Return to the database, attachments, settings and variables saved with \save-session
+    /// NAME [default: default]
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "default", conflicts_with_all = ["database", "memory"])]
+    resume: Option<String>,
+
+    /// Execute the given SQL and exit
+    #[arg(short = 'e', long = "execute")]
+    execute: Option<String>,
+
+    /// Execute the SQL script in FILE and exit
+    #[arg(short = 'f', long = "file", value_name = "FILE", conflicts_with = "execute")]
+    file: Option<PathBuf>,
+
+    /// Run the script (-e, -f or stdin) in one transaction, rolled back if any statement fails
+    #[arg(long)]
+    single_transaction: bool,
+
+    /// What to do when a statement of the script fails
+    #[arg(long, value_enum, default_value = "stop")]
+    on_error: OnError,
+}
+
+impl ShellArgs {
+    // Connects to `database`, for a task to run against it.
+    fn connect(database: String, create: bool) -> Self {
+        ShellArgs {
+            database: Some(database),
+            memory: false,
+            create,
+            resume: None,
+            execute: None,
+            file: None,
+            single_transaction: false,
+            on_error: OnError::Stop,
         }
-    } else {
-        None
+    }
+
+    // Runs `script` against `database` and exits, as -e does.
+    fn script(database: String, create: bool, script: String) -> Self {
+        ShellArgs { execute: Some(script), ..ShellArgs::connect(database, create) }
     }
 }
 
-fn format_db_name(name: &str) -> String {
-    let mut formatted_name = name.to_string();
+#[derive(Subcommand)]
+enum Command {
+    /// Open the interactive shell, or run a script with -e, -f or from stdin (the default
+    /// without a subcommand)
+    Shell(ShellArgs),
+    /// Run SQL, or shell commands, against a database and print the results
+    Query(QueryArgs),
+    /// Import a CSV or JSON file into a table
+    Import(ImportArgs),
+    /// Write all rows of a table to a file, in the format its extension names
+    Export(ExportArgs),
+    /// Write the database out as an SQL script
+    Dump(DumpArgs),
+    /// Apply, revert, list or create migrations in the migrations/ directory
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Serve a database over HTTP: POST /query runs a statement sent as JSON, GET /tables and
+    /// GET /schema describe the database, and GET /shell takes `connect` clients when a token is required
+    Serve(ServeArgs),
+    /// Open a shell on a database served elsewhere with `serve`
+    Connect(ConnectArgs),
+    /// Browse and query a database in a web browser, served on localhost
+    Web(WebArgs),
+}
 
-    if !formatted_name.ends_with(".db") {
-        formatted_name.push_str(".db");
-    }
+#[derive(Args)]
+struct QueryArgs {
+    /// Database file, or postgres:// URL
+    database: String,
+
+    /// Statements to run, separated by semicolons
+    sql: String,
+
+    /// Run the statements in one transaction, rolled back if any fails
+    #[arg(long)]
+    single_transaction: bool,
+
+    /// What to do when a statement fails
+    #[arg(long, value_enum, default_value = "stop")]
+    on_error: OnError,
 
-    formatted_name
+    /// Create the database file if it does not exist
+    #[arg(long)]
+    create: bool,
 }
 
-fn db_file_check(db_file_name: &str) -> bool {
-    let path = Path::new(&db_file_name);
+#[derive(Args)]
+struct ImportArgs {
+    /// Database file, or postgres:// URL
+    database: String,
 
-    if path.exists(){
-        return true;
-    }
-    return false;
+    /// CSV or JSON file to import
+    file: String,
+
+    /// Table to import into, created from the file if missing [default: the file's name]
+    #[arg(long)]
+    table: Option<String>,
+
+    /// Whether the file is CSV or JSON [default: from its extension]
+    #[arg(long, value_parser = ["csv", "json"])]
+    format: Option<String>,
+
+    /// Whether the first row of a CSV file is a header [default: guessed]
+    #[arg(long, value_parser = ["yes", "no"])]
+    header: Option<String>,
+
+    /// What to do with rows that conflict with rows already in the table
+    #[arg(long, value_enum, default_value = "abort")]
+    on_conflict: OnConflict,
+
+    /// Create the database file if it does not exist
+    #[arg(long)]
+    create: bool,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Database file, or postgres:// URL
+    database: String,
+
+    /// Table to export
+    table: String,
+
+    /// File to write: .csv, .tsv, .json, .md, .html or .parquet, optionally with .gz or .zst
+    file: String,
+}
+
+#[derive(Args)]
+struct DumpArgs {
+    /// Database file
+    database: String,
+
+    /// Tables to dump, as names or LIKE patterns [default: all]
+    tables: Vec<String>,
+
+    /// Leave out these tables (names or LIKE patterns)
+    #[arg(long, value_name = "TABLE")]
+    exclude: Vec<String>,
+
+    /// Dump only the CREATE statements
+    #[arg(long, conflicts_with = "data_only")]
+    schema_only: bool,
+
+    /// Dump only the rows
+    #[arg(long)]
+    data_only: bool,
+
+    /// Write the script to FILE, compressed if it ends in .gz or .zst, instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
 }
 
-fn help() {
-    println!(
-        "\nGalvanizeDB Basic Manual\n\
-        ---------------------------\n\
-        Create a database:\n    CREATE DATABASE database_name;\n\n\
-        Connect to a database:\n    USE database_name;\n\n\
-        List tables in a database:\n    SHOW TABLES;\n\n\
-        Close connection to a database:\n    DROP SCHEMA database_name;\n\n\
-        When connected to a database, use standard SQLite queries to interact with the database.\n\n\
-        Type 'exit' to close GalvanizeDB CLI.\n\n\
-        Report issues at: https://github.com/SlavicPixel/galvanizedb\n"
-    );
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// Apply the migrations not applied yet
+    Up {
+        /// Database file
+        database: String,
+    },
+    /// Revert the last migrations applied
+    Down {
+        /// Database file
+        database: String,
+
+        /// How many migrations to revert
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+    },
+    /// List the migrations and whether each is applied
+    Status {
+        /// Database file
+        database: String,
+    },
+    /// Create the .up.sql and .down.sql files of a new migration
+    New {
+        /// Name of the migration, such as create_users
+        name: String,
+    },
 }
 
+#[derive(Args)]
+struct ServeArgs {
+    /// Database file, or postgres:// URL, to serve
+    #[arg(long, value_name = "DATABASE")]
+    db: String,
+
+    /// Address and port to listen on
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
 
-async fn create_or_connect_database(db_name: &str) -> Result<SqlitePool, sqlx::Error> {
-    let database_url: String = format!("sqlite:{}?mode=rwc", db_name);
-    let pool = SqlitePool::connect(&database_url).await?;
-    Ok(pool)
+    /// Require this token as `Authorization: Bearer TOKEN` (also: the GALVANIZEDB_TOKEN variable)
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Refuse statements that change the database, and remote shells
+    #[arg(long)]
+    read_only: bool,
 }
 
-async fn execute_sql(pool: &SqlitePool, sql: &str) -> anyhow::Result<()> {
-    if sql.trim().to_lowercase().starts_with("select") {
-        let rows = sqlx::query(sql).fetch_all(pool).await?;
+#[derive(Args)]
+struct WebArgs {
+    /// Database file, or postgres:// URL, to open
+    database: String,
 
-        if rows.is_empty() {
-            println!("No results found.");
-            return Ok(());
-        }
+    /// Port to listen on, at 127.0.0.1
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
 
-        let columns = rows[0].columns();
-        let mut column_widths: Vec<usize> = columns.iter().map(|col| col.name().len()).collect();
-
-        for row in &rows {
-            for (i, col) in columns.iter().enumerate() {
-                let length = match col.type_info().name() {
-                    "TEXT" => row.try_get::<String, _>(col.name()).map(|v| v.len()).unwrap_or(0),
-                    "INTEGER" => row.try_get::<i64, _>(col.name()).map(|v| v.to_string().len()).unwrap_or(0),
-                    _ => "Unsupported type".len(),
-                };
-                column_widths[i] = std::cmp::max(column_widths[i], length);
-            }
+    /// Refuse statements that change the database
+    #[arg(long)]
+    read_only: bool,
+}
+
+#[derive(Args)]
+struct ConnectArgs {
+    /// Address of the server, such as ws://host:8080
+    url: String,
+
+    /// Token the server requires (also: the GALVANIZEDB_TOKEN variable)
+    #[arg(long)]
+    token: Option<String>,
+}
+
+fn token(token: Option<String>) -> Option<String> {
+    token.or_else(|| std::env::var("GALVANIZEDB_TOKEN").ok().filter(|token| !token.is_empty()))
+}
+
+// GALVANIZEDB_HISTFILE overrides the history location from the config file;
+// setting either to an empty string disables persistent history altogether.
+fn history_file(config: &ConfigFile) -> Option<PathBuf> {
+    match std::env::var_os("GALVANIZEDB_HISTFILE").map(PathBuf::from).or_else(|| config.history_file.clone()) {
+        Some(path) if path.as_os_str().is_empty() => None,
+        Some(path) => Some(path),
+        None => std::env::var_os("HOME").map(|home| Path::new(&home).join(DEFAULT_HISTORY_FILE)),
+    }
+}
+
+fn history_size(config: &ConfigFile) -> usize {
+    std::env::var("GALVANIZEDB_HISTSIZE")
+        .ok()
+        .and_then(|size| size.trim().parse().ok())
+        .or(config.history_size)
+        .unwrap_or(DEFAULT_HISTORY_SIZE)
+}
+
+// Sets up logging at the level asked for with -v. Without -v or a log file
+// nothing is logged, so the log never mixes with normal output unasked.
+fn init_logging(verbose: u8, log_file: Option<&Path>) -> Result<(), GalvanizeError> {
+    let verbose = if log_file.is_some() { verbose.max(1) } else { verbose };
+    let targets = match verbose {
+        0 => return Ok(()),
+        1 => Targets::new().with_default(LevelFilter::WARN).with_target("galvanizedb", LevelFilter::INFO),
+        2 => Targets::new()
+            .with_default(LevelFilter::WARN)
+            .with_target("galvanizedb", LevelFilter::DEBUG)
+            .with_target("sqlx", LevelFilter::DEBUG),
+        _ => Targets::new().with_default(LevelFilter::TRACE),
+    };
+
+    let layer = tracing_subscriber::fmt::layer();
+    match log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| GalvanizeError::Io(format!("Error opening log file '{}': {}", path.display(), e)))?;
+            let layer = layer.with_ansi(false).with_writer(std::sync::Mutex::new(file));
+            tracing_subscriber::registry().with(layer.with_filter(targets)).init();
+        },
+        None => {
+            let layer = layer.with_writer(std::io::stderr);
+            tracing_subscriber::registry().with(layer.with_filter(targets)).init();
+        },
+    }
+    Ok(())
+}
+
+// The import, export or dump that a subcommand asks for.
+impl ImportArgs {
+    fn task(&self) -> Task {
+        let json = match self.format.as_deref() {
+            Some(format) => format == "json",
+            None => [".json", ".jsonl", ".ndjson"].iter().any(|extension| self.file.to_lowercase().ends_with(extension)),
+        };
+        let table = self.table.clone().unwrap_or_else(|| {
+            let stem = Path::new(&self.file).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            stem.split('.').next().unwrap_or_default().to_string()
+        });
+        Task::Import {
+            path: self.file.clone(),
+            table,
+            json,
+            header: self.header.as_deref().map(|header| header == "yes"),
+            on_conflict: self.on_conflict,
         }
+    }
+}
 
-        // Print horizontal line
-        let create_line = |widths: &[usize]| {
-            widths
-                .iter()
-                .map(|w| "-".repeat(*w + 2))
-                .collect::<Vec<_>>()
-                .join("+")
+impl DumpArgs {
+    fn task(&self) -> Task {
+        let options = DumpOptions {
+            schema: !self.data_only,
+            data: !self.schema_only,
+            tables: self.tables.clone(),
+            exclude: self.exclude.clone(),
         };
+        Task::Dump { options, path: self.output.clone() }
+    }
+}
+
+// Reports an error that ends the program, with the exit status for its kind.
+fn exit_with(e: GalvanizeError) -> ! {
+    exit_at(e, None)
+}
 
-        // Print top border
-        println!("+{}+", create_line(&column_widths));
+// The same, for an error in a statement of a script, at `location`.
+fn exit_at(e: GalvanizeError, location: Option<&ErrorLocation>) -> ! {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        eprintln!("{}", e.to_json(location));
+    } else {
+        eprintln!("{}", e);
+    }
+    std::process::exit(e.exit_code());
+}
 
-        // Print header row
-        for (i, col) in columns.iter().enumerate() {
-            print!("| {:width$} ", col.name(), width = column_widths[i]);
+#[tokio::main]
+async fn main() {
+    let Cli { shell: args, options, command } = Cli::parse();
+    JSON_ERRORS.store(options.json_errors, Ordering::Relaxed);
+    if let Err(e) = init_logging(options.verbose, options.log_file.as_deref()) {
+        exit_with(e);
+    }
+
+    // Most subcommands come down to a script or a task for the shell; the
+    // rest run a server or a client instead.
+    let mut server = None;
+    let mut task = None;
+    let args = match command {
+        None => args,
+        Some(Command::Shell(args)) => args,
+        Some(Command::Query(query)) => ShellArgs {
+            single_transaction: query.single_transaction,
+            on_error: query.on_error,
+            ..ShellArgs::script(query.database, query.create, query.sql)
+        },
+        Some(Command::Import(import)) => {
+            task = Some(import.task());
+            ShellArgs::connect(import.database, import.create)
+        },
+        Some(Command::Export(export)) => {
+            task = Some(Task::Export { table: export.table, path: export.file });
+            ShellArgs::connect(export.database, false)
+        },
+        Some(Command::Dump(dump)) => {
+            task = Some(dump.task());
+            ShellArgs::connect(dump.database, false)
+        },
+        Some(Command::Migrate(MigrateCommand::Up { database })) => ShellArgs::script(database, false, "MIGRATE UP;".to_string()),
+        Some(Command::Migrate(MigrateCommand::Down { database, count })) => {
+            ShellArgs::script(database, false, format!("MIGRATE DOWN {};", count))
+        },
+        Some(Command::Migrate(MigrateCommand::Status { database })) => {
+            ShellArgs::script(database, false, "MIGRATE STATUS;".to_string())
+        },
+        Some(Command::Migrate(MigrateCommand::New { name })) => ShellArgs {
+            database: None,
+            ..ShellArgs::script(String::new(), false, format!("MIGRATE NEW {};", name))
+        },
+        Some(command) => {
+            server = Some(command);
+            args
+        },
+    };
+
+    let interactive = server.is_none() && task.is_none() && args.execute.is_none() && args.file.is_none() && std::io::stdin().is_terminal();
+    let config = config::load(options.config.as_deref()).unwrap_or_else(|e| exit_with(e));
+
+    let mut settings = Settings {
+        timing: interactive,
+        color: color_by_default(),
+        themes: config.themes().unwrap_or_else(|e| exit_with(e.context("Invalid configuration"))),
+        ..Settings::default()
+    };
+    for (name, value) in config.settings() {
+        if let Err(e) = apply_setting(&mut settings, &name, &value) {
+            exit_with(e.context("Invalid configuration"));
         }
-        println!("|");
-
-        // Print line after header
-        println!("+{}+", create_line(&column_widths));
-
-        // Print table rows
-        for row in &rows {
-            for (i, col) in columns.iter().enumerate() {
-                let value = match col.type_info().name() {
-                    "TEXT" => row.try_get::<String, _>(col.name()).unwrap_or_default(),
-                    "INTEGER" => row.try_get::<i64, _>(col.name()).map(|v| v.to_string()).unwrap_or_default(),
-                    _ => {
-                        row.try_get::<f64, _>(col.name()).map(|v| v.to_string())
-                            .unwrap_or_else(|_| "Unsupported type".to_string())
-                    },
-                };
-                print!("| {:width$} ", value, width = column_widths[i]);
+    }
+    if let Some(format) = options.format {
+        settings.format = format;
+    }
+    if options.no_color {
+        settings.color = false;
+    }
+    if options.quiet {
+        settings.quiet = true;
+    }
+    settings.json_errors = options.json_errors;
+    if let Some(null_value) = options.null_value {
+        settings.null_value = null_value;
+    }
+    if let Some(data_dir) = &options.data_dir {
+        if let Err(e) = apply_setting(&mut settings, "data_dir", data_dir) {
+            exit_with(e);
+        }
+    }
+
+    match server {
+        Some(Command::Serve(args)) => {
+            let options = ServeOptions {
+                listen: args.listen,
+                token: token(args.token),
+                read_only: args.read_only,
+                settings,
+                pragmas: config.pragmas(),
+                web: false,
+            };
+            if let Err(e) = server::serve(&args.db, options).await {
+                exit_with(e);
+            }
+            return;
+        },
+        Some(Command::Web(args)) => {
+            // Any site open in a browser can send requests to localhost, so
+            // the page gets a token of its own, in the link printed.
+            let options = ServeOptions {
+                listen: SocketAddr::from(([127, 0, 0, 1], args.port)),
+                token: Some(uuid::Uuid::new_v4().simple().to_string()),
+                read_only: args.read_only,
+                settings,
+                pragmas: config.pragmas(),
+                web: true,
+            };
+            if let Err(e) = server::serve(&args.database, options).await {
+                exit_with(e);
             }
-            println!("|");
+            return;
+        },
+        Some(Command::Connect(args)) => {
+            let token = token(args.token);
+            if let Err(e) = remote::connect(&args.url, token.as_deref(), &settings, history_file(&config), history_size(&config)).await {
+                exit_with(e);
+            }
+            return;
+        },
+        _ => {},
+    }
+
+    let banners = interactive && !settings.quiet;
+    let mut shell = Shell::new(settings, interactive, config.pragmas());
+    if let Some(path) = options.audit_log.as_ref().or(config.audit_log.as_ref()) {
+        match AuditLog::open(path) {
+            Ok(audit_log) => shell.set_audit_log(audit_log),
+            Err(e) => exit_with(e),
         }
+    }
 
-        // Print bottom border
-        println!("+{}+", create_line(&column_widths));
-    } else {
-        sqlx::query(sql).execute(pool).await?;
+    if banners {
+        println!("Welcome to the GalvanizeDB CLI. Type help or ? to list commands.\n");
     }
 
-    Ok(())
-}
+    if let Some(url) = args.database.as_deref().filter(|name| postgres::is_url(name)) {
+        if let Err(e) = shell.connect_postgres(url).await {
+            exit_with(e);
+        }
+        if banners {
+            println!("Database connection established to '{}'.\n", shell.database_name());
+        }
+    } else if args.memory {
+        if let Err(e) = shell.connect(MEMORY_DATABASE).await {
+            exit_with(e);
+        }
+    } else if let Some(name) = &args.database {
+        let name = format_db_name(name);
+        if !shell.database_path(&name).exists() && !args.create {
+            exit_with(GalvanizeError::Connection(format!("{} does not exist. Use --create to create it.", name)));
+        }
+        if let Err(e) = shell.connect(&name).await {
+            exit_with(e);
+        }
+        if banners {
+            println!("Database connection established to '{}'.\n", name);
+        }
+    }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let config = Config::default();
-    let mut rl = Editor::<(), MemHistory>::with_history(config, MemHistory::new())
-        .expect("Failed to create editor");
-
-    //print!("\x1B[2J\x1B[1;1H"); // clears the terminal
-    
-    let mut database_name = "None".to_string();
-    let mut sql_pool: Option<SqlitePool> = None;
-
-    println!("Welcome to the GalvanizeDB CLI. Type help or ? to list commands.\n");
-
-    loop {
-        let prompt = format!("GalvanizeDB [{}]> ", database_name);
-
-        match rl.readline(&prompt) {
-            Ok(line) => {
-                let _ = rl.add_history_entry(line.as_str());
-
-                if line.to_lowercase().starts_with("use ") || line.to_lowercase().starts_with("create database "){
-                    if let Some(active_database_name) = extract_db_name(&line) {
-                        database_name = active_database_name;
-                        if !db_file_check(&database_name) && line.to_lowercase().starts_with("use "){
-                            println!("{} does not exist. \nAttempting to create {}", database_name, database_name);
-                        }
-                        match create_or_connect_database(&database_name).await {
-                            Ok(pool) => {
-                                if line.to_lowercase().starts_with("create database ") {
-                                    println!("{} successfully created.", database_name);
-                                }
-                                println!("Database connection established to '{}'.\n", database_name);
-                                sql_pool = Some(pool);
-                            },
-                            Err(e) => {
-                                eprintln!("Error connecting to database '{}': {}\n", database_name, e);
-                                sql_pool = None; // Reset the pool if connection fails
-                            }
-                        }
-                    } else {
-                        eprintln!("Invalid database name.");
-                    }
-                }
-                else if line.to_lowercase().starts_with("drop schema ") {
-                    if let Some(pool) = &sql_pool {
-                        println!("Closing database connection...");
-                        pool.close().await;
-                        println!("Connection closed.\n");
-                        database_name = "None".to_string();
-                    }
-                }
-                else if line.to_lowercase() == "show tables;" {
-                    if let Some(pool) = &sql_pool {
-                        let show_tables_query = "SELECT name FROM sqlite_master WHERE type='table';";
-                        match execute_sql(pool, show_tables_query).await {
-                            Ok(_) => println!("\nQuery executed successfully.\n"),
-                            Err(e) => println!("\nError executing query: {}\n", e),
-                        }
-                    } else {
-                        println!("No database selected.");
-                    }
-                }
-                else if line.to_lowercase().starts_with("drop database ") {
-                    if let Some(new_database_name) = extract_db_name(&line) {
-                        if let Some(pool) = &sql_pool {
-                            println!("Closing database connection...");
-                            pool.close().await;
-                            println!("Connection closed.");
-                        }
-                
-                        let db_file_path = format!("{}", new_database_name);
-                        match std::fs::remove_file(&db_file_path) {
-                            Ok(_) => println!("Database '{}' dropped successfully.", new_database_name),
-                            Err(e) => eprintln!("Error dropping database '{}': {}", new_database_name, e),
-                        }
-                
-                        database_name = "None".to_string();
-                        sql_pool = None;
-                    } else {
-                        eprintln!("Invalid database name.");
-                    }
-                }
-                else if line.to_lowercase() == "help" || line == "?" {
-                    help();
-                }
-                else if line.to_lowercase() == "exit" {
-                    if let Some(pool) = sql_pool {
-                        println!("Closing database connection...");
-                        pool.close().await;
-                        println!("Connection closed.");
-                    }
-                    break;
-                } else {
-                    if let Some(pool) = &sql_pool {
-                        match execute_sql(pool, &line).await {
-                            Ok(_) => println!("\nQuery executed successfully.\n"),
-                            Err(e) => println!("\nError executing query: {}\n", e),
-                        }
-                    } else {
-                        println!("No database selected.");
-                    }
-                }
-            },
-            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
-                if let Some(pool) = sql_pool {
-                    println!("Closing database connection due to interruption...");
-                    pool.close().await;
-                    println!("Connection closed.");
-                }
-                break;
-            },
-            Err(err) => {
-                println!("Error reading line: {:?}", err);
-            }
+    if let Some(name) = &args.resume {
+        if let Err(e) = shell.resume_session(name).await {
+            exit_with(e);
+        }
+        if banners {
+            println!("Resumed session '{}'.\n", name);
         }
     }
 
-    Ok(())
+    let result = if let Some(task) = task {
+        shell.run_task(task).await
+    } else if let Some(sql) = &args.execute {
+        run_batch(&mut shell, sql, "<command line>", args.on_error, args.single_transaction).await
+    } else if let Some(path) = &args.file {
+        match std::fs::read_to_string(path) {
+            Ok(input) => run_batch(&mut shell, &input, &path.display().to_string(), args.on_error, args.single_transaction).await,
+            Err(e) => Err(GalvanizeError::Io(format!("Error reading '{}': {}", path.display(), e))),
+        }
+    } else if !interactive {
+        match std::io::read_to_string(std::io::stdin()) {
+            Ok(input) => run_batch(&mut shell, &input, "<stdin>", args.on_error, args.single_transaction).await,
+            Err(e) => Err(GalvanizeError::from(e).context("Error reading input")),
+        }
+    } else {
+        run_repl(&mut shell, history_file(&config), history_size(&config)).await;
+        Ok(())
+    };
+
+    shell.close_all().await;
+
+    if let Err(e) = result {
+        exit_at(e, shell.failed_statement());
+    }
 }
@@ -0,0 +1,202 @@
+use crate::error::{GalvanizeError, Result};
+
+/// What a meta-command does.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MetaAction {
+    Commands,
+    Help,
+    Quit,
+    Describe,
+    Tables,
+    Indexes,
+    Schema,
+    Explore,
+    Dump,
+    Include,
+    Output,
+    Pipe,
+    Set,
+    Unset,
+    Tee,
+    NoTee,
+    FormatSql,
+    SaveSession,
+    Headers,
+    Mode,
+    Import,
+    Open,
+}
+
+/// A command starting with `\` or `.`, rather than an SQL-like statement.
+pub struct MetaCommand {
+    /// The name of the command, then any aliases, each with its prefix.
+    pub names: &'static [&'static str],
+    /// What follows the name, for the help listing.
+    pub arguments: &'static str,
+    pub description: &'static str,
+    pub action: MetaAction,
+}
+
+pub const META_COMMANDS: &[MetaCommand] = &[
+    MetaCommand { names: &["\\?"], arguments: "", description: "List these commands", action: MetaAction::Commands },
+    MetaCommand { names: &["\\help", "\\h", ".help"], arguments: "", description: "Show the full help", action: MetaAction::Help },
+    MetaCommand { names: &["\\quit", "\\q", ".quit", ".exit"], arguments: "", description: "Close GalvanizeDB CLI", action: MetaAction::Quit },
+    MetaCommand {
+        names: &["\\d"],
+        arguments: "[table]",
+        description: "List the tables and views, or describe the columns of a table",
+        action: MetaAction::Describe,
+    },
+    MetaCommand { names: &["\\dt", ".tables"], arguments: "[pattern]", description: "List the tables, or those LIKE the pattern", action: MetaAction::Tables },
+    MetaCommand { names: &["\\di", ".indexes"], arguments: "[table]", description: "List the indexes, or those of a table", action: MetaAction::Indexes },
+    MetaCommand {
+        names: &["\\schema", ".schema"],
+        arguments: "[pattern]",
+        description: "Show the CREATE statements of the schema, or of the objects LIKE the pattern",
+        action: MetaAction::Schema,
+    },
+    MetaCommand {
+        names: &["\\explore"],
+        arguments: "",
+        description: "Explore the databases, tables, columns, indexes and triggers as a tree",
+        action: MetaAction::Explore,
+    },
+    MetaCommand {
+        names: &["\\dump", ".dump"],
+        arguments: "[options]",
+        description: "Write the database out as SQL, as DUMP does",
+        action: MetaAction::Dump,
+    },
+    MetaCommand { names: &["\\include", "\\i", ".read"], arguments: "file", description: "Execute the statements in a script file", action: MetaAction::Include },
+    MetaCommand {
+        names: &["\\output", "\\o"],
+        arguments: "file",
+        description: "Write the results of the next query to a file",
+        action: MetaAction::Output,
+    },
+    MetaCommand {
+        names: &["\\pipe"],
+        arguments: "command",
+        description: "Pipe the results of the next query into a shell command",
+        action: MetaAction::Pipe,
+    },
+    MetaCommand { names: &["\\set"], arguments: "[name [value]]", description: "Set a variable, or list them", action: MetaAction::Set },
+    MetaCommand { names: &["\\unset"], arguments: "name", description: "Remove a variable", action: MetaAction::Unset },
+    MetaCommand { names: &["\\tee"], arguments: "file", description: "Copy everything shown from now on to a file", action: MetaAction::Tee },
+    MetaCommand { names: &["\\notee"], arguments: "", description: "Stop copying output to a file", action: MetaAction::NoTee },
+    MetaCommand {
+        names: &["\\format-sql"],
+        arguments: "query",
+        description: "Show a query laid out for reading (Alt+Q lays out the one being edited)",
+        action: MetaAction::FormatSql,
+    },
+    MetaCommand {
+        names: &["\\save-session"],
+        arguments: "[name]",
+        description: "Save the database, attachments, settings and variables, to return to with --resume",
+        action: MetaAction::SaveSession,
+    },
+    // The dot-commands of the sqlite3 shell, for its scripts and habits.
+    MetaCommand { names: &[".headers"], arguments: "on|off", description: "Show or hide the header row of results", action: MetaAction::Headers },
+    MetaCommand {
+        names: &[".mode"],
+        arguments: "[mode]",
+        description: "Show or change the output format, by name or sqlite3 mode (column, line, tabs ...)",
+        action: MetaAction::Mode,
+    },
+    MetaCommand {
+        names: &[".import"],
+        arguments: "[--csv] [--skip 1] file table",
+        description: "Import a CSV file into a table, created with the first row as its columns if needed",
+        action: MetaAction::Import,
+    },
+    MetaCommand {
+        names: &[".open"],
+        arguments: "[file]",
+        description: "Close the database and open a file, or a new in-memory database",
+        action: MetaAction::Open,
+    },
+];
+
+/// Whether `line` is a meta-command rather than a statement.
+pub fn is_meta_command(line: &str) -> bool {
+    line.starts_with('\\') || line.starts_with('.')
+}
+
+/// Finds the command named by the first word of `line`, and returns it with
+/// the rest of the line, without a trailing `;`. A name may be abbreviated
+/// as long as only one command starts with it.
+pub fn find(line: &str) -> Result<(&'static MetaCommand, &str)> {
+    let line = line.trim().trim_end_matches(';').trim_end();
+    let (name, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let name = name.to_lowercase();
+
+    if let Some(command) = META_COMMANDS.iter().find(|command| command.names.contains(&name.as_str())) {
+        return Ok((command, argument.trim()));
+    }
+    let matches: Vec<&MetaCommand> = META_COMMANDS
+        .iter()
+        .filter(|command| command.names.iter().any(|candidate| candidate.starts_with(&name)))
+        .collect();
+    match matches.as_slice() {
+        [command] => Ok((command, argument.trim())),
+        [] => Err(GalvanizeError::Parse(format!("Unknown command '{}'. Type \\? for a list of commands.", name))),
+        _ => {
+            let names: Vec<&str> = matches
+                .iter()
+                .flat_map(|command| command.names.iter().copied().filter(|candidate| candidate.starts_with(&name)))
+                .collect();
+            Err(GalvanizeError::Parse(format!("Ambiguous command '{}': {}.", name, names.join(", "))))
+        },
+    }
+}
+
+/// The list of meta-commands shown by `\?`.
+pub fn listing() -> String {
+    let usages: Vec<String> = META_COMMANDS
+        .iter()
+        .map(|command| format!("{} {}", command.names.join(", "), command.arguments).trim_end().to_string())
+        .collect();
+    let width = usages.iter().map(String::len).max().unwrap_or(0);
+    let mut listing = String::from("Commands (names may be shortened while they stay unambiguous):\n");
+    for (usage, command) in usages.iter().zip(META_COMMANDS) {
+        listing.push_str(&format!("  {:width$}  {}\n", usage, command.description, width = width));
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(line: &str) -> (MetaAction, &str) {
+        let (command, argument) = find(line).unwrap();
+        (command.action, argument)
+    }
+
+    #[test]
+    fn finds_exact_names_before_prefixes() {
+        assert_eq!(action("\\d"), (MetaAction::Describe, ""));
+        assert_eq!(action("\\d users;"), (MetaAction::Describe, "users"));
+        assert_eq!(action("  \\DT  us%  ; "), (MetaAction::Tables, "us%"));
+        assert_eq!(action("\\h"), (MetaAction::Help, ""));
+        assert_eq!(action(".exit"), (MetaAction::Quit, ""));
+    }
+
+    #[test]
+    fn finds_unique_prefixes() {
+        assert_eq!(action("\\exp"), (MetaAction::Explore, ""));
+        assert_eq!(action("\\sa nightly"), (MetaAction::SaveSession, "nightly"));
+        assert_eq!(action("\\no"), (MetaAction::NoTee, ""));
+        assert_eq!(action("\\DU"), (MetaAction::Dump, ""));
+        assert_eq!(action(".imp --csv a.csv t"), (MetaAction::Import, "--csv a.csv t"));
+    }
+
+    #[test]
+    fn rejects_ambiguous_and_unknown_names() {
+        let error = |line: &str| find(line).err().unwrap().to_string();
+        assert_eq!(error("\\s"), "Ambiguous command '\\s': \\schema, \\set, \\save-session.");
+        assert_eq!(error(".h"), "Ambiguous command '.h': .help, .headers.");
+        assert_eq!(error("\\nope"), "Unknown command '\\nope'. Type \\? for a list of commands.");
+    }
+}
@@ -0,0 +1,277 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, Row};
+use sqlx::sqlite::SqliteConnection;
+use crate::error::{GalvanizeError, Result};
+use crate::output::format_unix_time;
+
+/// The directory, relative to the working directory, that holds the
+/// migrations: `<version>_<name>.up.sql` files, each with an optional
+/// `<version>_<name>.down.sql` that reverts it.
+pub const MIGRATIONS_DIR: &str = "migrations";
+
+// The table that records which migrations have been applied.
+const CREATE_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS _galvanize_migrations (\
+    version INTEGER PRIMARY KEY, name TEXT NOT NULL, checksum TEXT NOT NULL, applied_at TEXT NOT NULL);";
+
+struct Migration {
+    version: i64,
+    name: String,
+    up: PathBuf,
+    down: Option<PathBuf>,
+}
+
+impl Migration {
+    fn label(&self) -> String {
+        format!("{}_{}", self.version, self.name)
+    }
+}
+
+struct AppliedMigration {
+    version: i64,
+    name: String,
+    checksum: String,
+    applied_at: String,
+}
+
+/// Where a migration stands in the connected database.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Applied, but its file has been edited since.
+    Changed,
+    /// Applied, but its file is gone.
+    Missing,
+}
+
+impl MigrationState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::Changed => "changed since applied",
+            MigrationState::Missing => "file missing",
+        }
+    }
+}
+
+/// A migration in the directory or the database, for MIGRATE STATUS.
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub state: MigrationState,
+    pub applied_at: Option<String>,
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| GalvanizeError::Io(format!("Error reading '{}': {}", path.display(), e)))
+}
+
+fn checksum(sql: &str) -> String {
+    Sha256::digest(sql.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Splits a file name like `20240101120000_create_users.up.sql` into its
+// version, name and direction.
+fn parse_file_name(file_name: &str) -> Option<(i64, &str, bool)> {
+    let (stem, up) = match file_name.strip_suffix(".up.sql") {
+        Some(stem) => (stem, true),
+        None => (file_name.strip_suffix(".down.sql")?, false),
+    };
+    let (version, name) = stem.split_once('_')?;
+    Some((version.parse().ok()?, name, up))
+}
+
+// The migrations in `dir`, oldest first.
+fn read_migrations(dir: &Path) -> Result<Vec<Migration>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(GalvanizeError::Io(format!("Error reading '{}': {}", dir.display(), e))),
+    };
+
+    let mut migrations: Vec<Migration> = Vec::new();
+    let mut downs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let Some((version, name, up)) = parse_file_name(&file_name) else {
+            continue;
+        };
+        if !up {
+            downs.push((version, path));
+            continue;
+        }
+        if let Some(existing) = migrations.iter().find(|migration| migration.version == version) {
+            return Err(GalvanizeError::Parse(format!(
+                "Migrations '{}' and '{}' have the same version.",
+                existing.up.display(),
+                path.display()
+            )));
+        }
+        migrations.push(Migration { version, name: name.to_string(), up: path, down: None });
+    }
+    for (version, path) in downs {
+        match migrations.iter_mut().find(|migration| migration.version == version) {
+            Some(migration) => migration.down = Some(path),
+            None => return Err(GalvanizeError::Parse(format!("'{}' has no matching .up.sql file.", path.display()))),
+        }
+    }
+    migrations.sort_by_key(|migration| migration.version);
+    Ok(migrations)
+}
+
+async fn read_applied(conn: &mut SqliteConnection) -> Result<Vec<AppliedMigration>> {
+    sqlx::query(CREATE_MIGRATIONS_TABLE).execute(&mut *conn).await?;
+    Ok(sqlx::query("SELECT version, name, checksum, applied_at FROM _galvanize_migrations ORDER BY version;")
+        .fetch_all(conn)
+        .await?
+        .iter()
+        .map(|row| AppliedMigration {
+            version: row.get("version"),
+            name: row.get("name"),
+            checksum: row.get("checksum"),
+            applied_at: row.get("applied_at"),
+        })
+        .collect())
+}
+
+// Runs the script of migration `version` and records it as applied, with the
+// name and checksum given, or as reverted. Both happen under a savepoint, so
+// the script takes effect completely or not at all.
+async fn run_migration(conn: &mut SqliteConnection, version: i64, sql: &str, applied: Option<(&str, &str)>) -> Result<()> {
+    sqlx::query("SAVEPOINT migration;").execute(&mut *conn).await?;
+
+    let result = async {
+        conn.execute(sql).await?;
+        let record = match applied {
+            Some((name, checksum)) => sqlx::query(
+                "INSERT INTO _galvanize_migrations (version, name, checksum, applied_at) VALUES (?, ?, ?, datetime('now'));",
+            )
+            .bind(version)
+            .bind(name)
+            .bind(checksum),
+            None => sqlx::query("DELETE FROM _galvanize_migrations WHERE version = ?;").bind(version),
+        };
+        record.execute(&mut *conn).await?;
+        Ok::<_, GalvanizeError>(())
+    }
+    .await;
+    match result {
+        Ok(()) => {
+            sqlx::query("RELEASE migration;").execute(&mut *conn).await?;
+            Ok(())
+        },
+        Err(e) => {
+            sqlx::query("ROLLBACK TO migration;").execute(&mut *conn).await?;
+            sqlx::query("RELEASE migration;").execute(&mut *conn).await?;
+            Err(e)
+        },
+    }
+}
+
+/// The state of every migration in `dir` and every applied one, by version.
+pub async fn migration_status(conn: &mut SqliteConnection, dir: &Path) -> Result<Vec<MigrationStatus>> {
+    let migrations = read_migrations(dir)?;
+    let applied = read_applied(conn).await?;
+
+    let mut statuses = Vec::new();
+    for migration in &migrations {
+        let status = match applied.iter().find(|applied| applied.version == migration.version) {
+            Some(applied) => {
+                let changed = checksum(&read_file(&migration.up)?) != applied.checksum;
+                let state = if changed { MigrationState::Changed } else { MigrationState::Applied };
+                MigrationStatus { version: migration.version, name: migration.name.clone(), state, applied_at: Some(applied.applied_at.clone()) }
+            },
+            None => MigrationStatus { version: migration.version, name: migration.name.clone(), state: MigrationState::Pending, applied_at: None },
+        };
+        statuses.push(status);
+    }
+    for applied in applied.iter().filter(|applied| !migrations.iter().any(|migration| migration.version == applied.version)) {
+        statuses.push(MigrationStatus {
+            version: applied.version,
+            name: applied.name.clone(),
+            state: MigrationState::Missing,
+            applied_at: Some(applied.applied_at.clone()),
+        });
+    }
+    statuses.sort_by_key(|status| status.version);
+    Ok(statuses)
+}
+
+/// Applies the pending migrations in `dir`, oldest first, each in its own
+/// transaction, and writes a line for each. Stops at the first that fails,
+/// leaving the ones before it applied. Returns how many were applied.
+pub async fn migrate_up(conn: &mut SqliteConnection, dir: &Path, out: &mut dyn Write) -> Result<usize> {
+    let migrations = read_migrations(dir)?;
+    let applied = read_applied(conn).await?;
+
+    // An edited migration no longer describes what the database went through.
+    for migration in &migrations {
+        if let Some(applied) = applied.iter().find(|applied| applied.version == migration.version) {
+            if checksum(&read_file(&migration.up)?) != applied.checksum {
+                return Err(GalvanizeError::Execution(format!(
+                    "Migration '{}' has changed since it was applied. Restore the file, or revert it with MIGRATE DOWN before editing it.",
+                    migration.label()
+                )));
+            }
+        }
+    }
+
+    let mut count = 0;
+    for migration in migrations.iter().filter(|migration| !applied.iter().any(|applied| applied.version == migration.version)) {
+        let sql = read_file(&migration.up)?;
+        run_migration(conn, migration.version, &sql, Some((&migration.name, &checksum(&sql))))
+            .await
+            .map_err(|e| e.context(format!("Error applying migration '{}'", migration.label())))?;
+        writeln!(out, "Applied {}.", migration.label())?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Reverts the last `count` applied migrations, newest first, with their
+/// `.down.sql` files, each in its own transaction. Returns how many were
+/// reverted.
+pub async fn migrate_down(conn: &mut SqliteConnection, dir: &Path, count: usize, out: &mut dyn Write) -> Result<usize> {
+    let migrations = read_migrations(dir)?;
+    let applied = read_applied(conn).await?;
+
+    let mut reverted = 0;
+    for applied in applied.iter().rev().take(count) {
+        let label = format!("{}_{}", applied.version, applied.name);
+        let down = migrations
+            .iter()
+            .find(|migration| migration.version == applied.version)
+            .and_then(|migration| migration.down.as_ref())
+            .ok_or_else(|| GalvanizeError::Execution(format!("Migration '{}' has no {}.down.sql file to revert it with.", label, label)))?;
+        let sql = read_file(down)?;
+        run_migration(conn, applied.version, &sql, None)
+            .await
+            .map_err(|e| e.context(format!("Error reverting migration '{}'", label)))?;
+        writeln!(out, "Reverted {}.", label)?;
+        reverted += 1;
+    }
+    Ok(reverted)
+}
+
+/// Creates empty up and down files for a new migration in `dir`, versioned
+/// with the current UTC time, and returns their paths.
+pub fn new_migration(dir: &Path, name: &str) -> Result<(PathBuf, PathBuf)> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(GalvanizeError::Parse("Migration names may only contain letters, digits and underscores.".to_string()));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64);
+    let version: String = format_unix_time(now, "DATETIME").chars().filter(char::is_ascii_digit).collect();
+    std::fs::create_dir_all(dir).map_err(|e| GalvanizeError::Io(format!("Error creating '{}': {}", dir.display(), e)))?;
+
+    let up = dir.join(format!("{}_{}.up.sql", version, name));
+    let down = dir.join(format!("{}_{}.down.sql", version, name));
+    for (path, contents) in [(&up, format!("-- {}: changes to apply\n", name)), (&down, format!("-- {}: how to revert them\n", name))] {
+        std::fs::write(path, contents).map_err(|e| GalvanizeError::Io(format!("Error creating '{}': {}", path.display(), e)))?;
+    }
+    Ok((up, down))
+}
@@ -0,0 +1,856 @@
+use std::io::Write;
+use std::sync::Arc;
+use arrow_array::{ArrayRef, BinaryArray, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use futures_util::TryStreamExt;
+use futures_util::stream::BoxStream;
+use clap::ValueEnum;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+use sqlx::sqlite::{SqliteColumn, SqliteRow};
+use crate::Settings;
+use crate::error::{GalvanizeError, Result};
+use crate::theme::paint;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+const WIDTH_SAMPLE_ROWS: usize = 1000;
+const BLOB_PREVIEW_BYTES: usize = 16;
+// Columns are not narrowed below this to fit the terminal.
+const MIN_COLUMN_WIDTH: usize = 8;
+// Rows converted to Arrow arrays at a time, and per Parquet row group.
+const PARQUET_BATCH_ROWS: usize = 8192;
+const PARQUET_ROW_GROUP_ROWS: usize = 65_536;
+
+/// Decoded result rows, produced as they are fetched.
+pub type ValueStream<'a> = BoxStream<'a, Result<Vec<Value>>>;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+    Json,
+    Vertical,
+    Markdown,
+    Html,
+    /// Values separated by the `separator` setting, without headers by default.
+    List,
+    /// Tab-separated values, without headers by default.
+    Tsv,
+    /// Apache Parquet, a binary columnar format, for writing to a file.
+    Parquet,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        OutputFormat::from_str(name, true).ok()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Table => "TABLE",
+            OutputFormat::Csv => "CSV",
+            OutputFormat::Json => "JSON",
+            OutputFormat::Vertical => "VERTICAL",
+            OutputFormat::Markdown => "MARKDOWN",
+            OutputFormat::Html => "HTML",
+            OutputFormat::List => "LIST",
+            OutputFormat::Tsv => "TSV",
+            OutputFormat::Parquet => "PARQUET",
+        }
+    }
+
+    /// The format a file is written in going by its extension, looking past
+    /// a `.gz` or `.zst` compression suffix, if it names one.
+    pub fn from_extension(path: &std::path::Path) -> Option<OutputFormat> {
+        let path = match path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).as_deref() {
+            Some("gz" | "zst") => path.with_extension(""),
+            _ => path.to_path_buf(),
+        };
+        match path.extension()?.to_string_lossy().to_lowercase().as_str() {
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "json" => Some(OutputFormat::Json),
+            "md" | "markdown" => Some(OutputFormat::Markdown),
+            "html" | "htm" => Some(OutputFormat::Html),
+            "parquet" => Some(OutputFormat::Parquet),
+            _ => None,
+        }
+    }
+
+    /// The format of an sqlite3 `.mode`, such as `column` or `line`, or the
+    /// format called `mode`.
+    pub fn from_sqlite_mode(mode: &str) -> Option<OutputFormat> {
+        match mode.to_lowercase().as_str() {
+            "column" | "box" | "table" => Some(OutputFormat::Table),
+            "line" => Some(OutputFormat::Vertical),
+            "tabs" => Some(OutputFormat::Tsv),
+            _ => OutputFormat::from_name(mode),
+        }
+    }
+
+    /// All format names, for help and error messages.
+    pub fn names() -> String {
+        let names: Vec<&str> = OutputFormat::value_variants().iter().map(OutputFormat::name).collect();
+        match names.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+            _ => names.join(""),
+        }
+    }
+}
+
+/// What happens to table cells wider than their column.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Overflow {
+    /// Cut the text short with an ellipsis.
+    Truncate,
+    /// Continue the text on further lines.
+    Wrap,
+    /// Like truncate, but the table is not narrowed to fit the terminal.
+    Off,
+}
+
+impl Overflow {
+    pub fn from_name(name: &str) -> Option<Overflow> {
+        Overflow::from_str(name, true).ok()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Overflow::Truncate => "TRUNCATE",
+            Overflow::Wrap => "WRAP",
+            Overflow::Off => "OFF",
+        }
+    }
+}
+
+/// The width of the terminal stdout is connected to, if it is one.
+pub fn terminal_width() -> Option<usize> {
+    terminal_size::terminal_size().map(|(width, _)| width.0 as usize)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// Renders a blob as an SQL hex literal followed by its size, truncated to a
+// short preview unless full output was requested.
+fn format_blob(bytes: &[u8], full: bool) -> String {
+    let shown = if full { bytes } else { &bytes[..bytes.len().min(BLOB_PREVIEW_BYTES)] };
+    let hex: String = shown.iter().map(|b| format!("{:02X}", b)).collect();
+    let ellipsis = if shown.len() < bytes.len() { "…" } else { "" };
+
+    format!("x'{}{}' ({})", hex, ellipsis, format_size(bytes.len() as u64))
+}
+
+// Converts a number of days since 1970-01-01 to a (year, month, day) date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Formats a Unix timestamp according to the declared column type
+// (DATE, TIME or DATETIME).
+pub fn format_unix_time(secs: i64, declared_type: &str) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let seconds = secs.rem_euclid(86_400);
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+    let time = format!("{:02}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60);
+
+    match declared_type {
+        "DATE" => date,
+        "TIME" => time,
+        _ => format!("{} {}", date, time),
+    }
+}
+
+fn format_real(value: f64) -> String {
+    // Keep a decimal point on whole numbers so REAL values are
+    // distinguishable from INTEGER ones.
+    if value.is_finite() && value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{:.1}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// A single decoded result cell.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    // Arbitrary-precision numbers, such as PostgreSQL NUMERIC, kept as text.
+    Decimal(String),
+    Boolean(bool),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    fn is_numeric(&self) -> bool {
+        matches!(self, Value::Integer(_) | Value::Real(_) | Value::Decimal(_))
+    }
+}
+
+// SQLite is dynamically typed, so the value is decoded by its actual storage
+// class; the declared column type only decides how it is presented (booleans
+// and dates stored as numbers).
+pub fn cell_value(row: &SqliteRow, col: &SqliteColumn) -> Value {
+    let raw = match row.try_get_raw(col.ordinal()) {
+        Ok(raw) => raw,
+        Err(_) => return Value::Null,
+    };
+
+    if raw.is_null() {
+        return Value::Null;
+    }
+
+    let storage_class = raw.type_info().name().to_string();
+    let declared_type = col.type_info().name();
+
+    match storage_class.as_str() {
+        "INTEGER" => {
+            let value: i64 = row.try_get_unchecked(col.ordinal()).unwrap_or_default();
+            match declared_type {
+                "BOOLEAN" => Value::Boolean(value != 0),
+                "DATE" | "TIME" | "DATETIME" => Value::Text(format_unix_time(value, declared_type)),
+                _ => Value::Integer(value),
+            }
+        },
+        "REAL" => {
+            let value: f64 = row.try_get_unchecked(col.ordinal()).unwrap_or_default();
+            match declared_type {
+                // REAL dates are Julian day numbers.
+                "DATE" | "TIME" | "DATETIME" => {
+                    let secs = ((value - 2_440_587.5) * 86_400.0).round() as i64;
+                    Value::Text(format_unix_time(secs, declared_type))
+                },
+                _ => Value::Real(value),
+            }
+        },
+        "BLOB" => Value::Blob(row.try_get_unchecked(col.ordinal()).unwrap_or_default()),
+        _ => Value::Text(row.try_get_unchecked(col.ordinal()).unwrap_or_default()),
+    }
+}
+
+pub(crate) fn value_text(value: &Value, settings: &Settings) -> String {
+    match value {
+        Value::Null => settings.null_value.clone(),
+        Value::Integer(v) => v.to_string(),
+        Value::Real(v) => format_real(*v),
+        Value::Decimal(v) => v.clone(),
+        Value::Boolean(v) => v.to_string(),
+        Value::Text(v) => v.clone(),
+        Value::Blob(v) => format_blob(v, settings.full_blobs),
+    }
+}
+
+// The color of a value in the current theme, if the result is colored.
+fn value_style<'a>(value: &Value, settings: &'a Settings) -> &'a str {
+    match value {
+        _ if !settings.colored => "",
+        Value::Null => &settings.theme.null,
+        _ if value.is_numeric() => &settings.theme.number,
+        _ => "",
+    }
+}
+
+pub(crate) fn value_json(value: &Value, settings: &Settings) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(v) => serde_json::Value::from(*v),
+        Value::Real(v) => serde_json::Value::from(*v),
+        Value::Decimal(v) => v.parse::<serde_json::Number>().map(serde_json::Value::Number).unwrap_or_else(|_| serde_json::Value::from(v.as_str())),
+        Value::Boolean(v) => serde_json::Value::from(*v),
+        Value::Text(v) => serde_json::Value::from(v.as_str()),
+        Value::Blob(v) => serde_json::Value::from(format_blob(v, settings.full_blobs)),
+    }
+}
+
+pub fn row_values(row: &SqliteRow) -> Vec<Value> {
+    row.columns().iter().map(|col| cell_value(row, col)).collect()
+}
+
+/// Writes a result in the selected output format and returns the number of
+/// rows written.
+pub async fn print_result(column_names: &[String], rows: ValueStream<'_>, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    match settings.format {
+        OutputFormat::Table => print_table(column_names, rows, settings, out).await,
+        OutputFormat::Csv => print_csv(column_names, rows, settings, out).await,
+        OutputFormat::Json => print_json(column_names, rows, settings, out).await,
+        OutputFormat::Vertical => print_vertical(column_names, rows, settings, out).await,
+        OutputFormat::Markdown => print_markdown(column_names, rows, settings, out).await,
+        OutputFormat::Html => print_html(column_names, rows, settings, out).await,
+        OutputFormat::List => print_separated(column_names, rows, &settings.separator, settings, out).await,
+        OutputFormat::Tsv => print_separated(column_names, rows, "\t", settings, out).await,
+        OutputFormat::Parquet => print_parquet(column_names, rows, settings, out).await,
+    }
+}
+
+// Column widths are computed from the first rows only, so that the rest of
+// the result can be printed as it arrives instead of being held in memory.
+async fn sample_rows(rows: &mut ValueStream<'_>) -> Result<Vec<Vec<Value>>> {
+    let mut sample = Vec::new();
+    while sample.len() < WIDTH_SAMPLE_ROWS {
+        match rows.try_next().await? {
+            Some(values) => sample.push(values),
+            None => break,
+        }
+    }
+    Ok(sample)
+}
+
+// Columns whose sampled values are all numbers (or NULL), which are right-aligned.
+fn numeric_columns(sample: &[Vec<Value>], column_count: usize) -> Vec<bool> {
+    (0..column_count)
+        .map(|i| {
+            let mut values = sample.iter().map(|values| &values[i]).filter(|v| !matches!(v, Value::Null)).peekable();
+            values.peek().is_some() && values.all(Value::is_numeric)
+        })
+        .collect()
+}
+
+async fn print_table(column_names: &[String], mut rows: ValueStream<'_>, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    let sample = sample_rows(&mut rows).await?;
+
+    if sample.is_empty() {
+        writeln!(out, "No results found.")?;
+        return Ok(0);
+    }
+
+    let mut column_widths: Vec<usize> = column_names.iter().map(|name| text_width(name)).collect();
+
+    for values in &sample {
+        for (i, value) in values.iter().enumerate() {
+            let width = value_text(value, settings).split('\n').map(text_width).max().unwrap_or(0);
+            column_widths[i] = std::cmp::max(column_widths[i], width);
+        }
+    }
+
+    for (width, name) in column_widths.iter_mut().zip(column_names) {
+        if let Some(max_width) = settings.column_max_widths.get(&name.to_lowercase()) {
+            *width = (*width).min((*max_width).max(1));
+        }
+    }
+    if let (Some(available), true) = (settings.terminal_width, settings.overflow != Overflow::Off) {
+        fit_widths(&mut column_widths, available);
+    }
+
+    let numeric = numeric_columns(&sample, column_names.len());
+
+    // Print horizontal line
+    let create_line = |widths: &[usize]| {
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w + 2))
+            .collect::<Vec<_>>()
+            .join("+")
+    };
+
+    // Cells that do not fit their column are truncated or wrapped, in which
+    // case the row takes up several lines. Each cell is colored with the
+    // style given for it once padded, so the colors do not count towards the
+    // width.
+    let print_cells = |out: &mut dyn Write, cells: &[String], align: &[bool], styles: &[&str]| -> std::io::Result<()> {
+        let lines: Vec<Vec<String>> = cells
+            .iter()
+            .zip(&column_widths)
+            .map(|(text, width)| cell_lines(text, *width, settings.overflow))
+            .collect();
+        let height = lines.iter().map(Vec::len).max().unwrap_or(1);
+
+        for line in 0..height {
+            for (i, cell) in lines.iter().enumerate() {
+                let text = cell.get(line).map(String::as_str).unwrap_or("");
+                write!(out, "| {} ", paint(&pad(text, column_widths[i], align[i]), styles[i]))?;
+            }
+            writeln!(out, "|")?;
+        }
+        Ok(())
+    };
+    let print_row = |out: &mut dyn Write, values: &[Value]| -> std::io::Result<()> {
+        let cells: Vec<String> = values.iter().map(|value| value_text(value, settings)).collect();
+        let styles: Vec<&str> = values.iter().map(|value| value_style(value, settings)).collect();
+        print_cells(out, &cells, &numeric, &styles)
+    };
+    let header_style = if settings.colored { settings.theme.header.as_str() } else { "" };
+
+    // Print top border
+    writeln!(out, "+{}+", create_line(&column_widths))?;
+
+    // Print header row and the line after it
+    if settings.headers != Some(false) {
+        print_cells(out, column_names, &vec![false; column_names.len()], &vec![header_style; column_names.len()])?;
+        writeln!(out, "+{}+", create_line(&column_widths))?;
+    }
+
+    // Print table rows
+    let mut count = sample.len() as u64;
+    for values in &sample {
+        print_row(out, values)?;
+    }
+    while let Some(values) = rows.try_next().await? {
+        print_row(out, &values)?;
+        count += 1;
+    }
+
+    // Print bottom border
+    writeln!(out, "+{}+", create_line(&column_widths))?;
+    Ok(count)
+}
+
+// The number of terminal columns the text takes up: wide characters such as
+// CJK and emoji take two, combining and control characters none.
+pub(crate) fn text_width(text: &str) -> usize {
+    text.width()
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+fn pad(text: &str, width: usize, right_align: bool) -> String {
+    let padding = " ".repeat(width.saturating_sub(text_width(text)));
+    if right_align {
+        format!("{}{}", padding, text)
+    } else {
+        format!("{}{}", text, padding)
+    }
+}
+
+// Shortens text to at most `width` columns, ending it with an ellipsis.
+fn truncate(text: &str, width: usize) -> String {
+    if text_width(text) <= width {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        if used + char_width(c) + 1 > width {
+            break;
+        }
+        used += char_width(c);
+        truncated.push(c);
+    }
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+// Breaks a line of text into lines of at most `width` columns, at spaces
+// where possible.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_inclusive(' ') {
+        if !line.is_empty() && text_width(&line) + text_width(word.trim_end()) > width {
+            lines.push(line.trim_end().to_string());
+            line.clear();
+        }
+        // Words longer than the column are split wherever they reach its edge.
+        for c in word.chars() {
+            if !line.is_empty() && c != ' ' && text_width(&line) + char_width(c) > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(c);
+        }
+    }
+    lines.push(line.trim_end().to_string());
+    lines
+}
+
+// Lays out a cell as one or more lines: one per line of the text, each
+// truncated or wrapped to the column width.
+fn cell_lines(text: &str, width: usize, overflow: Overflow) -> Vec<String> {
+    text.split('\n')
+        .flat_map(|line| match overflow {
+            Overflow::Wrap => wrap(line, width),
+            _ => vec![truncate(line, width)],
+        })
+        .collect()
+}
+
+// Narrows the widest columns until the table fits in `available` columns,
+// without making any column narrower than MIN_COLUMN_WIDTH.
+fn fit_widths(widths: &mut [usize], available: usize) {
+    // Each column adds "| " and " ", plus the closing "|".
+    let mut total: usize = widths.iter().map(|w| w + 3).sum::<usize>() + 1;
+
+    while total > available {
+        let Some((widest, &width)) = widths.iter().enumerate().max_by_key(|(_, w)| **w) else { break };
+        if width <= MIN_COLUMN_WIDTH {
+            break;
+        }
+        let next = widths.iter().enumerate().filter(|(i, _)| *i != widest).map(|(_, w)| *w).max().unwrap_or(0);
+        let shrink = (total - available).min(width - next.max(MIN_COLUMN_WIDTH)).max(1);
+        widths[widest] -= shrink;
+        total -= shrink;
+    }
+}
+
+async fn print_csv(column_names: &[String], mut rows: ValueStream<'_>, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    let mut writer = csv::Writer::from_writer(out);
+    let mut count = 0;
+
+    if !column_names.is_empty() && settings.headers != Some(false) {
+        writer.write_record(column_names)?;
+    }
+    while let Some(values) = rows.try_next().await? {
+        writer.write_record(values.iter().map(|value| value_text(value, settings)))?;
+        count += 1;
+    }
+
+    writer.flush()?;
+    Ok(count)
+}
+
+// The Parquet type of a column, from its values in the first rows. Integers,
+// reals, booleans and blobs keep their type, a mix of integers and reals
+// becomes a double and anything else is written as text.
+fn parquet_type(sample: &[Vec<Value>], i: usize) -> DataType {
+    let mut column_type: Option<DataType> = None;
+    for value in sample.iter().map(|values| &values[i]) {
+        let value_type = match value {
+            Value::Null => continue,
+            Value::Integer(_) => DataType::Int64,
+            Value::Real(_) => DataType::Float64,
+            Value::Boolean(_) => DataType::Boolean,
+            Value::Blob(_) => DataType::Binary,
+            Value::Decimal(_) | Value::Text(_) => DataType::Utf8,
+        };
+        column_type = Some(match column_type {
+            None => value_type,
+            Some(column_type) if column_type == value_type => column_type,
+            Some(DataType::Int64 | DataType::Float64) if matches!(value_type, DataType::Int64 | DataType::Float64) => DataType::Float64,
+            Some(_) => DataType::Utf8,
+        });
+    }
+    column_type.unwrap_or(DataType::Utf8)
+}
+
+// Converts column `i` of the rows to an Arrow array of the field's type.
+fn parquet_column(rows: &[Vec<Value>], i: usize, field: &Field, settings: &Settings) -> Result<ArrayRef> {
+    let values = rows.iter().map(|values| &values[i]);
+    let mismatch = |value: &Value| {
+        GalvanizeError::Execution(format!(
+            "Column '{}' has the value {}, which does not fit the Parquet type {} taken from its first rows.",
+            field.name(), value_text(value, settings), field.data_type()
+        ))
+    };
+
+    Ok(match field.data_type() {
+        DataType::Int64 => Arc::new(
+            values
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Integer(n) => Ok(Some(*n)),
+                    Value::Boolean(b) => Ok(Some(i64::from(*b))),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<Int64Array>>()?,
+        ),
+        DataType::Float64 => Arc::new(
+            values
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Integer(n) => Ok(Some(*n as f64)),
+                    Value::Real(n) => Ok(Some(*n)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<Float64Array>>()?,
+        ),
+        DataType::Boolean => Arc::new(
+            values
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Boolean(b) => Ok(Some(*b)),
+                    Value::Integer(n) => Ok(Some(*n != 0)),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<BooleanArray>>()?,
+        ),
+        DataType::Binary => Arc::new(
+            values
+                .map(|value| match value {
+                    Value::Null => Ok(None),
+                    Value::Blob(bytes) => Ok(Some(bytes.as_slice())),
+                    Value::Text(text) | Value::Decimal(text) => Ok(Some(text.as_bytes())),
+                    value => Err(mismatch(value)),
+                })
+                .collect::<Result<BinaryArray>>()?,
+        ),
+        _ => Arc::new(
+            values
+                .map(|value| match value {
+                    Value::Null => None,
+                    value => Some(value_text(value, settings)),
+                })
+                .collect::<StringArray>(),
+        ),
+    })
+}
+
+// Writes the rows as a Snappy-compressed Parquet file. Column types are taken
+// from the first rows, like the widths of a table. The file is passed on to
+// `out` a row group at a time, so large results are not held in memory.
+async fn print_parquet(column_names: &[String], mut rows: ValueStream<'_>, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    let mut batch = sample_rows(&mut rows).await?;
+    let fields: Vec<Field> = column_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| Field::new(name, parquet_type(&batch, i), true))
+        .collect();
+    let schema = Arc::new(Schema::new(fields));
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_row_count(Some(PARQUET_ROW_GROUP_ROWS))
+        .build();
+    let mut writer = ArrowWriter::try_new(Vec::new(), schema.clone(), Some(properties))?;
+    let mut count = 0;
+
+    loop {
+        let mut finished = false;
+        while batch.len() < PARQUET_BATCH_ROWS {
+            match rows.try_next().await? {
+                Some(values) => batch.push(values),
+                None => {
+                    finished = true;
+                    break;
+                },
+            }
+        }
+
+        if !batch.is_empty() {
+            let columns = schema
+                .fields()
+                .iter()
+                .enumerate()
+                .map(|(i, field)| parquet_column(&batch, i, field, settings))
+                .collect::<Result<Vec<ArrayRef>>>()?;
+            writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+            count += batch.len() as u64;
+            batch.clear();
+            out.write_all(&std::mem::take(writer.inner_mut()))?;
+        }
+        if finished {
+            break;
+        }
+    }
+
+    out.write_all(&writer.into_inner()?)?;
+    Ok(count)
+}
+
+// Prints each row on a line of its own with the values joined by
+// `separator`, like sqlite3's list mode. There are no headers, unless they
+// are turned on, and values are written as they are, so the output can be
+// piped straight into cut or awk.
+async fn print_separated(column_names: &[String], mut rows: ValueStream<'_>, separator: &str, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    let mut count = 0;
+
+    if settings.headers == Some(true) && !column_names.is_empty() {
+        writeln!(out, "{}", column_names.join(separator))?;
+    }
+
+    while let Some(values) = rows.try_next().await? {
+        let line: Vec<String> = values.iter().map(|value| value_text(value, settings)).collect();
+        writeln!(out, "{}", line.join(separator))?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+// Prints the rows as a JSON array of objects keyed by column name, one object
+// at a time as rows arrive.
+async fn print_json(column_names: &[String], mut rows: ValueStream<'_>, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    let mut count = 0;
+
+    while let Some(values) = rows.try_next().await? {
+        let object = column_names
+            .iter()
+            .zip(&values)
+            .map(|(name, value)| (name.clone(), value_json(value, settings)))
+            .collect::<serde_json::Map<_, _>>();
+        let rendered = serde_json::to_string_pretty(&serde_json::Value::Object(object))?;
+
+        if count == 0 {
+            writeln!(out, "[")?;
+        } else {
+            writeln!(out, ",")?;
+        }
+        count += 1;
+        write!(out, "  {}", rendered.replace('\n', "\n  "))?;
+    }
+
+    if count == 0 {
+        writeln!(out, "[]")?;
+    } else {
+        writeln!(out, "\n]")?;
+    }
+    Ok(count)
+}
+
+// Prints each row as a block of `column: value` lines, for results too wide
+// to read as a table.
+async fn print_vertical(column_names: &[String], mut rows: ValueStream<'_>, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    let label_width = column_names.iter().map(|name| text_width(name)).max().unwrap_or(0);
+    let mut count = 0;
+
+    while let Some(values) = rows.try_next().await? {
+        count += 1;
+        writeln!(out, "{:*^59}", format!(" {}. row ", count))?;
+        for (name, value) in column_names.iter().zip(&values) {
+            let label = pad(name, label_width, true);
+            let label = if settings.colored { paint(&label, &settings.theme.header) } else { label };
+            writeln!(out, "{}: {}", label, paint(&value_text(value, settings), value_style(value, settings)))?;
+        }
+    }
+
+    if count == 0 {
+        writeln!(out, "No results found.")?;
+    }
+    Ok(count)
+}
+
+// Escapes text for a Markdown table cell, where `|` ends the cell and a line
+// break ends the row.
+fn markdown_cell(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+}
+
+// Prints a GitHub-flavored Markdown table, padded so that it also reads well
+// as plain text.
+async fn print_markdown(column_names: &[String], mut rows: ValueStream<'_>, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    let sample = sample_rows(&mut rows).await?;
+
+    if sample.is_empty() {
+        writeln!(out, "No results found.")?;
+        return Ok(0);
+    }
+
+    let header: Vec<String> = column_names.iter().map(|name| markdown_cell(name)).collect();
+    let mut column_widths: Vec<usize> = header.iter().map(|name| text_width(name).max(3)).collect();
+    for values in &sample {
+        for (i, value) in values.iter().enumerate() {
+            column_widths[i] = column_widths[i].max(text_width(&markdown_cell(&value_text(value, settings))));
+        }
+    }
+    let numeric = numeric_columns(&sample, column_names.len());
+
+    let print_cells = |out: &mut dyn Write, cells: &[String], align: &[bool]| -> std::io::Result<()> {
+        for (i, cell) in cells.iter().enumerate() {
+            write!(out, "| {} ", pad(cell, column_widths[i], align[i]))?;
+        }
+        writeln!(out, "|")
+    };
+    let print_row = |out: &mut dyn Write, values: &[Value]| -> std::io::Result<()> {
+        let cells: Vec<String> = values.iter().map(|value| markdown_cell(&value_text(value, settings))).collect();
+        print_cells(out, &cells, &numeric)
+    };
+
+    print_cells(out, &header, &vec![false; header.len()])?;
+    for (width, numeric) in column_widths.iter().zip(&numeric) {
+        if *numeric {
+            write!(out, "| {}: ", "-".repeat(width - 1))?;
+        } else {
+            write!(out, "| {} ", "-".repeat(*width))?;
+        }
+    }
+    writeln!(out, "|")?;
+
+    let mut count = sample.len() as u64;
+    for values in &sample {
+        print_row(out, values)?;
+    }
+    while let Some(values) = rows.try_next().await? {
+        print_row(out, &values)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// The start of a standalone HTML page for a result table, up to and
+/// including `<body>`. `title` is usually the query.
+pub fn html_page_start(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+         body {{ font-family: sans-serif; }}\n\
+         table {{ border-collapse: collapse; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: top; white-space: pre-wrap; }}\n\
+         th {{ background: #f4f4f4; }}\n\
+         td.number {{ text-align: right; }}\n\
+         td.null {{ color: #999; }}\n\
+         </style>\n</head>\n<body>\n",
+        html_escape(title)
+    )
+}
+
+pub const HTML_PAGE_END: &str = "</body>\n</html>\n";
+
+// Prints the rows as an HTML table, streaming them as they arrive. Numbers
+// and NULLs are marked with a class for styling.
+async fn print_html(column_names: &[String], mut rows: ValueStream<'_>, settings: &Settings, out: &mut dyn Write) -> Result<u64> {
+    if column_names.is_empty() {
+        writeln!(out, "<p>No results found.</p>")?;
+        return Ok(0);
+    }
+
+    writeln!(out, "<table>\n<thead>")?;
+    let header: String = column_names.iter().map(|name| format!("<th>{}</th>", html_escape(name))).collect();
+    writeln!(out, "<tr>{}</tr>", header)?;
+    writeln!(out, "</thead>\n<tbody>")?;
+
+    let mut count = 0;
+    while let Some(values) = rows.try_next().await? {
+        let cells: String = values
+            .iter()
+            .map(|value| {
+                let class = match value {
+                    Value::Null => " class=\"null\"",
+                    value if value.is_numeric() => " class=\"number\"",
+                    _ => "",
+                };
+                format!("<td{}>{}</td>", class, html_escape(&value_text(value, settings)))
+            })
+            .collect();
+        writeln!(out, "<tr>{}</tr>", cells)?;
+        count += 1;
+    }
+
+    writeln!(out, "</tbody>\n</table>")?;
+    Ok(count)
+}
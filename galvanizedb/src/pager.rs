@@ -0,0 +1,158 @@
+use std::io::Write;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use crate::error::{GalvanizeError, Result};
+
+const DEFAULT_PAGER: &str = "less -SR";
+
+/// Output that goes to stdout, unless it grows taller than the terminal, in
+/// which case everything is piped into `$PAGER` instead. Output is held back
+/// until it either fills the screen or is finished.
+pub struct Pager {
+    buffer: Vec<u8>,
+    lines: usize,
+    height: usize,
+    child: Option<Child>,
+    // Set when the pager could not be started.
+    direct: bool,
+    // Set once the pager has been quit.
+    discard: bool,
+}
+
+impl Pager {
+    pub fn new() -> Self {
+        let height = terminal_size::terminal_size().map(|(_, height)| height.0 as usize).unwrap_or(usize::MAX);
+        Pager {
+            buffer: Vec::new(),
+            lines: 0,
+            // Leave room for the prompt.
+            height: height.saturating_sub(1),
+            child: None,
+            direct: false,
+            discard: false,
+        }
+    }
+
+    fn start_pager(&mut self) -> std::io::Result<()> {
+        let command = std::env::var("PAGER").ok().filter(|pager| !pager.trim().is_empty()).unwrap_or_else(|| DEFAULT_PAGER.to_string());
+        match Command::new("sh").arg("-c").arg(&command).stdin(Stdio::piped()).spawn() {
+            Ok(child) => {
+                self.child = Some(child);
+                let buffer = std::mem::take(&mut self.buffer);
+                self.write_to_pager(&buffer)
+            },
+            Err(e) => {
+                errln!("Error starting pager '{}': {}", command, e);
+                self.direct = true;
+                std::io::stdout().write_all(&std::mem::take(&mut self.buffer))
+            },
+        }
+    }
+
+    fn write_to_pager(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        let stdin = self.child.as_mut().and_then(|child| child.stdin.as_mut());
+        match stdin.map(|stdin| stdin.write_all(buf)) {
+            // The pager was quit before reading everything; drop the rest.
+            Some(Err(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                self.discard = true;
+                Ok(())
+            },
+            Some(result) => result,
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes held back output to stdout, or waits for the pager to exit.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        match self.child.take() {
+            Some(mut child) => {
+                drop(child.stdin.take());
+                child.wait()?;
+                Ok(())
+            },
+            None => {
+                let mut stdout = std::io::stdout();
+                stdout.write_all(&self.buffer)?;
+                stdout.flush()
+            },
+        }
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.discard {
+            return Ok(buf.len());
+        }
+        crate::tee::copy(buf);
+        if self.direct {
+            return std::io::stdout().write(buf);
+        }
+        if self.child.is_some() {
+            self.write_to_pager(buf)?;
+            return Ok(buf.len());
+        }
+
+        self.buffer.extend_from_slice(buf);
+        self.lines += buf.iter().filter(|&&b| b == b'\n').count();
+        if self.lines > self.height {
+            self.start_pager()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.child.as_mut().and_then(|child| child.stdin.as_mut()) {
+            Some(stdin) => stdin.flush().or(Ok(())),
+            None if self.direct => std::io::stdout().flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Output piped into the standard input of a shell command, as with
+/// `\|` or `\pipe`. What the command prints goes straight to the terminal.
+pub struct Pipe {
+    child: Child,
+    // Set once the command has stopped reading.
+    discard: bool,
+}
+
+impl Pipe {
+    pub fn start(command: &str) -> Result<Pipe> {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| GalvanizeError::Io(format!("Error starting '{}': {}", command, e)))?;
+        Ok(Pipe { child, discard: false })
+    }
+
+    /// Closes the command's input and waits for it to exit.
+    pub fn finish(mut self) -> std::io::Result<ExitStatus> {
+        drop(self.child.stdin.take());
+        self.child.wait()
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.discard {
+            return Ok(buf.len());
+        }
+        let stdin = self.child.stdin.as_mut().expect("stdin is piped");
+        match stdin.write_all(buf) {
+            // The command exited without reading everything, as head does.
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => self.discard = true,
+            result => result?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.child.stdin.as_mut() {
+            Some(stdin) if !self.discard => stdin.flush().or(Ok(())),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use futures_util::{StreamExt, TryStreamExt};
+use sqlx::{Column, Connection, Either, Executor, Row, TypeInfo, ValueRef};
+use sqlx::postgres::{PgArguments, PgColumn, PgConnection, PgRow, Postgres};
+use sqlx::query::Query;
+use sqlx::types::chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use sqlx::types::{Decimal, JsonValue, Uuid};
+use crate::error::{GalvanizeError, Result};
+use crate::output::Value;
+use crate::session::{QueryOutcome, ResultRenderer};
+use crate::{quote_literal, returns_rows};
+
+pub const SHOW_TABLES: &str = "SELECT table_name AS name FROM information_schema.tables \
+    WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' ORDER BY table_name;";
+
+pub const SHOW_TABLES_LIKE: &str = "SELECT table_name AS name FROM information_schema.tables \
+    WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' AND table_name LIKE $1 ORDER BY table_name;";
+
+pub const SHOW_TABLES_AND_VIEWS: &str = "SELECT table_name AS \"Name\", lower(table_type) AS \"Type\" FROM information_schema.tables \
+    WHERE table_schema = current_schema() ORDER BY table_name;";
+
+// The tables, views, columns, indexes and triggers of the current schema,
+// for the \explore tree.
+pub const EXPLORE_TABLES: &str = "SELECT table_name::text, \
+        CASE table_type WHEN 'VIEW' THEN 'view' ELSE 'table' END, \
+        CASE table_type WHEN 'VIEW' THEN pg_get_viewdef(format('%I.%I', table_schema, table_name)::regclass) END \
+    FROM information_schema.tables WHERE table_schema = current_schema() ORDER BY table_name;";
+
+pub const EXPLORE_COLUMNS: &str = "SELECT c.table_name::text, c.column_name::text, c.data_type::text, c.is_nullable = 'NO', \
+        c.column_default::text, \
+        EXISTS ( \
+            SELECT 1 FROM information_schema.table_constraints tc \
+            JOIN information_schema.key_column_usage k \
+                ON k.constraint_name = tc.constraint_name AND k.table_schema = tc.table_schema \
+            WHERE tc.constraint_type = 'PRIMARY KEY' AND tc.table_schema = c.table_schema \
+                AND tc.table_name = c.table_name AND k.column_name = c.column_name \
+        ) \
+    FROM information_schema.columns c WHERE c.table_schema = current_schema() \
+    ORDER BY c.table_name, c.ordinal_position;";
+
+pub const EXPLORE_INDEXES: &str = "SELECT tablename::text, indexname::text, indexdef FROM pg_indexes \
+    WHERE schemaname = current_schema() ORDER BY tablename, indexname;";
+
+pub const EXPLORE_TRIGGERS: &str = "SELECT c.relname::text, t.tgname::text, pg_get_triggerdef(t.oid) \
+    FROM pg_trigger t JOIN pg_class c ON c.oid = t.tgrelid JOIN pg_namespace n ON n.oid = c.relnamespace \
+    WHERE NOT t.tgisinternal AND n.nspname = current_schema() ORDER BY 1, 2;";
+
+/// Whether a database argument names a PostgreSQL server rather than a file.
+pub fn is_url(name: &str) -> bool {
+    name.starts_with("postgres://") || name.starts_with("postgresql://")
+}
+
+/// The URL without its password, for display in the prompt and messages.
+pub fn display_url(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    match rest.split_once('@') {
+        Some((credentials, host)) => {
+            let user = credentials.split(':').next().unwrap_or("");
+            format!("{}://{}@{}", scheme, user, host)
+        },
+        None => url.to_string(),
+    }
+}
+
+pub async fn connect(url: &str) -> Result<PgConnection> {
+    PgConnection::connect(url)
+        .await
+        .map_err(|e| GalvanizeError::Connection(format!("Error connecting to '{}': {}", display_url(url), e)))
+}
+
+/// The process ID of the server backend serving the connection, which is
+/// what a cancel request names.
+pub async fn backend_pid(conn: &mut PgConnection) -> Result<i32> {
+    Ok(sqlx::query_scalar("SELECT pg_backend_pid();").fetch_one(conn).await?)
+}
+
+/// Whether the server only allows reading: it is a standby, or makes
+/// transactions read-only by default.
+pub async fn is_read_only(conn: &mut PgConnection) -> bool {
+    sqlx::query_scalar("SELECT pg_is_in_recovery() OR current_setting('transaction_read_only') = 'on';")
+        .fetch_one(conn)
+        .await
+        .unwrap_or(false)
+}
+
+/// Asks the server to cancel the statement running on backend `pid`. The
+/// request is sent over a connection of its own, since the one running the
+/// statement is busy.
+pub async fn cancel(url: &str, pid: i32) {
+    let result = async {
+        let mut conn = connect(url).await?;
+        sqlx::query("SELECT pg_cancel_backend($1);").bind(pid).execute(&mut conn).await?;
+        conn.close().await?;
+        Ok::<_, GalvanizeError>(())
+    };
+    if let Err(e) = result.await {
+        errln!("Error cancelling query: {}", e);
+    }
+}
+
+// Unlike SQLite, PostgreSQL columns have a fixed type, so the value is
+// decoded according to the column type. Types without a native
+// representation fall back to their text form.
+fn cell_value(row: &PgRow, col: &PgColumn) -> Value {
+    let index = col.ordinal();
+    match row.try_get_raw(index) {
+        Ok(raw) if !raw.is_null() => {},
+        _ => return Value::Null,
+    }
+
+    let text = |value: Result<String, sqlx::Error>| value.map(Value::Text);
+    let value = match col.type_info().name() {
+        "BOOL" => row.try_get(index).map(Value::Boolean),
+        "INT2" => row.try_get::<i16, _>(index).map(|v| Value::Integer(v.into())),
+        "INT4" => row.try_get::<i32, _>(index).map(|v| Value::Integer(v.into())),
+        "INT8" => row.try_get(index).map(Value::Integer),
+        "OID" => row.try_get::<sqlx::postgres::types::Oid, _>(index).map(|v| Value::Integer(v.0.into())),
+        "FLOAT4" => row.try_get::<f32, _>(index).map(|v| Value::Real(v.into())),
+        "FLOAT8" => row.try_get(index).map(Value::Real),
+        "NUMERIC" => row.try_get::<Decimal, _>(index).map(|v| Value::Decimal(v.to_string())),
+        "BYTEA" => row.try_get(index).map(Value::Blob),
+        "UUID" => text(row.try_get::<Uuid, _>(index).map(|v| v.to_string())),
+        "DATE" => text(row.try_get::<NaiveDate, _>(index).map(|v| v.to_string())),
+        "TIME" => text(row.try_get::<NaiveTime, _>(index).map(|v| v.to_string())),
+        "TIMESTAMP" => text(row.try_get::<NaiveDateTime, _>(index).map(|v| v.format("%Y-%m-%d %H:%M:%S%.f").to_string())),
+        "TIMESTAMPTZ" => text(row.try_get::<DateTime<Utc>, _>(index).map(|v| v.format("%Y-%m-%d %H:%M:%S%.f+00").to_string())),
+        "JSON" | "JSONB" => text(row.try_get::<JsonValue, _>(index).map(|v| v.to_string())),
+        _ => text(row.try_get_unchecked::<String, _>(index)),
+    };
+
+    value.unwrap_or_else(|_| Value::Text(format!("<{}>", col.type_info().name())))
+}
+
+fn row_values(row: &PgRow) -> Vec<Value> {
+    row.columns().iter().map(|col| cell_value(row, col)).collect()
+}
+
+// Binds `params` to the statement's parameters, in order.
+fn bind_values<'q>(mut query: Query<'q, Postgres, PgArguments>, params: &'q [Value]) -> Query<'q, Postgres, PgArguments> {
+    for value in params {
+        query = match value {
+            Value::Null => query.bind(None::<String>),
+            Value::Integer(v) => query.bind(*v),
+            Value::Real(v) => query.bind(*v),
+            Value::Boolean(v) => query.bind(*v),
+            Value::Decimal(v) | Value::Text(v) => query.bind(v.as_str()),
+            Value::Blob(v) => query.bind(v.as_slice()),
+        };
+    }
+    query
+}
+
+/// Runs a statement, passing any rows it returns to the renderer. Statements other than
+/// SELECT may return rows too (RETURNING, SHOW, VALUES ...), so the kind of
+/// result is decided by what the server sends back.
+pub async fn execute_sql(conn: &mut PgConnection, sql: &str, params: &[Value], renderer: &mut dyn ResultRenderer) -> Result<QueryOutcome> {
+    // Only a statement without parameters can hold several statements, since
+    // parameters need a prepared statement.
+    let mut results = if params.is_empty() {
+        sqlx::raw_sql(sql).fetch_many(&mut *conn)
+    } else {
+        conn.fetch_many(bind_values(sqlx::query(sql), params))
+    };
+    let mut rows_affected = 0;
+
+    let first = loop {
+        match results.try_next().await? {
+            Some(Either::Left(result)) => rows_affected += result.rows_affected(),
+            Some(Either::Right(row)) => break Some(row),
+            None => break None,
+        }
+    };
+
+    let first = match first {
+        Some(row) => row,
+        None if returns_rows(sql) => {
+            let count = renderer.render(&[], futures_util::stream::empty().boxed()).await?;
+            return Ok(QueryOutcome::Rows(count));
+        },
+        None => return Ok(QueryOutcome::Changes { rows_affected, last_insert_rowid: None }),
+    };
+
+    let column_names: Vec<String> = first.columns().iter().map(|col| col.name().to_string()).collect();
+    let rows = results.try_filter_map(|step| async move {
+        Ok(match step {
+            Either::Right(row) => Some(row),
+            Either::Left(_) => None,
+        })
+    });
+    let values = futures_util::stream::once(async { Ok(first) })
+        .chain(rows)
+        .map(|row| Ok(row_values(&row?)))
+        .boxed();
+
+    Ok(QueryOutcome::Rows(renderer.render(&column_names, values).await?))
+}
+
+pub async fn table_exists(conn: &mut PgConnection, table: &str) -> Result<bool> {
+    Ok(sqlx::query("SELECT 1 FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = $1;")
+        .bind(table)
+        .fetch_optional(conn)
+        .await?
+        .is_some())
+}
+
+/// The query behind DESCRIBE, with the same columns as for SQLite tables.
+pub fn describe_query(table: &str) -> String {
+    format!(
+        "SELECT c.column_name AS \"Field\", c.data_type AS \"Type\", c.is_nullable AS \"Null\", \
+            c.column_default AS \"Default\", \
+            CASE WHEN EXISTS ( \
+                SELECT 1 FROM information_schema.table_constraints tc \
+                JOIN information_schema.key_column_usage k \
+                    ON k.constraint_name = tc.constraint_name AND k.table_schema = tc.table_schema \
+                WHERE tc.constraint_type = 'PRIMARY KEY' AND tc.table_schema = c.table_schema \
+                    AND tc.table_name = c.table_name AND k.column_name = c.column_name \
+            ) THEN 'PRI' ELSE '' END AS \"Key\" \
+         FROM information_schema.columns c \
+         WHERE c.table_schema = current_schema() AND c.table_name = {} \
+         ORDER BY c.ordinal_position;",
+        quote_literal(table)
+    )
+}
+
+/// The columns of the primary key of a table in the current schema, in order.
+pub fn primary_key_query(table: &str) -> String {
+    format!(
+        "SELECT k.column_name::text FROM information_schema.table_constraints tc \
+         JOIN information_schema.key_column_usage k \
+            ON k.constraint_name = tc.constraint_name AND k.table_schema = tc.table_schema \
+         WHERE tc.constraint_type = 'PRIMARY KEY' AND tc.table_schema = current_schema() AND tc.table_name = {} \
+         ORDER BY k.ordinal_position;",
+        quote_literal(table)
+    )
+}
+
+/// The query behind SHOW INDEXES, with the same columns as for SQLite.
+pub fn show_indexes_query(table: Option<&str>) -> String {
+    format!(
+        "SELECT t.relname AS \"Table\", i.relname AS \"Index\", \
+            CASE WHEN ix.indisunique THEN 'YES' ELSE 'NO' END AS \"Unique\", \
+            (SELECT string_agg(a.attname, ', ' ORDER BY k.position) \
+                FROM unnest(ix.indkey::int2[]) WITH ORDINALITY AS k(attnum, position) \
+                JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = k.attnum) AS \"Columns\", \
+            CASE WHEN ix.indisprimary THEN 'PRIMARY KEY' \
+                WHEN EXISTS (SELECT 1 FROM pg_constraint c WHERE c.conindid = i.oid AND c.contype = 'u') THEN 'UNIQUE' \
+                ELSE 'CREATE INDEX' END AS \"Origin\" \
+         FROM pg_index ix \
+         JOIN pg_class i ON i.oid = ix.indexrelid \
+         JOIN pg_class t ON t.oid = ix.indrelid \
+         JOIN pg_namespace n ON n.oid = t.relnamespace \
+         WHERE n.nspname = current_schema(){} \
+         ORDER BY 1, 2;",
+        table.map(|table| format!(" AND t.relname = {}", quote_literal(table))).unwrap_or_default()
+    )
+}
+
+/// Collects the table, view and column names of the current schema.
+pub async fn load_schema_names(conn: &mut PgConnection) -> Result<Vec<String>> {
+    let mut names: Vec<String> = sqlx::query(
+        "SELECT table_name::text FROM information_schema.tables WHERE table_schema = current_schema() \
+         UNION SELECT column_name::text FROM information_schema.columns WHERE table_schema = current_schema();",
+    )
+    .fetch_all(conn)
+    .await?
+    .iter()
+    .map(|row| row.get::<String, _>(0))
+    .collect();
+
+    names.sort();
+    Ok(names)
+}
@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use crate::config;
+use crate::error::{GalvanizeError, Result};
+
+// Saved queries live next to the configuration file, as name = "SQL" pairs.
+fn path() -> Result<PathBuf> {
+    config::config_dir()
+        .map(|dir| dir.join("queries.toml"))
+        .ok_or_else(|| GalvanizeError::Io("No configuration directory for saved queries: HOME is not set.".to_string()))
+}
+
+/// The saved queries by name. No file just means nothing was saved yet.
+pub fn load() -> Result<BTreeMap<String, String>> {
+    let path = path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| GalvanizeError::Io(format!("Error reading '{}': {}", path.display(), e)))?;
+    toml::from_str(&text).map_err(|e| GalvanizeError::Parse(format!("Error in '{}': {}", path.display(), e)))
+}
+
+/// Saves `sql` under `name`, replacing any query saved under it before.
+pub fn save(name: &str, sql: &str) -> Result<()> {
+    let mut queries = load()?;
+    queries.insert(name.to_string(), sql.to_string());
+
+    let path = path()?;
+    let text = toml::to_string(&queries).map_err(|e| GalvanizeError::Execution(e.to_string()))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| GalvanizeError::Io(format!("Error creating '{}': {}", dir.display(), e)))?;
+    }
+    std::fs::write(&path, text).map_err(|e| GalvanizeError::Io(format!("Error writing '{}': {}", path.display(), e)))
+}
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::config;
+use crate::error::{GalvanizeError, Result};
+
+// How many databases are remembered; the least recently used go first.
+const MAX_RECENT: usize = 20;
+
+/// A database file opened in the shell, and when it was last opened.
+#[derive(Serialize, Deserialize)]
+pub struct RecentDatabase {
+    pub path: String,
+    /// Seconds since the Unix epoch.
+    pub last_used: i64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct RecentFile {
+    #[serde(default)]
+    databases: Vec<RecentDatabase>,
+}
+
+// The list lives next to the configuration file.
+fn path() -> Result<PathBuf> {
+    config::config_dir()
+        .map(|dir| dir.join("recent.toml"))
+        .ok_or_else(|| GalvanizeError::Io("No configuration directory for recent databases: HOME is not set.".to_string()))
+}
+
+/// The databases opened recently, the most recent first. No file just means
+/// none was opened yet.
+pub fn load() -> Result<Vec<RecentDatabase>> {
+    let path = path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| GalvanizeError::Io(format!("Error reading '{}': {}", path.display(), e)))?;
+    let file: RecentFile = toml::from_str(&text).map_err(|e| GalvanizeError::Parse(format!("Error in '{}': {}", path.display(), e)))?;
+    Ok(file.databases)
+}
+
+/// Moves `database` to the top of the list, as used now.
+pub fn record(database: &Path) -> Result<()> {
+    let database = std::fs::canonicalize(database).unwrap_or_else(|_| database.to_path_buf()).display().to_string();
+    let mut databases = load()?;
+    databases.retain(|recent| recent.path != database);
+    let last_used = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs() as i64);
+    databases.insert(0, RecentDatabase { path: database, last_used });
+    databases.truncate(MAX_RECENT);
+
+    let path = path()?;
+    let text = toml::to_string(&RecentFile { databases }).map_err(|e| GalvanizeError::Execution(e.to_string()))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| GalvanizeError::Io(format!("Error creating '{}': {}", dir.display(), e)))?;
+    }
+    std::fs::write(&path, text).map_err(|e| GalvanizeError::Io(format!("Error writing '{}': {}", path.display(), e)))
+}
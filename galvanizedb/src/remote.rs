@@ -0,0 +1,282 @@
+//! The remote shell: `galvanizedb serve` accepts WebSocket connections on
+//! `/shell`, and `galvanizedb connect ws://host:port` is the client.
+//!
+//! Each message is a JSON text frame. The client sends `{"line": "..."}`
+//! for each statement or command typed, and `"interrupt"` to stop the one
+//! running. The server answers with `{"stdout": "..."}` and
+//! `{"stderr": "..."}` as output is printed, then `{"ready": {"prompt": ...,
+//! "error": ..., "exit": ...}}` once the line is done. It sends `ready`
+//! once on connecting as well.
+
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use axum::extract::ws::{Message, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use rustyline::config::{Config, Configurer, EditMode};
+use rustyline::error::ReadlineError;
+use rustyline::history::FileHistory;
+use rustyline::Editor;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, mpsc, watch};
+use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest, http::HeaderValue};
+use tracing::info;
+use crate::completion::{SqlHelper, bind_keys, uppercase_keywords};
+use crate::error::{GalvanizeError, Result};
+use crate::postgres;
+use crate::settings::{Keymap, Settings};
+use crate::shell::{Flow, Shell};
+use crate::tee;
+use crate::theme::paint;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ClientMessage {
+    Line(String),
+    Interrupt,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ServerMessage {
+    Stdout(String),
+    Stderr(String),
+    // The last line is done, and the next can be sent.
+    Ready { prompt: String, error: Option<String>, exit: bool },
+}
+
+// Runs a shell connected to `database` on a thread of its own, as its
+// futures cannot move between threads, with everything it prints sent to
+// `messages`. It runs each line received, until `lines` is closed or the
+// user quits.
+fn spawn_shell(
+    database: String,
+    settings: Settings,
+    pragmas: Vec<(String, String)>,
+    mut lines: mpsc::UnboundedReceiver<String>,
+    messages: mpsc::UnboundedSender<ServerMessage>,
+    interrupt: Arc<Notify>,
+) -> Result<std::thread::JoinHandle<()>> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    Ok(std::thread::spawn(move || {
+        let output = messages.clone();
+        tee::capture(move |text, stderr| {
+            let _ = output.send(if stderr { ServerMessage::Stderr(text.to_string()) } else { ServerMessage::Stdout(text.to_string()) });
+        });
+        runtime.block_on(async move {
+            let mut shell = Shell::new(settings, false, pragmas);
+            shell.set_remote();
+            shell.set_interrupt(interrupt);
+            let connected =
+                if postgres::is_url(&database) { shell.connect_postgres(&database).await } else { shell.connect(&database).await };
+            let mut error = connected.err().map(|e| e.to_string());
+            let mut exit = error.is_some();
+            loop {
+                let _ = messages.send(ServerMessage::Ready { prompt: shell.prompt(), error: error.take(), exit });
+                if exit {
+                    break;
+                }
+                let Some(line) = lines.recv().await else { break };
+                match shell.handle_input(&line).await {
+                    Ok(Flow::Continue) => {},
+                    Ok(Flow::Exit) => exit = true,
+                    Err(e) => error = Some(e.to_string()),
+                }
+            }
+            shell.close_all().await;
+        })
+    }))
+}
+
+/// Runs a remote shell session for the client on `socket`, with a shell of
+/// its own connected to `database`, until the client leaves or `stopping`
+/// changes.
+pub(crate) async fn serve_shell(
+    socket: WebSocket,
+    database: String,
+    settings: Settings,
+    pragmas: Vec<(String, String)>,
+    mut stopping: watch::Receiver<()>,
+) {
+    let (lines, receiver) = mpsc::unbounded_channel();
+    let (sender, mut messages) = mpsc::unbounded_channel();
+    let interrupt = Arc::new(Notify::new());
+    let thread = match spawn_shell(database, settings, pragmas, receiver, sender, interrupt.clone()) {
+        Ok(thread) => thread,
+        Err(e) => {
+            tracing::warn!("Error starting a remote shell: {}", e);
+            return;
+        },
+    };
+    info!("Remote shell session started.");
+
+    let (mut sink, mut stream) = socket.split();
+    loop {
+        tokio::select! {
+            message = messages.recv() => {
+                // The shell has finished once it drops its sender.
+                let Some(message) = message else { break };
+                let Ok(text) = serde_json::to_string(&message) else { continue };
+                if sink.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            },
+            frame = stream.next() => match frame {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(ClientMessage::Line(line)) => {
+                        let _ = lines.send(line);
+                    },
+                    Ok(ClientMessage::Interrupt) => interrupt.notify_waiters(),
+                    Err(e) => tracing::warn!("Invalid message from a remote shell client: {}", e),
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {},
+            },
+            _ = stopping.changed() => {
+                let _ = sink.send(Message::Close(None)).await;
+                break;
+            },
+        }
+    }
+
+    // Whatever is still running is stopped, and the shell closes its
+    // databases once it has no more lines to run.
+    interrupt.notify_waiters();
+    drop(lines);
+    let _ = tokio::task::spawn_blocking(move || thread.join()).await;
+    info!("Remote shell session ended.");
+}
+
+// Writes output from the server as it arrives.
+fn print_output(text: &str, stderr: bool) {
+    if stderr {
+        eprint!("{}", text);
+    } else {
+        print!("{}", text);
+        let _ = std::io::stdout().flush();
+    }
+    tee::copy(text.as_bytes());
+}
+
+/// Runs an interactive shell against `galvanizedb serve` at `url`, such as
+/// `ws://host:8080`: lines are edited here, and run on the server.
+pub async fn connect(url: &str, token: Option<&str>, settings: &Settings, history_path: Option<PathBuf>, history_size: usize) -> Result<()> {
+    // The shell is served on /shell, which can be left out.
+    let url = match url.split_once("://").map(|(_, rest)| rest.trim_end_matches('/').contains('/')) {
+        Some(false) => format!("{}/shell", url.trim_end_matches('/')),
+        _ => url.to_string(),
+    };
+    let mut request = url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| GalvanizeError::Parse(format!("Invalid address '{}': {}", url, e)))?;
+    if let Some(token) = token {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| GalvanizeError::Parse("The token cannot be sent in a header.".to_string()))?;
+        request.headers_mut().insert("Authorization", value);
+    }
+    let (socket, _) = tokio_tungstenite::connect_async(request).await.map_err(|e| match e {
+        tungstenite::Error::Http(response) => GalvanizeError::Connection(format!(
+            "Error connecting to '{}': {}{}",
+            url,
+            response.status(),
+            response.body().as_deref().map(|body| format!(" {}", String::from_utf8_lossy(body))).unwrap_or_default()
+        )),
+        e => GalvanizeError::Connection(format!("Error connecting to '{}': {}", url, e)),
+    })?;
+    outln!("Connected to {}. Type help or ? to list commands.\n", url);
+    let (mut sink, mut stream) = socket.split();
+    let send = |message: ClientMessage| tungstenite::Message::Text(serde_json::to_string(&message).unwrap_or_default().into());
+
+    let config = Config::builder()
+        .max_history_size(history_size)
+        .expect("Invalid history size")
+        .history_ignore_dups(true)
+        .expect("Invalid history setting")
+        // A pasted script arrives as one input, rather than line by line, so
+        // that its statements are split and run together on Enter.
+        .bracketed_paste(true)
+        .build();
+    let mut rl = Editor::<SqlHelper, FileHistory>::with_history(config, FileHistory::new()).expect("Failed to create editor");
+    // The helper lets statements span lines; the schema is on the server,
+    // so there are no names to complete.
+    let mut helper = SqlHelper::new(Arc::new(Mutex::new(Vec::new())));
+    let colored = settings.color && std::io::stdout().is_terminal();
+    helper.set_theme(colored.then(|| settings.theme.clone()));
+    // SET runs on the server, so keywords are upper-cased as set on startup.
+    helper.set_uppercase(settings.uppercase_keywords);
+    rl.set_helper(Some(helper));
+    bind_keys(&mut rl);
+    rl.set_edit_mode(match settings.keymap {
+        Keymap::Emacs => EditMode::Emacs,
+        Keymap::Vi => EditMode::Vi,
+    });
+    if let Some(path) = &history_path {
+        let _ = rl.load_history(path);
+    }
+
+    let result = async {
+        loop {
+            // Output of the last line, until the server is ready for the next.
+            let (prompt, error, exit) = loop {
+                tokio::select! {
+                    message = stream.next() => match message {
+                        Some(Ok(tungstenite::Message::Text(text))) => match serde_json::from_str(&text) {
+                            Ok(ServerMessage::Stdout(text)) => print_output(&text, false),
+                            Ok(ServerMessage::Stderr(text)) => print_output(&text, true),
+                            Ok(ServerMessage::Ready { prompt, error, exit }) => break (prompt, error, exit),
+                            Err(e) => return Err(GalvanizeError::Connection(format!("Invalid message from the server: {}", e))),
+                        },
+                        Some(Ok(tungstenite::Message::Close(_))) | None => {
+                            return Err(GalvanizeError::Connection("The server closed the connection.".to_string()));
+                        },
+                        Some(Err(e)) => return Err(GalvanizeError::Connection(format!("Connection to the server lost: {}", e))),
+                        Some(Ok(_)) => {},
+                    },
+                    // Ctrl+C stops the statement running on the server.
+                    Ok(()) = tokio::signal::ctrl_c() => {
+                        let _ = sink.send(send(ClientMessage::Interrupt)).await;
+                    },
+                }
+            };
+            if let Some(error) = error {
+                let error = if settings.color && std::io::stderr().is_terminal() { paint(&error, &settings.theme.error) } else { error };
+                errln!("\n{}\n", error);
+            }
+            if exit {
+                return Ok(());
+            }
+
+            let prompt = if colored {
+                let text = prompt.trim_end_matches(' ');
+                format!("{}{}", paint(text, &settings.theme.prompt), &prompt[text.len()..])
+            } else {
+                prompt
+            };
+            let line = loop {
+                match rl.readline(&prompt) {
+                    Ok(line) => break line,
+                    // Ctrl+C discards the line being typed, Ctrl+D quits.
+                    Err(ReadlineError::Interrupted) => {},
+                    Err(ReadlineError::Eof) => return Ok(()),
+                    Err(e) => return Err(GalvanizeError::Io(format!("Error reading line: {}", e))),
+                }
+            };
+            let line = if settings.uppercase_keywords { uppercase_keywords(&line) } else { line };
+            let _ = rl.add_history_entry(line.as_str());
+            tee::copy(format!("{}\n", line).as_bytes());
+            sink.send(send(ClientMessage::Line(line)))
+                .await
+                .map_err(|e| GalvanizeError::Connection(format!("Connection to the server lost: {}", e)))?;
+        }
+    }
+    .await;
+    let _ = sink.send(tungstenite::Message::Close(None)).await;
+
+    if let Some(path) = &history_path {
+        if let Err(e) = rl.save_history(path) {
+            errln!("Error saving history to '{}': {}", path.display(), e);
+        }
+    }
+    result
+}
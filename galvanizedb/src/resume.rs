@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::config;
+use crate::error::{GalvanizeError, Result};
+
+/// The name sessions are saved and resumed under when none is given.
+pub const DEFAULT_NAME: &str = "default";
+
+/// Where the shell was when `\save-session` ran, to be put back with
+/// `--resume`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SavedSession {
+    /// The database file connected to, or the PostgreSQL URL without its
+    /// password.
+    pub database: Option<String>,
+    /// The files attached with ATTACH, by alias.
+    #[serde(default)]
+    pub attached: BTreeMap<String, String>,
+    /// Every setting, as given to SET.
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+    /// The variables set with \set, as SQL literals.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+}
+
+// Saved sessions live next to the configuration file, one file each.
+fn path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        return Err(GalvanizeError::Parse(format!("Invalid session name '{}': use letters, digits, _ and -.", name)));
+    }
+    config::config_dir()
+        .map(|dir| dir.join("sessions").join(format!("{}.toml", name)))
+        .ok_or_else(|| GalvanizeError::Io("No configuration directory for saved sessions: HOME is not set.".to_string()))
+}
+
+/// Saves `session` under `name`, replacing any saved under it before, and
+/// returns the file it is in.
+pub fn save(name: &str, session: &SavedSession) -> Result<PathBuf> {
+    let path = path(name)?;
+    let text = toml::to_string(session).map_err(|e| GalvanizeError::Execution(e.to_string()))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| GalvanizeError::Io(format!("Error creating '{}': {}", dir.display(), e)))?;
+    }
+    std::fs::write(&path, text).map_err(|e| GalvanizeError::Io(format!("Error writing '{}': {}", path.display(), e)))?;
+    Ok(path)
+}
+
+/// The session saved under `name`.
+pub fn load(name: &str) -> Result<SavedSession> {
+    let path = path(name)?;
+    if !path.exists() {
+        return Err(GalvanizeError::Parse(format!("No session saved as '{}'. Save one with \\save-session {}.", name, name)));
+    }
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| GalvanizeError::Io(format!("Error reading '{}': {}", path.display(), e)))?;
+    toml::from_str(&text).map_err(|e| GalvanizeError::Parse(format!("Error in '{}': {}", path.display(), e)))
+}
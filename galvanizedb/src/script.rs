@@ -0,0 +1,476 @@
+use std::collections::BTreeMap;
+use sqlformat::{FormatOptions, Indent, QueryParams};
+use crate::error::{GalvanizeError, Result};
+use crate::meta::is_meta_command;
+use crate::output::Value;
+
+/// A statement read from a script, together with the line and column it
+/// starts at.
+pub struct Statement {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+/// Splits SQL text into statements on `;` or `\G`. Terminators inside quoted
+/// strings and identifiers, PostgreSQL `$$` strings, `--` and `/* */`
+/// comments, and the BEGIN ... END body of a CREATE TRIGGER do not count.
+/// Comments between statements are left out. Each statement keeps its
+/// terminator. Backslash and dot commands also end at the end of their line.
+pub fn split_statements(input: &str) -> Vec<Statement> {
+    Scan::new(input).statements
+}
+
+/// Whether `input` stops inside a quoted string, a block comment or the body
+/// of a trigger, so that its last statement cannot be complete yet.
+pub fn is_incomplete(input: &str) -> bool {
+    Scan::new(input).incomplete
+}
+
+/// Lays out the statements in `input` for reading, one clause to a line with
+/// keywords in upper case. Backslash and dot commands are left as they are.
+pub fn format_sql(input: &str) -> String {
+    let options = FormatOptions { indent: Indent::Spaces(4), uppercase: true, lines_between_queries: 1 };
+    let statements: Vec<String> = split_statements(input)
+        .iter()
+        .map(|statement| {
+            if is_meta_command(&statement.text) {
+                return statement.text.clone();
+            }
+            // The formatter does not know \G, which would be split up.
+            match statement.text.strip_suffix("\\G") {
+                Some(text) => format!("{}\\G", sqlformat::format(text, &QueryParams::None, options)),
+                None => sqlformat::format(&statement.text, &QueryParams::None, options),
+            }
+        })
+        .collect();
+    statements.join("\n")
+}
+
+// The first words of a statement, to tell whether it creates a trigger, and
+// how deep the scan is in BEGIN ... END and CASE ... END blocks of its body.
+#[derive(Default)]
+struct Blocks {
+    words: Vec<String>,
+    depth: usize,
+}
+
+impl Blocks {
+    fn word(&mut self, word: &str) {
+        let word = word.to_lowercase();
+        if self.words.len() < 3 {
+            self.words.push(word.clone());
+        }
+        if !self.in_trigger() {
+            return;
+        }
+        match word.as_str() {
+            "begin" => self.depth += 1,
+            "case" if self.depth > 0 => self.depth += 1,
+            "end" if self.depth > 0 => self.depth -= 1,
+            _ => {},
+        }
+    }
+
+    fn in_trigger(&self) -> bool {
+        let words: Vec<&str> = self.words.iter().map(String::as_str).collect();
+        matches!(words.as_slice(), ["create", "trigger", ..] | ["create", "temp" | "temporary", "trigger", ..])
+    }
+}
+
+// The `$tag$` that opens a dollar-quoted string at the start of `rest`, if
+// one does; `$1` and `$name` parameters do not.
+pub(crate) fn dollar_quote(rest: &str) -> Option<&str> {
+    let end = rest.get(1..)?.find('$')? + 1;
+    let tag = &rest[1..end];
+    let valid = tag.chars().all(|c| c.is_alphanumeric() || c == '_') && !tag.starts_with(|c: char| c.is_ascii_digit());
+    valid.then(|| &rest[..=end])
+}
+
+struct Scan {
+    statements: Vec<Statement>,
+    incomplete: bool,
+}
+
+impl Scan {
+    fn new(input: &str) -> Scan {
+        let mut statements = Vec::new();
+        let mut push = |text: &str, line: usize, column: usize| {
+            // A lone `;`, e.g. after `\G`, is an empty statement.
+            if !text.is_empty() && text != ";" {
+                statements.push(Statement { line, column, text: text.to_string() });
+            }
+        };
+        // Byte offset, line and column of the statement being read, if any.
+        let mut start: Option<(usize, usize, usize)> = None;
+        let (mut line, mut column) = (1, 1);
+        // What ends the quoted string or comment being read, if any.
+        let mut closing: Option<String> = None;
+        let mut blocks = Blocks::default();
+        let mut word: Option<usize> = None;
+        let mut i = 0;
+
+        while let Some(c) = input[i..].chars().next() {
+            let rest = &input[i..];
+            let mut step = c.len_utf8();
+
+            if c == '\n' {
+                if let Some((at, start_line, start_column)) = start.filter(|(at, ..)| is_meta_command(&input[*at..i])) {
+                    push(input[at..i].trim(), start_line, start_column);
+                    start = None;
+                    closing = None;
+                }
+            }
+
+            match closing.as_deref() {
+                // A line comment ends before the newline, which is read as usual.
+                Some("\n") if c == '\n' => closing = None,
+                Some(end) if rest.starts_with(end) => {
+                    step = end.len();
+                    closing = None;
+                },
+                Some(_) => {},
+                None => {
+                    let word_char = c.is_alphanumeric() || c == '_';
+                    if !word_char {
+                        if let Some(at) = word.take() {
+                            blocks.word(&input[at..i]);
+                        }
+                    }
+
+                    if rest.starts_with("--") || rest.starts_with("/*") {
+                        closing = Some(if c == '-' { "\n" } else { "*/" }.to_string());
+                        step = 2;
+                    } else if start.is_some() || !c.is_whitespace() {
+                        let (at, start_line, start_column) = *start.get_or_insert_with(|| {
+                            blocks = Blocks::default();
+                            (i, line, column)
+                        });
+                        match c {
+                            'G' if input[..i].ends_with('\\') => {
+                                push(input[at..=i].trim(), start_line, start_column);
+                                start = None;
+                            },
+                            _ if word_char => {
+                                word.get_or_insert(i);
+                            },
+                            '\'' | '"' | '`' => closing = Some(c.to_string()),
+                            '[' => closing = Some("]".to_string()),
+                            '$' => {
+                                if let Some(tag) = dollar_quote(rest) {
+                                    closing = Some(tag.to_string());
+                                    step = tag.len();
+                                }
+                            },
+                            ';' if blocks.depth == 0 => {
+                                push(input[at..=i].trim(), start_line, start_column);
+                                start = None;
+                            },
+                            _ => {},
+                        }
+                    }
+                },
+            }
+
+            for c in input[i..i + step].chars() {
+                if c == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+            }
+            i += step;
+        }
+
+        if let Some(at) = word.take() {
+            blocks.word(&input[at..]);
+        }
+        // An open line comment ends with the input, and a backslash or dot
+        // command with its line.
+        let meta = start.is_some_and(|(at, ..)| is_meta_command(&input[at..]));
+        let incomplete = !meta && (closing.as_deref().is_some_and(|end| end != "\n") || blocks.depth > 0);
+        if let Some((at, start_line, start_column)) = start {
+            push(input[at..].trim(), start_line, start_column);
+        }
+        Scan { statements, incomplete }
+    }
+}
+
+// The words of a statement outside quotes and comments, lower-cased, each
+// with how many parentheses it is inside.
+fn words(sql: &str) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut depth: usize = 0;
+    let mut rest = sql;
+    while let Some(c) = rest.chars().next() {
+        let skip = match c {
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                rest[1..].find(close).map_or(rest.len(), |end| end + 2)
+            },
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest.find("*/").map_or(rest.len(), |end| end + 2),
+            '$' if dollar_quote(rest).is_some() => {
+                let tag = dollar_quote(rest).unwrap_or_default();
+                rest[tag.len()..].find(tag).map_or(rest.len(), |end| end + 2 * tag.len())
+            },
+            '(' => {
+                depth += 1;
+                1
+            },
+            ')' => {
+                depth = depth.saturating_sub(1);
+                1
+            },
+            c if c.is_alphanumeric() || c == '_' => {
+                let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                words.push((depth, rest[..end].to_lowercase()));
+                end
+            },
+            _ => c.len_utf8(),
+        };
+        rest = &rest[skip..];
+    }
+    words
+}
+
+// The first word of the statement that `words` make up, or of the one that
+// a leading WITH or EXPLAIN leads up to.
+fn main_statement(words: &[(usize, String)]) -> Option<&str> {
+    let first = words.first().map(|(_, word)| word.as_str());
+    if !matches!(first, Some("with" | "explain")) {
+        return first;
+    }
+    words
+        .iter()
+        .skip(1)
+        .find(|(depth, word)| *depth == 0 && ["select", "values", "insert", "update", "delete", "replace", "merge"].contains(&word.as_str()))
+        .map(|(_, word)| word.as_str())
+}
+
+/// Whether `sql` gives back rows, even when none match: a SELECT or VALUES,
+/// a WITH whose main statement is one, an EXPLAIN, or a statement with a
+/// RETURNING clause.
+pub(crate) fn returns_rows(sql: &str) -> bool {
+    let words = words(sql);
+    matches!(words.first().map(|(_, word)| word.as_str()), Some("explain"))
+        || matches!(main_statement(&words), Some("select" | "values"))
+        || words.iter().any(|(depth, word)| *depth == 0 && word == "returning")
+}
+
+/// Whether `sql` returns rows and only reads: one that changes nothing,
+/// even in a WITH, locks no rows, and is not an EXPLAIN ANALYZE that would
+/// run such a statement.
+pub(crate) fn is_query(sql: &str) -> bool {
+    let words = words(sql);
+    let writes = words.iter().any(|(_, word)| ["insert", "update", "delete", "merge"].contains(&word.as_str()));
+    let analyze = words.iter().take(4).any(|(_, word)| word == "analyze");
+    match words.first().map(|(_, word)| word.as_str()) {
+        Some("explain") if !analyze => true,
+        _ => !writes && matches!(main_statement(&words), Some("select" | "values")),
+    }
+}
+
+/// Replaces `:name` variables in a statement with numbered bind parameters
+/// (`$1`, `$2`, ...), and `?` placeholders too if `positional` values are
+/// given. Returns the rewritten statement and the values to bind to it.
+/// Quoted strings and identifiers, comments and `::` casts are left alone.
+pub fn bind_parameters(sql: &str, variables: &BTreeMap<String, Value>, positional: Option<&[Value]>) -> Result<(String, Vec<Value>)> {
+    let mut statement = String::with_capacity(sql.len());
+    let mut values = Vec::new();
+    let mut numbers: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut placeholders = 0;
+    let mut rest = sql;
+
+    while let Some(c) = rest.chars().next() {
+        let skip = match c {
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                rest[1..].find(close).map_or(rest.len(), |end| end + 2)
+            },
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest.find("*/").map_or(rest.len(), |end| end + 2),
+            ':' if rest.starts_with("::") => 2,
+            ':' if rest[1..].starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                let end = rest[1..].find(|c: char| !(c.is_alphanumeric() || c == '_')).map_or(rest.len(), |end| end + 1);
+                let name = &rest[1..end];
+                let value = variables
+                    .get(name)
+                    .ok_or_else(|| GalvanizeError::Parse(format!("Unknown variable ':{}'. Set it with \\set {} value.", name, name)))?;
+                // A variable used twice is bound once.
+                let number = *numbers.entry(name).or_insert_with(|| {
+                    values.push(value.clone());
+                    values.len()
+                });
+                statement.push_str(&format!("${}", number));
+                rest = &rest[end..];
+                continue;
+            },
+            '?' if positional.is_some() => {
+                placeholders += 1;
+                if let Some(value) = positional.and_then(|values| values.get(placeholders - 1)) {
+                    values.push(value.clone());
+                    statement.push_str(&format!("${}", values.len()));
+                }
+                rest = &rest[1..];
+                continue;
+            },
+            _ => c.len_utf8(),
+        };
+        statement.push_str(&rest[..skip]);
+        rest = &rest[skip..];
+    }
+
+    if let Some(positional) = positional.filter(|values| values.len() != placeholders) {
+        return Err(GalvanizeError::Parse(format!(
+            "BIND gave {} values, but the statement has {} ? placeholders.",
+            positional.len(),
+            placeholders
+        )));
+    }
+    Ok((statement, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(input: &str) -> Vec<String> {
+        split_statements(input).into_iter().map(|statement| statement.text).collect()
+    }
+
+    #[test]
+    fn splits_on_semicolons() {
+        assert_eq!(texts("SELECT 1; SELECT 2;\nSELECT 3"), ["SELECT 1;", "SELECT 2;", "SELECT 3"]);
+    }
+
+    #[test]
+    fn ignores_semicolons_in_quotes() {
+        assert_eq!(texts("SELECT 'a;b'; SELECT 2;"), ["SELECT 'a;b';", "SELECT 2;"]);
+        assert_eq!(texts("SELECT \"a;b\" FROM t; SELECT 2;"), ["SELECT \"a;b\" FROM t;", "SELECT 2;"]);
+        assert_eq!(texts("SELECT [a;b] FROM t; SELECT 2;"), ["SELECT [a;b] FROM t;", "SELECT 2;"]);
+        assert_eq!(texts("SELECT $$a;b$$; SELECT $x$;$x$;"), ["SELECT $$a;b$$;", "SELECT $x$;$x$;"]);
+    }
+
+    #[test]
+    fn ignores_semicolons_in_comments() {
+        assert_eq!(texts("SELECT 1 -- one; two\n; SELECT 2;"), ["SELECT 1 -- one; two\n;", "SELECT 2;"]);
+        assert_eq!(texts("SELECT /* ; */ 1;"), ["SELECT /* ; */ 1;"]);
+        // Comments between statements are left out.
+        assert_eq!(texts("-- first\nSELECT 1;\n/* second */ SELECT 2;"), ["SELECT 1;", "SELECT 2;"]);
+    }
+
+    #[test]
+    fn keeps_trigger_bodies_whole() {
+        let trigger = "CREATE TRIGGER t AFTER INSERT ON a BEGIN \
+            UPDATE b SET n = CASE WHEN n > 0 THEN n END; \
+            INSERT INTO c VALUES (1); END;";
+        assert_eq!(texts(&format!("{} SELECT 1;", trigger)), [trigger, "SELECT 1;"]);
+        assert_eq!(texts("BEGIN; SELECT 1; END;"), ["BEGIN;", "SELECT 1;", "END;"]);
+    }
+
+    #[test]
+    fn splits_on_vertical_terminator() {
+        assert_eq!(texts("SELECT 1\\G SELECT 2;"), ["SELECT 1\\G", "SELECT 2;"]);
+        assert_eq!(texts("SELECT 1\\G;"), ["SELECT 1\\G"]);
+    }
+
+    #[test]
+    fn ends_meta_commands_at_the_newline() {
+        assert_eq!(texts("\\tables\nSELECT 1;"), ["\\tables", "SELECT 1;"]);
+        assert_eq!(texts(".mode csv\nSELECT 1;"), [".mode csv", "SELECT 1;"]);
+    }
+
+    #[test]
+    fn records_where_statements_start() {
+        let statements = split_statements("SELECT 1;\n  SELECT 2;");
+        assert_eq!((statements[1].line, statements[1].column), (2, 3));
+    }
+
+    #[test]
+    fn tells_incomplete_input() {
+        assert!(is_incomplete("SELECT 'abc"));
+        assert!(is_incomplete("SELECT \"abc"));
+        assert!(is_incomplete("SELECT $$abc"));
+        assert!(is_incomplete("SELECT 1 /* comment"));
+        assert!(is_incomplete("CREATE TRIGGER t AFTER INSERT ON a BEGIN SELECT 1;"));
+        assert!(!is_incomplete("SELECT 'abc';"));
+        assert!(!is_incomplete("SELECT 1 -- comment"));
+        assert!(!is_incomplete("\\echo 'abc"));
+    }
+
+    fn variables() -> BTreeMap<String, Value> {
+        BTreeMap::from([("id".to_string(), Value::Integer(7)), ("name".to_string(), Value::Text("ann".to_string()))])
+    }
+
+    #[test]
+    fn binds_variables() {
+        let (sql, values) = bind_parameters("SELECT * FROM t WHERE id = :id AND name = :name OR id = :id;", &variables(), None).unwrap();
+        assert_eq!(sql, "SELECT * FROM t WHERE id = $1 AND name = $2 OR id = $1;");
+        assert_eq!(values, [Value::Integer(7), Value::Text("ann".to_string())]);
+    }
+
+    #[test]
+    fn leaves_quotes_comments_and_casts_alone() {
+        let sql = "SELECT ':id', \":id\", [:id], `:id`, x::text -- :id\n/* :id ? */ FROM t;";
+        let (bound, values) = bind_parameters(sql, &variables(), Some(&[])).unwrap();
+        assert_eq!(bound, sql);
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn binds_positional_values() {
+        let positional = [Value::Integer(1), Value::Null];
+        let (sql, values) = bind_parameters("SELECT ?, :id, '?', ?;", &variables(), Some(&positional)).unwrap();
+        assert_eq!(sql, "SELECT $1, $2, '?', $3;");
+        assert_eq!(values, [Value::Integer(1), Value::Integer(7), Value::Null]);
+        // Without BIND, `?` is left for the database.
+        assert_eq!(bind_parameters("SELECT ?;", &variables(), None).unwrap().0, "SELECT ?;");
+    }
+
+    #[test]
+    fn rejects_unknown_variables_and_wrong_counts() {
+        assert!(bind_parameters("SELECT :missing;", &variables(), None).is_err());
+        assert!(bind_parameters("SELECT ?, ?;", &variables(), Some(&[Value::Integer(1)])).is_err());
+        assert!(bind_parameters("SELECT 1;", &variables(), Some(&[Value::Integer(1)])).is_err());
+    }
+
+    #[test]
+    fn tells_queries() {
+        for sql in [
+            "SELECT 1;",
+            "  values (1), (2)",
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) SELECT i FROM n;",
+            "EXPLAIN QUERY PLAN SELECT 1;",
+            "EXPLAIN DELETE FROM t;",
+            "EXPLAIN ANALYZE SELECT 1;",
+        ] {
+            assert!(is_query(sql), "{}", sql);
+            assert!(returns_rows(sql), "{}", sql);
+        }
+        for sql in [
+            "WITH a AS (SELECT 1) DELETE FROM t WHERE x IN (SELECT * FROM a);",
+            "INSERT INTO t SELECT * FROM u;",
+            "CREATE TABLE t AS SELECT 1;",
+            "UPDATE t SET \"returning\" = 'returning';",
+            "-- SELECT\nDELETE FROM t;",
+        ] {
+            assert!(!is_query(sql), "{}", sql);
+            assert!(!returns_rows(sql), "{}", sql);
+        }
+        assert!(!is_query("INSERT INTO t VALUES (1) RETURNING id;"));
+        assert!(returns_rows("INSERT INTO t VALUES (1) RETURNING id;"));
+        assert!(returns_rows("WITH a AS (SELECT 1) DELETE FROM t RETURNING *;"));
+        // Rows are returned, but a change is made or rows are locked.
+        for sql in [
+            "WITH a AS (DELETE FROM t RETURNING *) SELECT * FROM a;",
+            "SELECT * FROM t FOR UPDATE;",
+            "EXPLAIN ANALYZE DELETE FROM t;",
+            "EXPLAIN (ANALYZE) DELETE FROM t;",
+        ] {
+            assert!(!is_query(sql), "{}", sql);
+            assert!(returns_rows(sql), "{}", sql);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+use crate::error::{GalvanizeError, Result};
+use crate::{quote_identifier, quote_literal};
+
+// Trigger suffixes and the events that keep a search index in step with its
+// table.
+const TRIGGERS: [(&str, &str); 3] = [("insert", "INSERT"), ("delete", "DELETE"), ("update", "UPDATE")];
+
+/// The name of the FTS5 table that indexes `table`.
+pub fn index_name(table: &str) -> String {
+    format!("{}_fts", table)
+}
+
+async fn column_names(conn: &mut SqliteConnection, table: &str) -> Result<Vec<String>> {
+    Ok(sqlx::query("SELECT name FROM pragma_table_info(?) ORDER BY cid;")
+        .bind(table)
+        .fetch_all(conn)
+        .await?
+        .iter()
+        .map(|row| row.get::<String, _>("name"))
+        .collect())
+}
+
+async fn index_exists(conn: &mut SqliteConnection, table: &str) -> Result<bool> {
+    Ok(sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?;")
+        .bind(index_name(table))
+        .fetch_optional(conn)
+        .await?
+        .is_some())
+}
+
+async fn execute_all(conn: &mut SqliteConnection, statements: &[String]) -> Result<()> {
+    for statement in statements {
+        sqlx::query(statement).execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+// Runs the statements under a savepoint, so they all take effect or none do,
+// also when a transaction is already open.
+async fn execute_atomically(conn: &mut SqliteConnection, statements: &[String]) -> Result<()> {
+    sqlx::query("SAVEPOINT search_index;").execute(&mut *conn).await?;
+
+    match execute_all(conn, statements).await {
+        Ok(()) => {
+            sqlx::query("RELEASE search_index;").execute(&mut *conn).await?;
+            Ok(())
+        },
+        Err(e) => {
+            sqlx::query("ROLLBACK TO search_index;").execute(&mut *conn).await?;
+            sqlx::query("RELEASE search_index;").execute(&mut *conn).await?;
+            Err(e)
+        },
+    }
+}
+
+/// Creates an FTS5 index over `columns` of `table`, filled with the rows the
+/// table has now and kept up to date by triggers. The index stores no copy of
+/// the text; it reads it from the table.
+pub async fn create_search_index(conn: &mut SqliteConnection, table: &str, columns: &[String]) -> Result<()> {
+    let existing = column_names(conn, table).await?;
+    if existing.is_empty() {
+        return Err(GalvanizeError::Execution(format!("Table '{}' does not exist.", table)));
+    }
+    if let Some(column) = columns.iter().find(|column| !existing.iter().any(|name| name.eq_ignore_ascii_case(column))) {
+        return Err(GalvanizeError::Execution(format!("Table '{}' has no column '{}'.", table, column)));
+    }
+    if index_exists(conn, table).await? {
+        return Err(GalvanizeError::Execution(format!(
+            "Table '{}' already has a search index. Remove it first with DROP SEARCH INDEX ON {};",
+            table, table
+        )));
+    }
+
+    let index = quote_identifier(&index_name(table));
+    let quoted_table = quote_identifier(table);
+    let column_list = columns.iter().map(|column| quote_identifier(column)).collect::<Vec<_>>().join(", ");
+    let values = |row: &str| columns.iter().map(|column| format!("{}.{}", row, quote_identifier(column))).collect::<Vec<_>>().join(", ");
+    let insert = format!("INSERT INTO {}(rowid, {}) VALUES (new.rowid, {});", index, column_list, values("new"));
+    let delete = format!("INSERT INTO {}({}, rowid, {}) VALUES ('delete', old.rowid, {});", index, index, column_list, values("old"));
+
+    let mut statements = vec![
+        format!("CREATE VIRTUAL TABLE {} USING fts5({}, content={});", index, column_list, quote_literal(table)),
+        format!("INSERT INTO {}({}) VALUES ('rebuild');", index, index),
+    ];
+    for (suffix, event) in TRIGGERS {
+        let body = match suffix {
+            "insert" => insert.clone(),
+            "delete" => delete.clone(),
+            _ => format!("{} {}", delete, insert),
+        };
+        statements.push(format!(
+            "CREATE TRIGGER {} AFTER {} ON {} BEGIN {} END;",
+            quote_identifier(&format!("{}_{}", index_name(table), suffix)), event, quoted_table, body
+        ));
+    }
+    execute_atomically(conn, &statements).await
+}
+
+/// Drops the search index of `table` and the triggers that maintain it.
+pub async fn drop_search_index(conn: &mut SqliteConnection, table: &str) -> Result<()> {
+    if !index_exists(conn, table).await? {
+        return Err(GalvanizeError::Execution(format!("Table '{}' has no search index.", table)));
+    }
+    let mut statements: Vec<String> = TRIGGERS
+        .iter()
+        .map(|(suffix, _)| format!("DROP TRIGGER IF EXISTS {};", quote_identifier(&format!("{}_{}", index_name(table), suffix))))
+        .collect();
+    statements.push(format!("DROP TABLE {};", quote_identifier(&index_name(table))));
+    execute_atomically(conn, &statements).await
+}
+
+/// A query for the rows of `table` that match the full-text query bound to
+/// `$1`, best matches first, with the matching terms marked in [brackets].
+pub async fn search_query(conn: &mut SqliteConnection, table: &str) -> Result<String> {
+    if !index_exists(conn, table).await? {
+        return Err(GalvanizeError::Execution(format!(
+            "Table '{}' has no search index. Create one with CREATE SEARCH INDEX ON {}(column, ...);",
+            table, table
+        )));
+    }
+    let index = quote_identifier(&index_name(table));
+    let columns = column_names(conn, &index_name(table)).await?;
+    let highlights = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("highlight({}, {}, '[', ']') AS {}", index, i, quote_identifier(column)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok(format!("SELECT rowid, {} FROM {} WHERE {} MATCH $1 ORDER BY rank;", highlights, index, index))
+}
@@ -0,0 +1,501 @@
+//! `galvanizedb serve`: a database behind a small HTTP API that takes and
+//! returns JSON.
+//!
+//! * `POST /query` runs one statement, given as `{"sql": "...", "params": [...]}`.
+//! * `GET /tables` lists the tables and views.
+//! * `GET /schema` lists them with their columns and CREATE statements.
+//! * `GET /shell` is a WebSocket for `galvanizedb connect`, served only with a
+//!   token; see [`crate::remote`].
+//!
+//! `galvanizedb web` serves the same API but the shell, and a page at `/`
+//! that uses it. The page is given a token in the link printed at startup,
+//! and only requests for, and from, `127.0.0.1` or `localhost` are served.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, json};
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::info;
+use crate::error::{GalvanizeError, Result};
+use crate::output::{Value, value_json};
+use crate::script::{is_query, split_statements};
+use crate::session::{QueryResult, Session};
+use crate::settings::Settings;
+use crate::{postgres, remote, returns_rows};
+
+/// How the server listens and what it lets clients do.
+pub struct ServeOptions {
+    pub listen: SocketAddr,
+    /// Token that requests must carry as `Authorization: Bearer <token>`.
+    pub token: Option<String>,
+    /// Whether statements that change the database are refused.
+    pub read_only: bool,
+    /// Settings for remote shells, which start with these.
+    pub settings: Settings,
+    /// Pragmas run on each SQLite connection, as for [`Session::open_sqlite`].
+    pub pragmas: Vec<(String, String)>,
+    /// Whether the web UI is served at `/`, instead of the remote shell.
+    pub web: bool,
+}
+
+// The web UI: one page, with its styles and script, built into the binary.
+const WEB_PAGE: &str = include_str!("web.html");
+
+// A statement for the database thread, and where to send its result.
+struct Statement {
+    sql: String,
+    params: Vec<Value>,
+    reply: oneshot::Sender<Result<QueryResult>>,
+}
+
+struct Server {
+    // Statements run one at a time, on the one session, which lives on a
+    // thread of its own as its futures cannot move between threads.
+    db: mpsc::Sender<Statement>,
+    postgres: bool,
+    token: Option<String>,
+    read_only: bool,
+    // Port of the web UI, if it is served.
+    web_port: Option<u16>,
+    // Blobs are sent whole, as hex.
+    settings: Settings,
+    // What remote shells connect to, and how.
+    database: String,
+    shell_settings: Settings,
+    pragmas: Vec<(String, String)>,
+    // Changes, as its sender is dropped, when the server is stopping.
+    stopping: watch::Receiver<()>,
+}
+
+impl Server {
+    async fn execute(&self, sql: &str, params: Vec<Value>) -> Result<QueryResult> {
+        let (reply, result) = oneshot::channel();
+        let stopped = || GalvanizeError::Connection("The database is closed.".to_string());
+        self.db.send(Statement { sql: sql.to_string(), params, reply }).await.map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+}
+
+// Runs the statements sent to it on `db`, until the server is dropped. With
+// `read_only`, each runs in a read-only transaction that is then rolled back.
+fn spawn_database(mut db: Session, read_only: bool) -> Result<(mpsc::Sender<Statement>, std::thread::JoinHandle<()>)> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let (sender, mut statements) = mpsc::channel::<Statement>(16);
+    let thread = std::thread::spawn(move || {
+        runtime.block_on(async {
+            while let Some(statement) = statements.recv().await {
+                let result = if read_only {
+                    execute_read_only(&mut db, &statement.sql, &statement.params).await
+                } else {
+                    db.execute_sql_with(&statement.sql, &statement.params).await
+                };
+                let _ = statement.reply.send(result);
+            }
+            db.close().await;
+        })
+    });
+    Ok((sender, thread))
+}
+
+// A SELECT can still change a PostgreSQL database, through a data-modifying
+// WITH or by calling set_config to turn off the session's read-only default.
+// In a read-only transaction the first fails, and the rollback undoes the
+// second.
+async fn execute_read_only(db: &mut Session, sql: &str, params: &[Value]) -> Result<QueryResult> {
+    db.execute_sql("BEGIN READ ONLY;").await?;
+    let result = db.execute_sql_with(sql, params).await;
+    db.rollback().await?;
+    result
+}
+
+#[derive(Deserialize)]
+struct QueryRequest {
+    sql: String,
+    // Values for the statement's `?` or `$1` placeholders.
+    #[serde(default)]
+    params: Vec<JsonValue>,
+}
+
+// An error sent back as `{"error": "..."}` with a status for its kind.
+struct ApiError(StatusCode, String);
+
+impl From<GalvanizeError> for ApiError {
+    fn from(e: GalvanizeError) -> Self {
+        let status = match e {
+            GalvanizeError::Parse(_) | GalvanizeError::Execution(_) | GalvanizeError::UnsupportedType(_) => StatusCode::BAD_REQUEST,
+            GalvanizeError::Busy(_) => StatusCode::SERVICE_UNAVAILABLE,
+            GalvanizeError::Connection(_) | GalvanizeError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn parameter(value: JsonValue) -> Value {
+    match value {
+        JsonValue::Null => Value::Null,
+        JsonValue::Bool(v) => Value::Boolean(v),
+        JsonValue::Number(v) => match v.as_i64() {
+            Some(v) => Value::Integer(v),
+            None => Value::Real(v.as_f64().unwrap_or(f64::NAN)),
+        },
+        JsonValue::String(v) => Value::Text(v),
+        other => Value::Text(other.to_string()),
+    }
+}
+
+fn first_word(sql: &str) -> String {
+    sql.trim_start().split(|c: char| !c.is_alphanumeric()).next().unwrap_or("").to_lowercase()
+}
+
+// Compares tokens in a time that does not depend on where they differ.
+fn same_token(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Whether a request to the web UI on `port` is for this machine and comes
+// from its own page. Pages elsewhere can reach localhost too, and even take
+// its place under their own name (DNS rebinding), but not these headers.
+fn local_request(request: &Request, port: u16) -> bool {
+    let hosts = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    let header = |name| request.headers().get(name).and_then(|value: &header::HeaderValue| value.to_str().ok());
+    let host = header(header::HOST).is_some_and(|host| hosts.iter().any(|local| local == host));
+    let origin = header(header::ORIGIN).is_none_or(|origin| hosts.iter().any(|local| origin.strip_prefix("http://") == Some(local)));
+    host && origin
+}
+
+async fn authorize(State(server): State<Arc<Server>>, request: Request, next: Next) -> Response {
+    if let Some(port) = server.web_port {
+        if !local_request(&request, port) {
+            return ApiError(StatusCode::FORBIDDEN, "Requests are only served for and from this machine.".to_string()).into_response();
+        }
+        // The page itself holds nothing, and reads the token from the link.
+        if request.uri().path() == "/" {
+            return next.run(request).await;
+        }
+    }
+    if let Some(token) = &server.token {
+        let given = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !given.is_some_and(|given| same_token(given.trim().as_bytes(), token.as_bytes())) {
+            let mut response = ApiError(StatusCode::UNAUTHORIZED, "A valid bearer token is required.".to_string()).into_response();
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+            return response;
+        }
+    }
+    next.run(request).await
+}
+
+async fn query(State(server): State<Arc<Server>>, Json(request): Json<QueryRequest>) -> Result<Json<JsonValue>, ApiError> {
+    let sql = request.sql.trim();
+    if split_statements(sql).len() != 1 {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "Send exactly one statement per request.".to_string()));
+    }
+    // Each request stands alone, so a transaction would be left open
+    // between them, holding locks.
+    let first_word = first_word(sql);
+    if ["begin", "commit", "end", "rollback", "savepoint", "release"].contains(&first_word.as_str()) {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "Transactions cannot span requests; send one statement at a time.".to_string()));
+    }
+    if server.read_only && !is_query(sql) {
+        return Err(ApiError(StatusCode::FORBIDDEN, "The server is read-only.".to_string()));
+    }
+
+    let params: Vec<Value> = request.params.into_iter().map(parameter).collect();
+    let result = server.execute(sql, params).await;
+    info!(sql, ok = result.is_ok(), "Ran a statement for an HTTP client.");
+    let result = result?;
+    Ok(Json(if returns_rows(sql) {
+        let rows: Vec<JsonValue> = result
+            .rows
+            .iter()
+            .map(|row| JsonValue::Array(row.iter().map(|value| value_json(value, &server.settings)).collect()))
+            .collect();
+        json!({ "columns": result.columns, "rows": rows })
+    } else {
+        json!({ "rows_affected": result.rows_affected, "last_insert_rowid": result.last_insert_rowid })
+    }))
+}
+
+async fn tables(State(server): State<Arc<Server>>) -> Result<Json<JsonValue>, ApiError> {
+    let query = if server.postgres {
+        postgres::SHOW_TABLES_AND_VIEWS
+    } else {
+        "SELECT name, type FROM sqlite_master \
+            WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY name;"
+    };
+    let tables: Vec<JsonValue> = server
+        .execute(query, Vec::new())
+        .await?
+        .rows
+        .iter()
+        .map(|row| json!({ "name": value_json(&row[0], &server.settings), "type": value_json(&row[1], &server.settings) }))
+        .collect();
+    Ok(Json(json!({ "tables": tables })))
+}
+
+async fn schema(State(server): State<Arc<Server>>) -> Result<Json<JsonValue>, ApiError> {
+    // Both give the same columns: name, type and CREATE statement of each
+    // table, then its columns' table, name, type, NOT NULL, default and
+    // primary key.
+    let (tables, columns) = if server.postgres {
+        (server.execute(postgres::EXPLORE_TABLES, Vec::new()).await?, server.execute(postgres::EXPLORE_COLUMNS, Vec::new()).await?)
+    } else {
+        let tables = server
+            .execute(
+                "SELECT name, type, sql FROM sqlite_master \
+                 WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY name;",
+                Vec::new(),
+            )
+            .await?;
+        let columns = server
+            .execute(
+                "SELECT m.name, p.name, p.type, p.\"notnull\", p.dflt_value, p.pk > 0 \
+                 FROM sqlite_master m, pragma_table_info(m.name) p \
+                 WHERE m.type IN ('table', 'view') ORDER BY m.name, p.cid;",
+                Vec::new(),
+            )
+            .await?;
+        (tables, columns)
+    };
+
+    let json = |value: &Value| value_json(value, &server.settings);
+    let as_bool = |value: &Value| matches!(value, Value::Boolean(true)) || matches!(value, Value::Integer(v) if *v != 0);
+    let tables: Vec<JsonValue> = tables
+        .rows
+        .iter()
+        .map(|table| {
+            let columns: Vec<JsonValue> = columns
+                .rows
+                .iter()
+                .filter(|column| column[0] == table[0])
+                .map(|column| {
+                    json!({
+                        "name": json(&column[1]),
+                        "type": json(&column[2]),
+                        "not_null": as_bool(&column[3]),
+                        "default": json(&column[4]),
+                        "primary_key": as_bool(&column[5]),
+                    })
+                })
+                .collect();
+            json!({ "name": json(&table[0]), "type": json(&table[1]), "sql": json(&table[2]), "columns": columns })
+        })
+        .collect();
+    Ok(Json(json!({ "tables": tables })))
+}
+
+// Each client gets a shell of its own, with its own connection.
+async fn shell(State(server): State<Arc<Server>>, headers: HeaderMap, upgrade: WebSocketUpgrade) -> Result<Response, ApiError> {
+    check_shell_request(&server, &headers)?;
+    let (database, settings, pragmas) = (server.database.clone(), server.shell_settings.clone(), server.pragmas.clone());
+    let stopping = server.stopping.clone();
+    Ok(upgrade.on_upgrade(move |socket| remote::serve_shell(socket, database, settings, pragmas, stopping)))
+}
+
+// The shell runs any statement, so it is not offered when the database is
+// only to be read, or to anyone who can reach the port.
+fn check_shell_request(server: &Server, headers: &HeaderMap) -> Result<(), ApiError> {
+    if server.read_only {
+        return Err(ApiError(StatusCode::FORBIDDEN, "The remote shell is not available on a read-only server.".to_string()));
+    }
+    if server.token.is_none() {
+        return Err(ApiError(StatusCode::FORBIDDEN, "The remote shell needs a token: start the server with --token.".to_string()));
+    }
+    // Browsers let any page open a WebSocket to any host, but say which page
+    // it is in Origin; galvanizedb connect sends none.
+    if headers.contains_key(header::ORIGIN) {
+        return Err(ApiError(StatusCode::FORBIDDEN, "The remote shell is not available to web pages.".to_string()));
+    }
+    Ok(())
+}
+
+async fn web_page() -> Html<&'static str> {
+    Html(WEB_PAGE)
+}
+
+async fn not_found() -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, "Unknown endpoint. Use POST /query, GET /tables, GET /schema or GET /shell.".to_string())
+}
+
+/// Opens `database`, an SQLite file or a postgres:// URL, and serves it
+/// until Ctrl+C is pressed.
+pub async fn serve(database: &str, options: ServeOptions) -> Result<()> {
+    let mut pragmas = options.pragmas.clone();
+    // Statements are checked before they run, and the database refuses
+    // changes as well.
+    let (db, database) = if postgres::is_url(database) {
+        let mut db = Session::open_postgres(database).await?;
+        if options.read_only {
+            db.execute_sql("SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY;").await?;
+        }
+        (db, database.to_string())
+    } else {
+        let path = Path::new(database);
+        if !path.exists() {
+            return Err(GalvanizeError::Connection(format!("{} does not exist.", database)));
+        }
+        if options.read_only {
+            pragmas.push(("query_only".to_string(), "ON".to_string()));
+        }
+        let db = Session::open_sqlite(database, path, &pragmas, options.settings.functions).await?;
+        // Remote shells look names up in data_dir, so they are given the
+        // whole path.
+        let path = std::fs::canonicalize(path)?;
+        (db, path.display().to_string())
+    };
+    let name = db.database_name().to_string();
+    let postgres = db.is_postgres();
+    let (db, database_thread) = spawn_database(db, postgres && options.read_only)?;
+    let (stop, stopping) = watch::channel(());
+    let link = match (&options.token, options.web) {
+        (Some(token), true) => format!(" Open http://{}/#token={} in a browser.", options.listen, token),
+        _ => String::new(),
+    };
+    let server = Arc::new(Server {
+        db,
+        postgres,
+        token: options.token,
+        read_only: options.read_only,
+        web_port: options.web.then(|| options.listen.port()),
+        settings: Settings { full_blobs: true, ..Settings::default() },
+        database,
+        // Output is sent as plain text, with each statement's time.
+        shell_settings: Settings { color: false, pager: false, timing: true, ..options.settings },
+        pragmas: options.pragmas,
+        stopping,
+    });
+    // The shell runs anything, so it is not offered to browsers.
+    let mut app = Router::new();
+    app = if options.web { app.route("/", get(web_page)) } else { app.route("/shell", get(shell)) };
+    let app = app
+        .route("/query", post(query))
+        .route("/tables", get(tables))
+        .route("/schema", get(schema))
+        .fallback(not_found)
+        .layer(middleware::from_fn_with_state(server.clone(), authorize))
+        .with_state(server);
+
+    let listener = tokio::net::TcpListener::bind(options.listen)
+        .await
+        .map_err(|e| GalvanizeError::Io(format!("Error listening on {}: {}", options.listen, e)))?;
+    info!(address = %options.listen, "Serving HTTP.");
+    outln!(
+        "Serving '{}' on http://{}{}.{} Press Ctrl+C to stop.",
+        name,
+        options.listen,
+        if options.read_only { ", read-only" } else { "" },
+        link
+    );
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            // Remote shells are ended, as the server waits for them.
+            drop(stop);
+        })
+        .await?;
+
+    // The session is closed once the last sender is dropped with the router.
+    let _ = tokio::task::spawn_blocking(move || database_thread.join()).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::HeaderName;
+    use tower::ServiceExt;
+    use super::*;
+
+    fn server(token: Option<&str>, web_port: Option<u16>, read_only: bool) -> Arc<Server> {
+        let (db, _) = mpsc::channel(1);
+        let (_, stopping) = watch::channel(());
+        Arc::new(Server {
+            db,
+            postgres: false,
+            token: token.map(String::from),
+            read_only,
+            web_port,
+            settings: Settings::default(),
+            database: String::new(),
+            shell_settings: Settings::default(),
+            pragmas: Vec::new(),
+            stopping,
+        })
+    }
+
+    // The status of a request for `uri` with `headers`, through `authorize`.
+    async fn status(server: &Arc<Server>, uri: &str, headers: &[(HeaderName, &str)]) -> StatusCode {
+        let app = Router::new()
+            .route("/", get(|| async { "page" }))
+            .route("/query", get(|| async { "rows" }))
+            .layer(middleware::from_fn_with_state(server.clone(), authorize))
+            .with_state(server.clone());
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(same_token(b"secret", b"secret"));
+        assert!(!same_token(b"secreT", b"secret"));
+        assert!(!same_token(b"secret2", b"secret"));
+        assert!(!same_token(b"", b"secret"));
+    }
+
+    #[tokio::test]
+    async fn requires_the_token() {
+        let server = server(Some("secret"), None, false);
+        assert_eq!(status(&server, "/query", &[]).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&server, "/query", &[(header::AUTHORIZATION, "Bearer wrong")]).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&server, "/query", &[(header::AUTHORIZATION, "secret")]).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&server, "/query", &[(header::AUTHORIZATION, "Bearer secret")]).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn serves_the_web_ui_only_to_this_machine() {
+        let server = server(Some("secret"), Some(8080), false);
+        let token = (header::AUTHORIZATION, "Bearer secret");
+        let local = (header::HOST, "127.0.0.1:8080");
+        assert_eq!(status(&server, "/", &[(header::HOST, "127.0.0.1:8080")]).await, StatusCode::OK);
+        assert_eq!(status(&server, "/query", &[(header::HOST, "127.0.0.1:8080")]).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&server, "/query", &[local.clone(), token.clone()]).await, StatusCode::OK);
+        assert_eq!(status(&server, "/query", &[(header::HOST, "localhost:8080"), (header::ORIGIN, "http://localhost:8080"), token.clone()]).await, StatusCode::OK);
+        // Another site, or another name for this machine (DNS rebinding).
+        assert_eq!(status(&server, "/query", &[local.clone(), (header::ORIGIN, "http://evil.example"), token.clone()]).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(&server, "/query", &[(header::HOST, "evil.example:8080"), token.clone()]).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(&server, "/", &[(header::HOST, "127.0.0.1:9090")]).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(&server, "/", &[]).await, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn offers_the_shell_only_with_a_token_and_no_origin() {
+        let none = HeaderMap::new();
+        let mut from_page = HeaderMap::new();
+        from_page.insert(header::ORIGIN, header::HeaderValue::from_static("http://localhost:8080"));
+        assert!(check_shell_request(&server(Some("secret"), None, false), &none).is_ok());
+        assert_eq!(check_shell_request(&server(None, None, false), &none).err().map(|e| e.0), Some(StatusCode::FORBIDDEN));
+        assert_eq!(check_shell_request(&server(Some("secret"), None, true), &none).err().map(|e| e.0), Some(StatusCode::FORBIDDEN));
+        assert_eq!(check_shell_request(&server(Some("secret"), None, false), &from_page).err().map(|e| e.0), Some(StatusCode::FORBIDDEN));
+    }
+}
@@ -0,0 +1,756 @@
+use std::ffi::{CStr, CString};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use futures_util::future::LocalBoxFuture;
+use futures_util::{StreamExt, TryStreamExt};
+use sqlx::{Column, Connection, Row};
+use sqlx::pool::PoolConnection;
+use sqlx::query::Query;
+use sqlx::postgres::PgConnection;
+use sqlx::sqlite::{Sqlite, SqliteArguments, SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions};
+use tracing::{debug, info, warn};
+use crate::audit::AuditLog;
+use crate::error::{GalvanizeError, Result};
+use crate::jobs::Jobs;
+use crate::output::{Value, ValueStream, print_result, row_values};
+use crate::{MEMORY_DATABASE, Settings, functions, postgres, returns_rows};
+
+// Number of SQLite virtual machine instructions between checks for a
+// cancelled statement.
+pub(crate) const PROGRESS_HANDLER_OPS: i32 = 1000;
+
+// Client data key under which each SQLite connection records how many of its
+// session's extensions it has loaded.
+const LOADED_EXTENSIONS_KEY: &[u8] = b"galvanizedb.loaded_extensions\0";
+
+// Client data key under which each SQLite connection records the change of
+// the foreign_keys setting it was last set to.
+const FOREIGN_KEYS_KEY: &[u8] = b"galvanizedb.foreign_keys\0";
+
+/// What a statement did: the number of rows it returned, or for statements
+/// that return no rows, what it changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryOutcome {
+    Rows(u64),
+    Changes { rows_affected: u64, last_insert_rowid: Option<i64> },
+}
+
+/// The complete result of a statement, as returned by [`Session::execute_sql`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Rows changed by a statement that returns no rows.
+    pub rows_affected: u64,
+    /// Rowid of the last row inserted into SQLite, for statements that
+    /// return no rows.
+    pub last_insert_rowid: Option<i64>,
+}
+
+/// Receives the rows of a statement as they are fetched. `render` is called
+/// once for each statement that returns rows, and returns how many it
+/// handled.
+pub trait ResultRenderer {
+    fn render<'a>(&'a mut self, columns: &'a [String], rows: ValueStream<'a>) -> LocalBoxFuture<'a, Result<u64>>;
+}
+
+/// Writes results in the output format selected by the settings.
+pub struct FormatRenderer<'a> {
+    settings: &'a Settings,
+    out: &'a mut dyn Write,
+    max_rows: Option<u64>,
+    // Rows passed over at the start of each result, as already shown.
+    skip_rows: u64,
+    // Whether `max_rows` left rows of the last result out.
+    cut_short: bool,
+}
+
+impl<'a> FormatRenderer<'a> {
+    pub fn new(settings: &'a Settings, out: &'a mut dyn Write) -> Self {
+        FormatRenderer { settings, out, max_rows: None, skip_rows: 0, cut_short: false }
+    }
+
+    /// Writes no more than `max_rows` rows of each result. One more is
+    /// fetched, to tell whether there are others, and the rest are not.
+    pub fn max_rows(mut self, max_rows: Option<u64>) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    /// Fetches and passes over the first `skip_rows` rows of each result,
+    /// which are not counted either.
+    pub fn skip_rows(mut self, skip_rows: u64) -> Self {
+        self.skip_rows = skip_rows;
+        self
+    }
+
+    /// Whether rows were left out because of `max_rows`.
+    pub fn cut_short(&self) -> bool {
+        self.cut_short
+    }
+}
+
+impl ResultRenderer for FormatRenderer<'_> {
+    fn render<'a>(&'a mut self, columns: &'a [String], rows: ValueStream<'a>) -> LocalBoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            let mut rows = rows;
+            for _ in 0..self.skip_rows {
+                if rows.try_next().await?.is_none() {
+                    break;
+                }
+            }
+            let Some(max_rows) = self.max_rows else {
+                return print_result(columns, rows, self.settings, &mut *self.out).await;
+            };
+            let shown = print_result(columns, Box::pin(rows.by_ref().take(max_rows as usize)), self.settings, &mut *self.out).await?;
+            self.cut_short = rows.try_next().await?.is_some();
+            Ok(shown)
+        })
+    }
+}
+
+// Keeps the rows in memory, for `Session::execute_sql`: all of them, or up
+// to `max_rows`.
+#[derive(Default)]
+struct Collector {
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+    max_rows: Option<usize>,
+    // Whether `max_rows` left rows out.
+    cut_short: bool,
+}
+
+impl ResultRenderer for Collector {
+    fn render<'a>(&'a mut self, columns: &'a [String], rows: ValueStream<'a>) -> LocalBoxFuture<'a, Result<u64>> {
+        Box::pin(async move {
+            self.columns = columns.to_vec();
+            let Some(max_rows) = self.max_rows else {
+                self.rows = rows.try_collect().await?;
+                return Ok(self.rows.len() as u64);
+            };
+            let mut rows = rows;
+            self.rows = rows.by_ref().take(max_rows).try_collect().await?;
+            self.cut_short = rows.try_next().await?.is_some();
+            Ok(self.rows.len() as u64)
+        })
+    }
+}
+
+// Loads the extensions in `paths` that the connection has not loaded yet, in
+// order. Loading is only enabled for the call, so the SQL load_extension()
+// function stays disabled.
+async fn load_extensions(conn: &mut SqliteConnection, paths: &[PathBuf]) -> Result<()> {
+    let mut handle = conn.lock_handle().await?;
+    let db = handle.as_raw_handle().as_ptr();
+    let key = LOADED_EXTENSIONS_KEY.as_ptr().cast();
+    // SAFETY: the handle is valid and locked for the duration of the calls.
+    // The client data is a count stored in the pointer itself, so it needs no
+    // destructor, and error messages are freed with sqlite3_free.
+    unsafe {
+        use libsqlite3_sys::*;
+        let loaded = sqlite3_get_clientdata(db, key) as usize;
+        for (count, path) in paths.iter().enumerate().skip(loaded) {
+            let file = CString::new(path.to_string_lossy().into_owned()).map_err(|_| {
+                GalvanizeError::Parse(format!("Invalid extension path '{}'.", path.display()))
+            })?;
+            let mut message = std::ptr::null_mut();
+            sqlite3_db_config(db, SQLITE_DBCONFIG_ENABLE_LOAD_EXTENSION, 1, std::ptr::null_mut::<i32>());
+            let code = sqlite3_load_extension(db, file.as_ptr(), std::ptr::null(), &mut message);
+            sqlite3_db_config(db, SQLITE_DBCONFIG_ENABLE_LOAD_EXTENSION, 0, std::ptr::null_mut::<i32>());
+            if code != SQLITE_OK {
+                let reason = if message.is_null() {
+                    format!("error code {}", code)
+                } else {
+                    let reason = CStr::from_ptr(message).to_string_lossy().into_owned();
+                    sqlite3_free(message.cast());
+                    reason
+                };
+                return Err(GalvanizeError::Execution(format!("Error loading extension '{}': {}", path.display(), reason)));
+            }
+            sqlite3_set_clientdata(db, key, (count + 1) as *mut std::ffi::c_void, None);
+        }
+    }
+    Ok(())
+}
+
+// Whether SQLite connections enforce foreign keys, shared with the pool so
+// that connections are set up as they open. Each change of the setting has a
+// number, and none was made while it is zero.
+#[derive(Default)]
+struct ForeignKeys {
+    enabled: AtomicBool,
+    change: AtomicUsize,
+}
+
+// Sets whether the connection enforces foreign keys, once for each change of
+// the setting rather than before every statement, so that a PRAGMA
+// foreign_keys run by the user stands until the setting changes. SQLite
+// ignores the pragma inside a transaction, so it is left for later there.
+async fn apply_foreign_keys(conn: &mut SqliteConnection, foreign_keys: &ForeignKeys) -> sqlx::Result<()> {
+    let change = foreign_keys.change.load(Ordering::Relaxed);
+    {
+        let mut handle = conn.lock_handle().await?;
+        // SAFETY: the handle is valid and locked for the duration of the call.
+        let applied = unsafe { libsqlite3_sys::sqlite3_get_clientdata(handle.as_raw_handle().as_ptr(), FOREIGN_KEYS_KEY.as_ptr().cast()) as usize };
+        if change == 0 || applied == change {
+            return Ok(());
+        }
+    }
+    if in_transaction(conn).await {
+        return Ok(());
+    }
+    let pragma = if foreign_keys.enabled.load(Ordering::Relaxed) { "PRAGMA foreign_keys = ON;" } else { "PRAGMA foreign_keys = OFF;" };
+    sqlx::query(pragma).execute(&mut *conn).await?;
+    let mut handle = conn.lock_handle().await?;
+    // SAFETY: as above. The client data is the change number stored in the
+    // pointer itself, so it needs no destructor.
+    unsafe {
+        let db = handle.as_raw_handle().as_ptr();
+        libsqlite3_sys::sqlite3_set_clientdata(db, FOREIGN_KEYS_KEY.as_ptr().cast(), change as *mut std::ffi::c_void, None);
+    }
+    Ok(())
+}
+
+/// The value a `PRAGMA foreign_keys = ...` statement sets, if it is one.
+pub(crate) fn foreign_keys_pragma(sql: &str) -> Option<bool> {
+    let sql = sql.trim().trim_end_matches(';').trim_end().to_lowercase();
+    let rest = sql.strip_prefix("pragma")?.trim_start().strip_prefix("foreign_keys")?.trim_start();
+    let value = match rest.strip_prefix('=') {
+        Some(value) => value,
+        None => rest.strip_prefix('(')?.strip_suffix(')')?,
+    };
+    match value.trim().trim_matches(['\'', '"']) {
+        "on" | "true" | "yes" | "1" => Some(true),
+        "off" | "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+async fn create_or_connect_database(
+    db_name: &str,
+    pragmas: &[(String, String)],
+    register_functions: bool,
+    foreign_keys: Arc<ForeignKeys>,
+) -> sqlx::Result<SqlitePool> {
+    let database_url: String = format!("sqlite:{}?mode=rwc", db_name);
+    // sqlx opens `:memory:` URLs as shared-cache databases with
+    // SQLITE_OPEN_MEMORY, which VACUUM INTO inherits, so BACKUP TO would
+    // write to memory too. A private database on one connection avoids both.
+    let mut options = if db_name == MEMORY_DATABASE {
+        SqliteConnectOptions::new().filename(MEMORY_DATABASE)
+    } else {
+        SqliteConnectOptions::from_str(&database_url)?
+    };
+    for (name, value) in pragmas {
+        options = options.pragma(name.clone(), value.clone());
+    }
+
+    let pool_options = SqlitePoolOptions::new().after_connect(move |conn, _| {
+        let foreign_keys = foreign_keys.clone();
+        Box::pin(async move {
+            debug!("Opened a new pooled connection.");
+            if register_functions {
+                functions::register(conn).await?;
+            }
+            apply_foreign_keys(conn, &foreign_keys).await
+        })
+    });
+
+    // An in-memory database lives only as long as its connection, so it gets
+    // a single connection that is never closed while the pool is open.
+    let pool = if db_name == MEMORY_DATABASE {
+        pool_options
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?
+    } else {
+        pool_options.connect_with(options).await?
+    };
+    Ok(pool)
+}
+
+pub(crate) fn bind_values<'q>(mut query: Query<'q, Sqlite, SqliteArguments<'q>>, params: &'q [Value]) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    for value in params {
+        query = match value {
+            Value::Null => query.bind(None::<String>),
+            Value::Integer(v) => query.bind(*v),
+            Value::Real(v) => query.bind(*v),
+            Value::Boolean(v) => query.bind(*v),
+            Value::Decimal(v) | Value::Text(v) => query.bind(v.as_str()),
+            Value::Blob(v) => query.bind(v.as_slice()),
+        };
+    }
+    query
+}
+
+// Runs a statement with `params` bound to it, passing any rows it returns to
+// the renderer.
+async fn execute_sql(conn: &mut SqliteConnection, sql: &str, params: &[Value], renderer: &mut dyn ResultRenderer) -> Result<QueryOutcome> {
+    if returns_rows(sql) {
+        let mut rows = bind_values(sqlx::query(sql), params).fetch(&mut *conn);
+
+        // Column names are taken from the first row; an empty result has none.
+        let first = match rows.try_next().await? {
+            Some(row) => row,
+            None => {
+                let count = renderer.render(&[], futures_util::stream::empty().boxed()).await?;
+                return Ok(QueryOutcome::Rows(count));
+            },
+        };
+        let column_names: Vec<String> = first.columns().iter().map(|col| col.name().to_string()).collect();
+        let values = futures_util::stream::once(async { Ok(first) })
+            .chain(rows)
+            .map(|row| Ok(row_values(&row?)))
+            .boxed();
+
+        Ok(QueryOutcome::Rows(renderer.render(&column_names, values).await?))
+    } else {
+        let result = bind_values(sqlx::query(sql), params).execute(conn).await?;
+        Ok(QueryOutcome::Changes {
+            rows_affected: result.rows_affected(),
+            last_insert_rowid: Some(result.last_insert_rowid()),
+        })
+    }
+}
+
+// Runs `execution`, awaiting `interrupt` if `cancel` completes first. The
+// statement is interrupted rather than dropped, so that the connection is
+// left in a usable state, and if it then fails it fails with the error
+// `cancel` completed with.
+async fn run_cancellable<T>(
+    execution: impl Future<Output = Result<T>>,
+    cancel: impl Future<Output = GalvanizeError>,
+    interrupt: impl Future<Output = ()>,
+) -> Result<T> {
+    tokio::pin!(execution);
+    let reason = tokio::select! {
+        result = &mut execution => return result,
+        reason = cancel => reason,
+    };
+
+    info!(reason = %reason, "Interrupting the statement.");
+    interrupt.await;
+    execution.await.map_err(|_| reason)
+}
+
+// Whether the connection has a transaction open, i.e. is not in autocommit
+// mode. This covers BEGIN, SAVEPOINT and statements that end a transaction
+// implicitly, such as a failed COMMIT.
+pub(crate) async fn in_transaction(conn: &mut SqliteConnection) -> bool {
+    match conn.lock_handle().await {
+        // SAFETY: the handle is valid and locked for the duration of the call.
+        Ok(mut handle) => unsafe { libsqlite3_sys::sqlite3_get_autocommit(handle.as_raw_handle().as_ptr()) == 0 },
+        Err(_) => false,
+    }
+}
+
+// Whether the main database of the connection cannot be written: its file
+// was opened read-only, or `PRAGMA query_only` is on.
+async fn is_read_only(conn: &mut SqliteConnection) -> bool {
+    let file_read_only = match conn.lock_handle().await {
+        // SAFETY: the handle is valid and locked for the duration of the call.
+        Ok(mut handle) => unsafe { libsqlite3_sys::sqlite3_db_readonly(handle.as_raw_handle().as_ptr(), c"main".as_ptr()) == 1 },
+        Err(_) => false,
+    };
+    file_read_only || sqlx::query_scalar::<_, bool>("PRAGMA query_only;").fetch_one(conn).await.unwrap_or(false)
+}
+
+pub(crate) const ATTACHED_DATABASES: &str = "SELECT name AS \"Alias\", file AS \"File\" FROM pragma_database_list WHERE name NOT IN ('main', 'temp');";
+
+// Whether other databases are attached to the connection with ATTACH.
+async fn has_attachments(conn: &mut SqliteConnection) -> bool {
+    sqlx::query(ATTACHED_DATABASES).fetch_optional(conn).await.is_ok_and(|row| row.is_some())
+}
+
+/// A connection to one database: an SQLite file (or `:memory:`) or a
+/// PostgreSQL server. A new session is not connected to anything.
+pub struct Session {
+    database_name: String,
+    // File of the SQLite database, as it was opened.
+    path: Option<PathBuf>,
+    sql_pool: Option<SqlitePool>,
+    // Connection pinned while a transaction is open or other databases are
+    // attached, since both only exist on the connection that started them.
+    pinned: Option<PoolConnection<Sqlite>>,
+    in_transaction: bool,
+    // Whether statements cannot change the database, as of the last one.
+    read_only: bool,
+    // Connection to a PostgreSQL server, used instead of `sql_pool`. A single
+    // connection keeps transactions and session state across statements.
+    pg: Option<PgConnection>,
+    // URL and backend process ID of the PostgreSQL connection, for cancelling
+    // statements.
+    pg_backend: Option<(String, i32)>,
+    // How long SQLite connections wait for a lock, if it has been changed,
+    // and whether they enforce foreign keys.
+    busy_timeout: Option<Duration>,
+    foreign_keys: Arc<ForeignKeys>,
+    // Extension libraries loaded into every SQLite connection, in order.
+    extensions: Vec<PathBuf>,
+    // Statements running in the background, each on a connection of its own.
+    jobs: Jobs,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            database_name: "None".to_string(),
+            path: None,
+            sql_pool: None,
+            pinned: None,
+            in_transaction: false,
+            read_only: false,
+            pg: None,
+            pg_backend: None,
+            busy_timeout: None,
+            foreign_keys: Arc::default(),
+            extensions: Vec::new(),
+            jobs: Jobs::default(),
+        }
+    }
+}
+
+impl Session {
+    /// Opens the SQLite database at `path`, creating it if needed, and
+    /// applies `pragmas` to every connection, registering the shell's SQL
+    /// functions on each if `functions` is set. `name` is how the database is
+    /// shown to the user.
+    pub async fn open_sqlite(name: &str, path: &Path, pragmas: &[(String, String)], functions: bool) -> Result<Session> {
+        let foreign_keys = Arc::new(ForeignKeys::default());
+        let pool = create_or_connect_database(&path.to_string_lossy(), pragmas, functions, foreign_keys.clone())
+            .await
+            .map_err(|e| GalvanizeError::Connection(format!("Error connecting to database '{}': {}", name, e)))
+            .inspect_err(|e| warn!("{}", e))?;
+        info!(database = name, path = %path.display(), "Opened SQLite database.");
+        let read_only = match pool.acquire().await {
+            Ok(mut conn) => is_read_only(&mut conn).await,
+            Err(_) => false,
+        };
+        Ok(Session {
+            database_name: name.to_string(),
+            path: Some(path.to_path_buf()),
+            sql_pool: Some(pool),
+            read_only,
+            foreign_keys,
+            ..Session::default()
+        })
+    }
+
+    /// Connects to the PostgreSQL server at `url`.
+    pub async fn open_postgres(url: &str) -> Result<Session> {
+        let mut conn = postgres::connect(url).await.inspect_err(|e| warn!("{}", e))?;
+        let pid = postgres::backend_pid(&mut conn).await?;
+        let read_only = postgres::is_read_only(&mut conn).await;
+        info!(url = %postgres::display_url(url), backend_pid = pid, "Connected to PostgreSQL.");
+        Ok(Session {
+            database_name: postgres::display_url(url),
+            pg: Some(conn),
+            pg_backend: Some((url.to_string(), pid)),
+            read_only,
+            ..Session::default()
+        })
+    }
+
+    pub fn database_name(&self) -> &str {
+        &self.database_name
+    }
+
+    /// The file of the connected SQLite database.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.sql_pool.is_some() || self.pg.is_some()
+    }
+
+    pub fn is_postgres(&self) -> bool {
+        self.pg.is_some()
+    }
+
+    /// Whether the last statement left a transaction open.
+    pub fn in_transaction(&self) -> bool {
+        self.in_transaction
+    }
+
+    /// Whether the database cannot be changed: an SQLite file opened
+    /// read-only or with `PRAGMA query_only` on, or a PostgreSQL server that
+    /// is a standby or makes transactions read-only by default.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub(crate) fn postgres(&mut self) -> Option<&mut PgConnection> {
+        self.pg.as_mut()
+    }
+
+    pub(crate) fn pool(&self) -> Result<&SqlitePool> {
+        if self.pg.is_some() {
+            return Err(GalvanizeError::UnsupportedType("This command is only supported for SQLite databases.".to_string()));
+        }
+        self.sql_pool.as_ref().ok_or_else(|| GalvanizeError::Connection("No database selected.".to_string()))
+    }
+
+    /// Sets how long statements wait for another connection to release a
+    /// lock on an SQLite database; zero fails them straight away.
+    pub fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = Some(timeout);
+    }
+
+    /// Loads the SQLite extension library at `path` into this session, and
+    /// into every connection it opens from now on.
+    pub async fn load_extension(&mut self, path: &Path) -> Result<()> {
+        let mut conn = self.acquire().await?;
+        self.extensions.push(path.to_path_buf());
+        let result = load_extensions(&mut conn, &self.extensions).await;
+        if result.is_err() {
+            self.extensions.pop();
+        }
+        self.release(conn).await;
+        result
+    }
+
+    /// Sets whether SQLite enforces foreign key constraints, on each
+    /// connection as it is next used or opened. Changes made while a
+    /// transaction is open take effect once it ends.
+    pub fn set_foreign_keys(&mut self, enabled: bool) {
+        self.foreign_keys.enabled.store(enabled, Ordering::Relaxed);
+        self.foreign_keys.change.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Statements run on the pinned connection, if there is one, or on any
+    // pooled connection otherwise.
+    pub(crate) async fn acquire(&mut self) -> Result<PoolConnection<Sqlite>> {
+        let mut conn = match self.pinned.take() {
+            Some(conn) => {
+                debug!("Using the pinned connection.");
+                conn
+            },
+            None => {
+                let pool = self.pool()?;
+                let conn = pool.acquire().await?;
+                debug!(size = pool.size(), idle = pool.num_idle(), "Acquired a pooled connection.");
+                conn
+            },
+        };
+        if let Err(e) = self.configure(&mut conn).await {
+            self.release(conn).await;
+            return Err(e);
+        }
+        Ok(conn)
+    }
+
+    // Pooled connections may have been opened before the settings changed, or
+    // extensions were loaded.
+    async fn configure(&self, conn: &mut SqliteConnection) -> Result<()> {
+        if let Some(timeout) = self.busy_timeout {
+            let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+            if let Ok(mut handle) = conn.lock_handle().await {
+                // SAFETY: the handle is valid and locked for the duration of the call.
+                unsafe { libsqlite3_sys::sqlite3_busy_timeout(handle.as_raw_handle().as_ptr(), millis) };
+            }
+        }
+        apply_foreign_keys(conn, &self.foreign_keys).await?;
+        load_extensions(conn, &self.extensions).await
+    }
+
+    /// Starts running a statement in the background, on a connection of its
+    /// own and so outside any open transaction, and returns the number of
+    /// the job. The statement is recorded in `audit_log` once it finishes.
+    pub async fn spawn_job(&mut self, sql: &str, params: &[Value], audit_log: Option<AuditLog>) -> Result<usize> {
+        if self.path.as_deref() == Some(Path::new(MEMORY_DATABASE)) {
+            return Err(GalvanizeError::UnsupportedType(
+                "An in-memory database has a single connection, so statements cannot run in the background.".to_string(),
+            ));
+        }
+        let mut conn = self.pool()?.acquire().await?;
+        self.configure(&mut conn).await?;
+        debug!("Acquired a pooled connection for a background job.");
+        Ok(self.jobs.spawn(conn, &self.database_name, sql, params.to_vec(), audit_log))
+    }
+
+    /// The background jobs started in this session.
+    pub(crate) fn jobs(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    // Pins the connection if the statement left a transaction open or a
+    // database attached, otherwise hands it back to the pool.
+    pub(crate) async fn release(&mut self, mut conn: PoolConnection<Sqlite>) {
+        self.in_transaction = in_transaction(&mut conn).await;
+        self.read_only = is_read_only(&mut conn).await;
+        if self.in_transaction || has_attachments(&mut conn).await {
+            debug!(in_transaction = self.in_transaction, "Pinned the connection.");
+            self.pinned = Some(conn);
+        }
+    }
+
+    // Rolls back an open transaction and unpins the connection, which also
+    // detaches any attached databases.
+    async fn rollback_open_transaction(&mut self) {
+        if let Some(mut conn) = self.pinned.take() {
+            if self.in_transaction {
+                errln!("Rolling back the open transaction.");
+                if let Err(e) = sqlx::query("ROLLBACK;").execute(&mut *conn).await {
+                    errln!("Error rolling back transaction: {}", e);
+                }
+            }
+            // Closing the connection rather than returning it to the pool
+            // drops its attachments along with it.
+            let _ = conn.detach().close().await;
+        }
+        self.in_transaction = false;
+    }
+
+    /// Closes the connection, rolling back any open transaction.
+    pub async fn close(&mut self) {
+        if self.is_open() {
+            info!(database = %self.database_name, "Closing the connection.");
+        }
+        // The pool only closes once the jobs have returned their connections.
+        self.jobs.kill_all().await;
+        self.rollback_open_transaction().await;
+
+        self.pg_backend = None;
+        if let Some(conn) = self.pg.take() {
+            // Closing ends the session, rolling back any open transaction.
+            if let Err(e) = conn.close().await {
+                errln!("Error closing connection: {}", e);
+            }
+        }
+        if let Some(pool) = self.sql_pool.take() {
+            pool.close().await;
+        }
+        self.database_name = "None".to_string();
+        self.path = None;
+    }
+
+    /// The table, view and column names of the database, for completion.
+    pub async fn schema_names(&mut self) -> Vec<String> {
+        if let Some(conn) = self.pg.as_mut() {
+            return postgres::load_schema_names(conn).await.unwrap_or_default();
+        }
+        match self.acquire().await {
+            Ok(mut conn) => {
+                let names = crate::completion::load_schema_names(&mut conn).await.unwrap_or_default();
+                self.release(conn).await;
+                names
+            },
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Runs a statement, streaming any rows it returns to `renderer`.
+    pub async fn execute(&mut self, sql: &str, renderer: &mut dyn ResultRenderer) -> Result<QueryOutcome> {
+        self.execute_cancellable(sql, &[], renderer, std::future::pending()).await
+    }
+
+    /// Like [`Session::execute`], but with `params` bound to the statement's
+    /// `$1`, `$2`, ... parameters. The statement is cancelled if `cancel`
+    /// completes before it does, e.g. when the user presses Ctrl+C, and fails
+    /// with the error `cancel` completed with. The session stays connected.
+    pub async fn execute_cancellable(
+        &mut self,
+        sql: &str,
+        params: &[Value],
+        renderer: &mut dyn ResultRenderer,
+        cancel: impl Future<Output = GalvanizeError>,
+    ) -> Result<QueryOutcome> {
+        debug!(sql, params = params.len(), "Executing statement.");
+        let started = std::time::Instant::now();
+        let result = self.execute_on_connection(sql, params, renderer, cancel).await;
+        match &result {
+            Ok(outcome) => info!(sql, elapsed_ms = started.elapsed().as_secs_f64() * 1000.0, ?outcome, "Statement finished."),
+            Err(e) => warn!(sql, error = %e, "Statement failed."),
+        }
+        result
+    }
+
+    async fn execute_on_connection(
+        &mut self,
+        sql: &str,
+        params: &[Value],
+        renderer: &mut dyn ResultRenderer,
+        cancel: impl Future<Output = GalvanizeError>,
+    ) -> Result<QueryOutcome> {
+        if let Some(conn) = self.pg.as_mut() {
+            let backend = self.pg_backend.clone();
+            let interrupt = async move {
+                if let Some((url, pid)) = backend {
+                    postgres::cancel(&url, pid).await;
+                }
+            };
+            return run_cancellable(postgres::execute_sql(conn, sql, params, renderer), cancel, interrupt).await;
+        }
+
+        // sqlx steps a statement again after it fails, which restarts it, so
+        // a one-off sqlite3_interrupt is not enough. A progress handler keeps
+        // failing the statement until it has been given up on.
+        let mut conn = self.acquire().await?;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Ok(mut handle) = conn.lock_handle().await {
+            let cancelled = Arc::clone(&cancelled);
+            handle.set_progress_handler(PROGRESS_HANDLER_OPS, move || !cancelled.load(Ordering::Relaxed));
+        }
+        let interrupt = async { cancelled.store(true, Ordering::Relaxed) };
+        let result = run_cancellable(execute_sql(&mut conn, sql, params, renderer), cancel, interrupt).await;
+        if let Ok(mut handle) = conn.lock_handle().await {
+            handle.remove_progress_handler();
+        }
+        self.release(conn).await;
+        // The next statement may run on another pooled connection, so the
+        // pragma is taken as the session's setting, as SET would be.
+        if let Some(enabled) = foreign_keys_pragma(sql).filter(|_| result.is_ok()) {
+            self.set_foreign_keys(enabled);
+        }
+        result
+    }
+
+    /// Rolls back the open transaction, if there is one.
+    pub async fn rollback(&mut self) -> Result<()> {
+        // PostgreSQL only warns when no transaction is open.
+        if self.is_postgres() || self.in_transaction {
+            self.execute_sql("ROLLBACK;").await?;
+        }
+        Ok(())
+    }
+
+    /// Runs a statement and returns its complete result.
+    pub async fn execute_sql(&mut self, sql: &str) -> Result<QueryResult> {
+        self.execute_sql_with(sql, &[]).await
+    }
+
+    /// Like [`Session::execute_sql`], but with `params` bound to the
+    /// statement's parameters.
+    pub async fn execute_sql_with(&mut self, sql: &str, params: &[Value]) -> Result<QueryResult> {
+        self.collect(sql, params, Collector::default()).await.map(|(result, _)| result)
+    }
+
+    /// Like [`Session::execute_sql_with`], but keeps no more than `max_rows`
+    /// rows of the result, and says whether there were more.
+    pub async fn execute_sql_limited(&mut self, sql: &str, params: &[Value], max_rows: usize) -> Result<(QueryResult, bool)> {
+        self.collect(sql, params, Collector { max_rows: Some(max_rows), ..Collector::default() }).await
+    }
+
+    async fn collect(&mut self, sql: &str, params: &[Value], mut collector: Collector) -> Result<(QueryResult, bool)> {
+        let outcome = self.execute_cancellable(sql, params, &mut collector, std::future::pending()).await?;
+        let (rows_affected, last_insert_rowid) = match outcome {
+            QueryOutcome::Rows(_) => (0, None),
+            QueryOutcome::Changes { rows_affected, last_insert_rowid } => (rows_affected, last_insert_rowid),
+        };
+        let result = QueryResult {
+            columns: collector.columns,
+            rows: collector.rows,
+            rows_affected,
+            last_insert_rowid,
+        };
+        Ok((result, collector.cut_short))
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::ValueEnum;
+use crate::error::{GalvanizeError, Result};
+use crate::expand_path;
+use crate::output::{OutputFormat, Overflow};
+use crate::theme::Theme;
+
+pub const DEFAULT_PROMPT: &str = "GalvanizeDB [{session}{db}]{txn}> ";
+/// The placeholders of a prompt template, each replaced by what it shows.
+pub const PROMPT_PLACEHOLDERS: &[&str] = &["db", "session", "txn", "backend", "ro", "time"];
+const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The key bindings of the line editor.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Keymap {
+    Emacs,
+    Vi,
+}
+
+impl Keymap {
+    pub fn from_name(name: &str) -> Option<Keymap> {
+        Keymap::from_str(name, true).ok()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Keymap::Emacs => "EMACS",
+            Keymap::Vi => "VI",
+        }
+    }
+}
+
+/// How results are displayed, and other options changed with `SET`.
+#[derive(Clone)]
+pub struct Settings {
+    pub format: OutputFormat,
+    pub null_value: String,
+    pub full_blobs: bool,
+    pub timing: bool,
+    /// Page results taller than the terminal (interactive sessions only).
+    pub pager: bool,
+    /// Template of the prompt, with placeholders such as `{db}`.
+    pub prompt: String,
+    pub keymap: Keymap,
+    /// Directory that database names are resolved against.
+    pub data_dir: Option<PathBuf>,
+    pub overflow: Overflow,
+    /// How many rows of a result the interactive shell shows at most.
+    pub max_rows: Option<u64>,
+    /// Whether results have a header row, or `None` for the default of the
+    /// format: LIST and TSV output have none.
+    pub headers: Option<bool>,
+    /// Separator between values in LIST output.
+    pub separator: String,
+    /// How long a statement may run, including fetching its rows, before it
+    /// is cancelled.
+    pub timeout: Option<Duration>,
+    /// How long SQLite waits for another connection to release a lock before
+    /// a statement fails as busy.
+    pub busy_timeout: Option<Duration>,
+    /// How many times a statement that failed as busy is retried, outside of
+    /// a transaction.
+    pub busy_retries: u32,
+    /// Whether SQLite enforces foreign key constraints.
+    pub foreign_keys: bool,
+    /// Run `PRAGMA optimize` on SQLite databases when the shell exits.
+    pub optimize_on_exit: bool,
+    /// Register the shell's SQL functions, such as regexp() and uuid4(), on
+    /// SQLite databases opened from now on.
+    pub functions: bool,
+    /// Maximum widths of table columns, by lowercase column name.
+    pub column_max_widths: BTreeMap<String, usize>,
+    /// Width of the terminal the current result is printed to, if it is
+    /// printed to one, for fitting tables to the screen.
+    pub terminal_width: Option<usize>,
+    /// Color output shown in a terminal, with the colors of `theme`.
+    pub color: bool,
+    pub theme: Theme,
+    /// Themes defined in the config file, by lowercase name.
+    pub themes: BTreeMap<String, Theme>,
+    /// Whether the current result is printed to a terminal in color.
+    pub colored: bool,
+    /// Leave out messages about what a command did, such as "Imported 10
+    /// rows", so that only results are printed.
+    pub quiet: bool,
+    /// Keep a history file for each database, beside the main one, and
+    /// recall the statements of the connected database only.
+    pub history_per_database: bool,
+    /// Upper-case the SQL keywords of statements typed at the prompt, as each
+    /// word is finished and when the statement is entered.
+    pub uppercase_keywords: bool,
+    /// Run the statements of one input line in one transaction, rolled back
+    /// if any of them fails.
+    pub single_transaction: bool,
+    /// Report errors on stderr as lines of JSON (`--json-errors`).
+    pub json_errors: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            format: OutputFormat::Table,
+            null_value: "NULL".to_string(),
+            full_blobs: false,
+            timing: false,
+            pager: true,
+            prompt: DEFAULT_PROMPT.to_string(),
+            keymap: Keymap::Emacs,
+            data_dir: None,
+            overflow: Overflow::Truncate,
+            max_rows: None,
+            headers: None,
+            separator: "|".to_string(),
+            timeout: None,
+            busy_timeout: Some(DEFAULT_BUSY_TIMEOUT),
+            busy_retries: 0,
+            foreign_keys: true,
+            optimize_on_exit: false,
+            functions: true,
+            column_max_widths: BTreeMap::new(),
+            terminal_width: None,
+            color: true,
+            theme: Theme::default(),
+            themes: BTreeMap::new(),
+            colored: false,
+            quiet: false,
+            history_per_database: false,
+            uppercase_keywords: false,
+            single_transaction: false,
+            json_errors: false,
+        }
+    }
+}
+
+pub(crate) fn parse_switch(name: &str, value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(GalvanizeError::Parse(format!("{} must be ON or OFF.", name))),
+    }
+}
+
+// Parses a duration such as `30s`, `500ms`, `2m` or `1h`; a bare number is
+// in seconds. OFF or 0 means no limit.
+fn parse_duration(name: &str, value: &str) -> Result<Option<Duration>> {
+    let value = value.trim().to_lowercase();
+    if value == "off" {
+        return Ok(None);
+    }
+
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let invalid = || GalvanizeError::Parse(format!("{} must be a duration such as 30s, 500ms or 2m, or OFF.", name));
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" | "min" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(invalid()),
+    };
+
+    let duration = Duration::try_from_secs_f64(seconds).map_err(|_| invalid())?;
+    Ok(Some(duration).filter(|duration| !duration.is_zero()))
+}
+
+/// A duration as accepted by `SET timeout`, or OFF.
+pub fn format_duration(duration: Option<Duration>) -> String {
+    match duration {
+        None => "OFF".to_string(),
+        Some(duration) if duration.subsec_millis() != 0 => format!("{}ms", duration.as_millis()),
+        Some(duration) => format!("{}s", duration.as_secs()),
+    }
+}
+
+// Checks the placeholders of a prompt template. A prompt without any, from
+// before prompts were templates, is the name shown in the default prompt.
+fn parse_prompt(value: &str) -> Result<String> {
+    if !value.contains('{') {
+        return Ok(DEFAULT_PROMPT.replacen("GalvanizeDB", value, 1));
+    }
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or_else(|| GalvanizeError::Parse("Unclosed { in the prompt.".to_string()))?;
+        let name = &rest[start + 1..start + end];
+        if !PROMPT_PLACEHOLDERS.contains(&name) {
+            let names: Vec<String> = PROMPT_PLACEHOLDERS.iter().map(|name| format!("{{{}}}", name)).collect();
+            return Err(GalvanizeError::Parse(format!("Unknown prompt placeholder '{{{}}}'. Use {}.", name, names.join(", "))));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(value.to_string())
+}
+
+/// Fills in the placeholders of the prompt template `template` with
+/// `value`, which gives what each one shows.
+pub fn expand_prompt(template: &str, value: impl Fn(&str) -> String) -> String {
+    let mut prompt = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        prompt.push_str(&rest[..start]);
+        match rest[start..].find('}') {
+            Some(end) => {
+                prompt.push_str(&value(&rest[start + 1..start + end]));
+                rest = &rest[start + end + 1..];
+            },
+            None => {
+                prompt.push_str(&rest[start..]);
+                rest = "";
+            },
+        }
+    }
+    prompt.push_str(rest);
+    prompt
+}
+
+/// Every setting with its value, as `SET name value` takes it.
+pub fn setting_values(settings: &Settings) -> Vec<(String, String)> {
+    let mut values = vec![
+        ("format", settings.format.name().to_string()),
+        ("null", settings.null_value.clone()),
+        ("blob", if settings.full_blobs { "FULL" } else { "PREVIEW" }.to_string()),
+        ("timing", if settings.timing { "ON" } else { "OFF" }.to_string()),
+        ("pager", if settings.pager { "ON" } else { "OFF" }.to_string()),
+        ("prompt", settings.prompt.clone()),
+        ("keymap", settings.keymap.name().to_string()),
+        ("data_dir", settings.data_dir.as_ref().map(|dir| dir.display().to_string()).unwrap_or_default()),
+        ("overflow", settings.overflow.name().to_string()),
+        ("max_rows", settings.max_rows.map_or("OFF".to_string(), |max_rows| max_rows.to_string())),
+        ("headers", settings.headers.map_or("DEFAULT", |headers| if headers { "ON" } else { "OFF" }).to_string()),
+        ("separator", settings.separator.replace('\t', "\\t").replace('\n', "\\n")),
+        ("timeout", format_duration(settings.timeout)),
+        ("busy_timeout", format_duration(settings.busy_timeout)),
+        ("busy_retries", settings.busy_retries.to_string()),
+        ("foreign_keys", if settings.foreign_keys { "ON" } else { "OFF" }.to_string()),
+        ("optimize_on_exit", if settings.optimize_on_exit { "ON" } else { "OFF" }.to_string()),
+        ("functions", if settings.functions { "ON" } else { "OFF" }.to_string()),
+        ("color", if settings.color { "ON" } else { "OFF" }.to_string()),
+        ("theme", settings.theme.name.clone()),
+        ("quiet", if settings.quiet { "ON" } else { "OFF" }.to_string()),
+        ("history_per_database", if settings.history_per_database { "ON" } else { "OFF" }.to_string()),
+        ("uppercase_keywords", if settings.uppercase_keywords { "ON" } else { "OFF" }.to_string()),
+        ("single_transaction", if settings.single_transaction { "ON" } else { "OFF" }.to_string()),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect::<Vec<_>>();
+    values.extend(settings.column_max_widths.iter().map(|(column, width)| (format!("width.{}", column), width.to_string())));
+    values
+}
+
+/// Changes the setting `name`, as with `SET name value`.
+pub fn apply_setting(settings: &mut Settings, name: &str, value: &str) -> Result<()> {
+    match name {
+        "format" => {
+            settings.format = OutputFormat::from_name(value)
+                .ok_or_else(|| GalvanizeError::Parse(format!("Unknown output format '{}'. Use {}.", value, OutputFormat::names())))?;
+        },
+        "null" | "nullvalue" => settings.null_value = value.to_string(),
+        "prompt" => settings.prompt = parse_prompt(value)?,
+        "keymap" => {
+            settings.keymap = Keymap::from_name(value).ok_or_else(|| GalvanizeError::Parse("keymap must be EMACS or VI.".to_string()))?;
+        },
+        "max_rows" => {
+            settings.max_rows = match value.to_lowercase().as_str() {
+                "off" | "0" => None,
+                count => Some(count.parse().map_err(|_| GalvanizeError::Parse(format!("{} must be a number of rows or OFF.", name)))?),
+            };
+        },
+        "headers" => {
+            settings.headers = if value.eq_ignore_ascii_case("default") { None } else { Some(parse_switch(name, value)?) };
+        },
+        "separator" => {
+            if value.is_empty() {
+                return Err(GalvanizeError::Parse("separator must not be empty.".to_string()));
+            }
+            settings.separator = value.replace("\\t", "\t").replace("\\n", "\n");
+        },
+        "data_dir" => {
+            settings.data_dir = if value.is_empty() { None } else { Some(expand_path(value)) };
+        },
+        "overflow" => {
+            settings.overflow = Overflow::from_name(value).ok_or_else(|| GalvanizeError::Parse("overflow must be TRUNCATE, WRAP or OFF.".to_string()))?;
+        },
+        _ if name.starts_with("width.") => {
+            let column = name["width.".len()..].to_lowercase();
+            match value.to_lowercase().as_str() {
+                "off" | "0" => {
+                    settings.column_max_widths.remove(&column);
+                },
+                width => {
+                    let width = width.parse().map_err(|_| GalvanizeError::Parse(format!("{} must be a number of characters or OFF.", name)))?;
+                    settings.column_max_widths.insert(column, width);
+                },
+            }
+        },
+        "timing" => settings.timing = parse_switch(name, value)?,
+        "timeout" => settings.timeout = parse_duration(name, value)?,
+        "busy_timeout" => settings.busy_timeout = parse_duration(name, value)?,
+        "busy_retries" => {
+            settings.busy_retries = value.parse().map_err(|_| GalvanizeError::Parse(format!("{} must be a number.", name)))?;
+        },
+        "pager" => settings.pager = parse_switch(name, value)?,
+        "foreign_keys" => settings.foreign_keys = parse_switch(name, value)?,
+        "optimize_on_exit" => settings.optimize_on_exit = parse_switch(name, value)?,
+        "functions" => settings.functions = parse_switch(name, value)?,
+        "color" => settings.color = parse_switch(name, value)?,
+        "quiet" => settings.quiet = parse_switch(name, value)?,
+        "history_per_database" => settings.history_per_database = parse_switch(name, value)?,
+        "uppercase_keywords" => settings.uppercase_keywords = parse_switch(name, value)?,
+        "single_transaction" => settings.single_transaction = parse_switch(name, value)?,
+        "theme" => settings.theme = Theme::find(value, &settings.themes)?,
+        "blob" => match value.to_lowercase().as_str() {
+            "full" => settings.full_blobs = true,
+            "preview" => settings.full_blobs = false,
+            _ => return Err(GalvanizeError::Parse("blob must be FULL or PREVIEW.".to_string())),
+        },
+        _ => return Err(GalvanizeError::Parse(format!("Unknown setting '{}'.", name))),
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(value: &str) -> Result<Option<Duration>> {
+        parse_duration("timeout", value)
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(duration("30").unwrap(), Some(Duration::from_secs(30)));
+        assert_eq!(duration("30s").unwrap(), Some(Duration::from_secs(30)));
+        assert_eq!(duration("500ms").unwrap(), Some(Duration::from_millis(500)));
+        assert_eq!(duration("1.5s").unwrap(), Some(Duration::from_millis(1500)));
+        assert_eq!(duration(" 2 MIN ").unwrap(), Some(Duration::from_secs(120)));
+        assert_eq!(duration("2m").unwrap(), Some(Duration::from_secs(120)));
+        assert_eq!(duration("1h").unwrap(), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn zero_or_off_is_no_limit() {
+        assert_eq!(duration("0").unwrap(), None);
+        assert_eq!(duration("0ms").unwrap(), None);
+        assert_eq!(duration("OFF").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        for value in ["", "s", "10d", "10 seconds", "-5s", "1e3", "1.2.3s", "99999999999999999999h"] {
+            let e = duration(value).unwrap_err().to_string();
+            assert!(e.contains("timeout must be a duration"), "{}: {}", value, e);
+        }
+    }
+
+    #[test]
+    fn applies_durations() {
+        let mut settings = Settings::default();
+        apply_setting(&mut settings, "timeout", "250ms").unwrap();
+        apply_setting(&mut settings, "busy_timeout", "0").unwrap();
+        assert_eq!(settings.timeout, Some(Duration::from_millis(250)));
+        assert_eq!(settings.busy_timeout, None);
+        assert!(apply_setting(&mut settings, "busy_timeout", "5 weeks").unwrap_err().to_string().starts_with("busy_timeout must be"));
+        assert_eq!(settings.busy_timeout, None);
+        assert_eq!(format_duration(settings.timeout), "250ms");
+        assert_eq!(format_duration(Some(Duration::from_secs(90))), "90s");
+        assert_eq!(format_duration(None), "OFF");
+    }
+
+    #[test]
+    fn checks_prompt_placeholders() {
+        for placeholder in PROMPT_PLACEHOLDERS {
+            let prompt = format!("[{{{}}}]> ", placeholder);
+            assert_eq!(parse_prompt(&prompt).unwrap(), prompt);
+        }
+        assert_eq!(parse_prompt("{db}{txn} {time}> ").unwrap(), "{db}{txn} {time}> ");
+        assert_eq!(parse_prompt("{}").unwrap_err().to_string(), "Unknown prompt placeholder '{}'. Use {db}, {session}, {txn}, {backend}, {ro}, {time}.");
+        assert!(parse_prompt("{db} {user}> ").unwrap_err().to_string().starts_with("Unknown prompt placeholder '{user}'."));
+        assert!(parse_prompt("{DB}> ").unwrap_err().to_string().starts_with("Unknown prompt placeholder '{DB}'."));
+        assert_eq!(parse_prompt("{db}> {").unwrap_err().to_string(), "Unclosed { in the prompt.");
+        assert_eq!(parse_prompt("{db").unwrap_err().to_string(), "Unclosed { in the prompt.");
+    }
+
+    #[test]
+    fn names_plain_prompts() {
+        assert_eq!(parse_prompt("mydb").unwrap(), "mydb [{session}{db}]{txn}> ");
+        assert_eq!(parse_prompt("}").unwrap(), "} [{session}{db}]{txn}> ");
+        let mut settings = Settings::default();
+        apply_setting(&mut settings, "prompt", "{backend}{ro}> ").unwrap();
+        assert!(apply_setting(&mut settings, "prompt", "{nope}> ").is_err());
+        assert_eq!(settings.prompt, "{backend}{ro}> ");
+    }
+
+    #[test]
+    fn expands_prompts() {
+        let value = |name: &str| format!("<{}>", name);
+        assert_eq!(expand_prompt("{db}{txn} {time}> ", value), "<db><txn> <time>> ");
+        assert_eq!(expand_prompt("no placeholders", value), "no placeholders");
+        assert_eq!(expand_prompt("{db}> {", value), "<db>> {");
+    }
+}
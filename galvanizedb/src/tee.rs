@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::error::{GalvanizeError, Result};
+use crate::theme::strip_colors;
+
+// Receives what is printed on this thread instead of stdout and stderr, with
+// whether it was meant for stderr. Set on threads that run a remote shell.
+type Capture = Box<dyn Fn(&str, bool)>;
+
+thread_local! {
+    static CAPTURE: RefCell<Option<Capture>> = const { RefCell::new(None) };
+    // The file opened with \tee, which everything the shell prints is copied
+    // to. Each shell runs on a thread of its own, so each has its own.
+    static TEE: RefCell<Option<(PathBuf, File)>> = const { RefCell::new(None) };
+}
+
+/// Like `println!`, but also copied to the \tee file, if one is open.
+macro_rules! outln {
+    () => {
+        outln!("")
+    };
+    ($($arg:tt)*) => {{
+        let text = format!($($arg)*);
+        $crate::tee::print_line(&text, false);
+    }};
+}
+
+/// Like `eprintln!`, but also copied to the \tee file, if one is open.
+macro_rules! errln {
+    () => {
+        errln!("")
+    };
+    ($($arg:tt)*) => {{
+        let text = format!($($arg)*);
+        $crate::tee::print_line(&text, true);
+    }};
+}
+
+/// Sends everything printed on this thread from now on to `capture`,
+/// rather than to stdout or stderr.
+pub fn capture(capture: impl Fn(&str, bool) + 'static) {
+    CAPTURE.with(|current| *current.borrow_mut() = Some(Box::new(capture)));
+}
+
+// Sends `text` where captured output goes, if it is captured on this thread.
+fn captured(text: &str, stderr: bool) -> bool {
+    CAPTURE.with(|capture| match capture.borrow().as_ref() {
+        Some(capture) => {
+            capture(text, stderr);
+            true
+        },
+        None => false,
+    })
+}
+
+/// Prints a line for [`outln!`] or [`errln!`].
+pub fn print_line(text: &str, stderr: bool) {
+    let line = format!("{}\n", text);
+    if !captured(&line, stderr) {
+        if stderr {
+            eprint!("{}", line);
+        } else {
+            print!("{}", line);
+        }
+    }
+    copy(line.as_bytes());
+}
+
+/// Starts copying output to the file at `path`, appending to it. A file
+/// that was already open is closed first.
+pub fn start(path: &Path) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| GalvanizeError::Io(format!("Error opening '{}': {}", path.display(), e)))?;
+    TEE.with(|tee| *tee.borrow_mut() = Some((path.to_path_buf(), file)));
+    Ok(())
+}
+
+/// Stops copying output, and returns the file it was copied to.
+pub fn stop() -> Option<PathBuf> {
+    TEE.with(|tee| tee.borrow_mut().take().map(|(path, _)| path))
+}
+
+/// Copies `buf` to the \tee file, without colors. If the file cannot be
+/// written, copying stops rather than failing what was being printed.
+pub fn copy(buf: &[u8]) {
+    TEE.with(|tee| {
+        let mut tee = tee.borrow_mut();
+        if let Some((path, file)) = tee.as_mut() {
+            let plain;
+            let buf = if buf.contains(&0x1B) {
+                plain = strip_colors(&String::from_utf8_lossy(buf));
+                plain.as_bytes()
+            } else {
+                buf
+            };
+            if let Err(e) = file.write_all(buf) {
+                eprintln!("Error writing to '{}': {}. Output is no longer copied to it.", path.display(), e);
+                *tee = None;
+            }
+        }
+    })
+}
+
+/// Locked standard output, with everything written to it also copied to
+/// the \tee file. Where output is captured, it is not locked.
+pub struct Stdout(Option<std::io::StdoutLock<'static>>);
+
+pub fn stdout() -> Stdout {
+    let captured = CAPTURE.with(|capture| capture.borrow().is_some());
+    Stdout((!captured).then(|| std::io::stdout().lock()))
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = match &mut self.0 {
+            Some(stdout) => stdout.write(buf)?,
+            None => {
+                captured(&String::from_utf8_lossy(buf), false);
+                buf.len()
+            },
+        };
+        copy(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.0 {
+            Some(stdout) => stdout.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use serde::Deserialize;
+use crate::error::{GalvanizeError, Result};
+
+/// The names of the built-in themes.
+pub const THEME_NAMES: &[&str] = &["default", "light", "mono"];
+
+/// The ANSI escape sequences that color each kind of output. An empty
+/// sequence leaves that kind uncolored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub name: String,
+    pub header: String,
+    pub null: String,
+    pub number: String,
+    pub error: String,
+    pub prompt: String,
+    /// Colors of the statement being typed, and of the suggestion to
+    /// complete it from history.
+    pub keyword: String,
+    pub string: String,
+    pub comment: String,
+    pub hint: String,
+}
+
+/// A theme defined in the `[themes.<name>]` table of the config file, as
+/// style descriptions such as `"bold yellow"`. Kinds that are left out keep
+/// their default colors.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeStyles {
+    pub header: Option<String>,
+    pub null: Option<String>,
+    pub number: Option<String>,
+    pub error: Option<String>,
+    pub prompt: Option<String>,
+    pub keyword: Option<String>,
+    pub string: Option<String>,
+    pub comment: Option<String>,
+    pub hint: Option<String>,
+}
+
+// Converts a style description, such as `bold bright_blue` or `none`, into
+// an ANSI escape sequence.
+fn parse_style(style: &str) -> Result<String> {
+    let mut codes = Vec::new();
+    for word in style.split_whitespace().map(str::to_lowercase) {
+        let (bright, color) = match word.strip_prefix("bright_") {
+            Some(color) => (true, color),
+            None => (false, word.as_str()),
+        };
+        let code = match color {
+            "none" => continue,
+            "bold" if !bright => 1,
+            "dim" if !bright => 2,
+            "italic" if !bright => 3,
+            "underline" if !bright => 4,
+            "black" => 30,
+            "red" => 31,
+            "green" => 32,
+            "yellow" => 33,
+            "blue" => 34,
+            "magenta" => 35,
+            "cyan" => 36,
+            "white" => 37,
+            _ => {
+                return Err(GalvanizeError::Parse(format!(
+                    "Unknown style '{}'. Use bold, dim, italic, underline, a color such as red or bright_red, or none.",
+                    word
+                )))
+            },
+        };
+        codes.push(if bright { code + 60 } else { code }.to_string());
+    }
+    Ok(if codes.is_empty() { String::new() } else { format!("\x1B[{}m", codes.join(";")) })
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::builtin("default").unwrap()
+    }
+}
+
+impl Theme {
+    /// The built-in theme called `name`.
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let [header, null, number, error, prompt, keyword, string, comment, hint] = match name {
+            "default" => ["bold cyan", "magenta", "yellow", "bold red", "bold green", "bold blue", "green", "dim", "dim"],
+            // Darker colors that stay readable on a light background.
+            "light" => ["bold blue", "bright_black", "magenta", "bold red", "bold blue", "blue", "green", "bright_black", "bright_black"],
+            "mono" => ["bold", "dim", "none", "bold", "bold", "bold", "none", "dim", "dim"],
+            _ => return None,
+        };
+        let style = |style: &str| parse_style(style).unwrap();
+        Some(Theme {
+            name: name.to_string(),
+            header: style(header),
+            null: style(null),
+            number: style(number),
+            error: style(error),
+            prompt: style(prompt),
+            keyword: style(keyword),
+            string: style(string),
+            comment: style(comment),
+            hint: style(hint),
+        })
+    }
+
+    /// A theme from the config file, starting from the default colors.
+    pub fn from_styles(name: &str, styles: &ThemeStyles) -> Result<Theme> {
+        let mut theme = Theme { name: name.to_string(), ..Theme::default() };
+        for (style, target) in [
+            (&styles.header, &mut theme.header),
+            (&styles.null, &mut theme.null),
+            (&styles.number, &mut theme.number),
+            (&styles.error, &mut theme.error),
+            (&styles.prompt, &mut theme.prompt),
+            (&styles.keyword, &mut theme.keyword),
+            (&styles.string, &mut theme.string),
+            (&styles.comment, &mut theme.comment),
+            (&styles.hint, &mut theme.hint),
+        ] {
+            if let Some(style) = style {
+                *target = parse_style(style).map_err(|e| e.context(format!("Invalid theme '{}'", name)))?;
+            }
+        }
+        Ok(theme)
+    }
+
+    /// The theme called `name`: one of `themes`, from the config file, or a
+    /// built-in one.
+    pub fn find(name: &str, themes: &BTreeMap<String, Theme>) -> Result<Theme> {
+        let name = name.to_lowercase();
+        themes.get(&name).cloned().or_else(|| Theme::builtin(&name)).ok_or_else(|| {
+            let mut names: Vec<&str> = THEME_NAMES.to_vec();
+            names.extend(themes.keys().map(String::as_str));
+            GalvanizeError::Parse(format!("Unknown theme '{}'. Use {}.", name, names.join(", ")))
+        })
+    }
+}
+
+/// Wraps `text` in the escape sequence `style`, if there is one.
+pub fn paint(text: &str, style: &str) -> String {
+    if style.is_empty() || text.is_empty() {
+        text.to_string()
+    } else {
+        format!("{}{}\x1B[0m", style, text)
+    }
+}
+
+/// Removes the color escape sequences from `text`.
+pub fn strip_colors(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1B[") {
+        plain.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find(|c: char| !(c.is_ascii_digit() || c == ';')) {
+            Some(end) if after[end..].starts_with('m') => rest = &after[end + 1..],
+            _ => {
+                plain.push_str("\x1B[");
+                rest = after;
+            },
+        }
+    }
+    plain.push_str(rest);
+    plain
+}
+
+/// Whether output should be colored by default: not when the NO_COLOR
+/// environment variable is set to anything non-empty.
+pub fn color_by_default() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}